@@ -0,0 +1,116 @@
+// Compares the two Value layouts. Run it once per layout and compare the numbers:
+//     cargo bench --bench value_layout
+//     cargo bench --bench value_layout --features nan-boxing
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use rlox::{ObjData, Value, ValueKind, get_value_str, values_equal, values_less};
+
+const ITERATIONS: usize = 1_000_000;
+
+
+fn time(name: &str, bench: impl Fn()) {
+    // one warm up run, then take the best of a few so noise only ever makes us look slower
+    bench();
+    let mut best = Duration::MAX;
+    for _ in 0..5 {
+        let start = Instant::now();
+        bench();
+        best = best.min(start.elapsed());
+    }
+    println!("{: <28} {: >10.2?}  ({:.1} ns/iter)", name, best, best.as_nanos() as f64 / ITERATIONS as f64);
+}
+
+fn make_string(string: &str) -> Value {
+    return Value::from_obj(ObjData::String(string.chars().collect()));
+}
+
+
+// What the vm does for `Constant; Constant; Add`, push two clones and pop them back off
+fn stack_push_pop_numbers() {
+    let constants = [Value::from_number(1.5), Value::from_number(2.5)];
+    let mut stack: Vec<Value> = Vec::with_capacity(256);
+    let mut total = 0.0;
+    for _ in 0..ITERATIONS {
+        stack.push(constants[0].clone());
+        stack.push(constants[1].clone());
+        let right = stack.pop().unwrap();
+        let left = stack.pop().unwrap();
+        if let (ValueKind::Number(left_val), ValueKind::Number(right_val)) = (left.kind(), right.kind()) {
+            stack.push(Value::from_number(left_val + right_val));
+        }
+        if let ValueKind::Number(num) = stack.pop().unwrap().kind() {
+            total += num;
+        }
+    }
+    black_box(total);
+}
+
+fn stack_push_pop_strings() {
+    let constants = [make_string("breakfast"), make_string("idk")];
+    let mut stack: Vec<Value> = Vec::with_capacity(256);
+    for _ in 0..ITERATIONS {
+        stack.push(constants[0].clone());
+        stack.push(constants[1].clone());
+        black_box(stack.pop());
+        black_box(stack.pop());
+    }
+}
+
+fn globals_lookup() {
+    let mut globals = std::collections::HashMap::new();
+    globals.insert("x".to_string(), Value::from_number(1.0));
+    globals.insert("name".to_string(), make_string("breakfast"));
+    let mut stack: Vec<Value> = Vec::with_capacity(256);
+    for i in 0..ITERATIONS {
+        let key = if i % 2 == 0 { "x" } else { "name" };
+        stack.push(globals.get(key).unwrap().clone());
+        black_box(stack.pop());
+    }
+}
+
+fn compare_numbers() {
+    let left = Value::from_number(3.0);
+    let right = Value::from_number(4.0);
+    let mut count = 0;
+    for _ in 0..ITERATIONS {
+        if values_less(black_box(left.clone()), black_box(right.clone())) {
+            count += 1;
+        }
+        if values_equal(black_box(left.clone()), black_box(right.clone())) {
+            count += 1;
+        }
+    }
+    black_box(count);
+}
+
+fn compare_strings() {
+    let left = make_string("breakfast");
+    let right = make_string("breakfasts");
+    let mut count = 0;
+    for _ in 0..ITERATIONS {
+        if values_equal(black_box(left.clone()), black_box(right.clone())) {
+            count += 1;
+        }
+    }
+    black_box(count);
+}
+
+fn stringify() {
+    let values = [Value::from_number(14.0), Value::from_bool(true), Value::null(), make_string("yep")];
+    for i in 0..ITERATIONS / 4 {
+        black_box(get_value_str(&values[i % values.len()]));
+    }
+}
+
+
+fn main() {
+    let layout = if cfg!(feature = "nan-boxing") { "nan-boxing" } else { "enum" };
+    println!("Value layout: {}, size_of::<Value>() = {} bytes", layout, std::mem::size_of::<Value>());
+    time("stack push/pop numbers", stack_push_pop_numbers);
+    time("stack push/pop strings", stack_push_pop_strings);
+    time("globals lookup", globals_lookup);
+    time("compare numbers", compare_numbers);
+    time("compare strings", compare_strings);
+    time("stringify", stringify);
+}
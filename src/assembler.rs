@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+
+use enum_map::Enum;
+
+use crate::LoxError;
+use crate::chunk::{Chunk, OpCode, OperandLayout, opcode_name, operand_layout};
+use crate::value::{ObjData, Value};
+
+
+// Reads back what the disassembler writes, so disassembling a chunk and assembling the listing
+// gives the same bytes, and is easy enough to write by hand for testing the vm without the
+// compiler:
+//     CONSTANT "hi"
+//     PRINT
+//     TRUE
+//     JUMP_IF_FALSE done
+//     GET_GLOBAL clock
+//     CALL 0
+//     PRINT
+//     done:
+// Opcodes can be written like the disassembler does (JumpIfFalse) or in capitals with
+// underscores, and everything after a ; is a comment.
+//
+// Once the file has a constants: section, a number operand is an index into it, the way the
+// listing refers to constants. Without one, operands are the values themselves (numbers,
+// "strings", true, false, null, and bare names for globals) and the pool is built up as they
+// come. Instructions get the line of the .loxasm file they're on, unless they start with an
+// offset and a line number like listing lines do. Jumps go to labels or to offsets.
+
+struct Assembler {
+    chunk: Chunk,
+    labels: HashMap<String, usize>,
+    // where each jump's operand is, where it's going and the line to blame if that's nowhere
+    jumps: Vec<(usize, String, i64)>,
+    has_constant_section: bool,
+}
+
+pub fn assemble(source: &str) -> Result<Chunk, LoxError> {
+    let mut assembler = Assembler { chunk: Chunk::default(), labels: HashMap::new(), jumps: vec![], has_constant_section: false };
+    let mut in_constants = false;
+    for (index, text) in source.lines().enumerate() {
+        let line = index as i64 + 1;
+        let error = |message: String| LoxError::Compile { line: line, message: message };
+        let words = split_words(text).map_err(error)?;
+        if words.is_empty() || words[0].starts_with("===") {
+            continue;
+        }
+        if words.len() == 1 && (words[0] == "constants:" || words[0] == "code:") {
+            in_constants = words[0] == "constants:";
+            assembler.has_constant_section |= in_constants;
+            continue;
+        }
+        if in_constants {
+            assembler.constant_entry(&words).map_err(error)?;
+        }
+        else if words.len() == 1 && words[0].ends_with(':') {
+            let label = words[0].trim_end_matches(':').to_string();
+            if assembler.labels.insert(label.clone(), assembler.chunk.code.len()).is_some() {
+                return Err(error(format!("The label {} is already somewhere else", label)));
+            }
+        }
+        else {
+            assembler.instruction(&words, line).map_err(error)?;
+        }
+    }
+    assembler.patch_jumps()?;
+    return Ok(assembler.chunk);
+}
+
+impl Assembler {
+    fn constant_entry(&mut self, words: &[String]) -> Result<(), String> {
+        if words.len() != 2 || words[0].parse::<usize>() != Ok(self.chunk.constants.len()) {
+            return Err(format!("Expected constant {} as `{} value`", self.chunk.constants.len(), self.chunk.constants.len()));
+        }
+        let value = parse_literal(&words[1])?;
+        return self.push_constant(value).map(|_| ());
+    }
+
+    fn push_constant(&mut self, value: Value) -> Result<u8, String> {
+        if self.chunk.constants.len() >= u8::MAX as usize {
+            return Err("Too many constants in the pool!".to_string());
+        }
+        self.chunk.constants.push(value);
+        return Ok(self.chunk.constants.len() as u8 - 1);
+    }
+
+    fn emit(&mut self, byte: u8, line: i64) {
+        self.chunk.code.push(byte);
+        self.chunk.lines.push(line);
+    }
+
+    fn instruction(&mut self, words: &[String], file_line: i64) -> Result<(), String> {
+        let mut words = words;
+        let mut line = file_line;
+        // a listing line, the offset is only for reading but the line is kept
+        if words.len() >= 3 && words[0].chars().all(|c| c.is_ascii_digit()) {
+            line = match words[1].as_str() {
+                "|" => *self.chunk.lines.last().ok_or("The first instruction can't use | for its line")?,
+                "?" => 0,
+                number => number.parse::<i64>().map_err(|_| format!("Expected a line number but got {}", number))?,
+            };
+            words = &words[2..];
+        }
+
+        if words[0] == ".byte" {
+            let byte = words.get(1).and_then(|word| word.parse::<u8>().ok()).ok_or(".byte needs a number from 0 to 255")?;
+            self.emit(byte, line);
+            return Ok(());
+        }
+        let instruction = parse_opcode(&words[0])?;
+        let operands = &words[1..];
+        let expected = match operand_layout(instruction) {
+            OperandLayout::None => 0,
+            OperandLayout::Method => 2,
+            _ => 1,
+        };
+        if operands.len() != expected {
+            return Err(format!("{} takes {} operands but got {}", opcode_name(instruction), expected, operands.len()));
+        }
+
+        self.emit(instruction, line);
+        match operand_layout(instruction) {
+            OperandLayout::None => (),
+            OperandLayout::Constant => {
+                let index = self.constant_operand(&operands[0], false)?;
+                self.emit(index, line);
+            },
+            OperandLayout::Global => {
+                let index = self.constant_operand(&operands[0], true)?;
+                self.emit(index, line);
+            },
+            OperandLayout::ArgCount => {
+                let count = operands[0].parse::<u8>().map_err(|_| format!("Expected an argument count but got {}", operands[0]))?;
+                self.emit(count, line);
+            },
+            OperandLayout::Method => {
+                let index = self.constant_operand(&operands[0], true)?;
+                let count = operands[1].parse::<u8>().map_err(|_| format!("Expected an argument count but got {}", operands[1]))?;
+                self.emit(index, line);
+                self.emit(count, line);
+            },
+            OperandLayout::Jump => {
+                self.jumps.push((self.chunk.code.len(), operands[0].clone(), line));
+                self.emit(0, line);
+                self.emit(0, line);
+            },
+        }
+        return Ok(());
+    }
+
+    // An index when there's a constants section, otherwise the value itself. A bare word is a
+    // global's name when one's expected.
+    fn constant_operand(&mut self, word: &str, is_name: bool) -> Result<u8, String> {
+        if self.has_constant_section && word.chars().all(|c| c.is_ascii_digit()) {
+            let index = word.parse::<u8>().map_err(|_| format!("There's no constant {}", word))?;
+            if index as usize >= self.chunk.constants.len() {
+                return Err(format!("There's no constant {}", index));
+            }
+            return Ok(index);
+        }
+        let value = if is_name && !word.starts_with('"') {
+            Value::from_obj(ObjData::String(word.chars().collect()))
+        }
+        else {
+            parse_literal(word)?
+        };
+        return self.push_constant(value);
+    }
+
+    fn patch_jumps(&mut self) -> Result<(), LoxError> {
+        for (operand, target, line) in &self.jumps {
+            let error = |message: String| LoxError::Compile { line: *line, message: message };
+            let offset = match self.labels.get(target) {
+                Some(offset) => *offset,
+                None => target.parse::<usize>().map_err(|_| error(format!("There's no label called {}", target)))?,
+            };
+            // jumps are measured from the end of the instruction
+            let from = operand + 2;
+            if offset < from {
+                return Err(error(format!("Jumps can only go forward, {} is behind", target)));
+            }
+            let jump = offset - from;
+            if jump > u16::MAX as usize {
+                return Err(error(format!("{} is too far away to jump to", target)));
+            }
+            self.chunk.code[*operand] = (jump >> 8) as u8;
+            self.chunk.code[operand + 1] = (jump & 0xff) as u8;
+        }
+        return Ok(());
+    }
+}
+
+// Both JumpIfFalse and JUMP_IF_FALSE work
+fn parse_opcode(word: &str) -> Result<u8, String> {
+    let wanted = word.replace('_', "").to_lowercase();
+    for instruction in 0..OpCode::LENGTH as u8 {
+        if opcode_name(instruction).to_lowercase() == wanted {
+            return Ok(instruction);
+        }
+    }
+    return Err(format!("{} isn't an opcode", word));
+}
+
+fn parse_literal(word: &str) -> Result<Value, String> {
+    if word.starts_with('"') {
+        return Ok(Value::from_obj(ObjData::String(unescape(word)?.chars().collect())));
+    }
+    match word {
+        "true" => return Ok(Value::from_bool(true)),
+        "false" => return Ok(Value::from_bool(false)),
+        "null" => return Ok(Value::null()),
+        _ => (),
+    }
+    match word.parse::<f64>() {
+        Ok(number) => return Ok(Value::from_number(number)),
+        Err(_) => return Err(format!("Expected a number, string, true, false or null but got {}", word)),
+    }
+}
+
+// The other way from json_string, the quotes are still on
+fn unescape(word: &str) -> Result<String, String> {
+    let mut string = String::new();
+    let mut chars = word[1..word.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            string.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('"') => string.push('"'),
+            Some('\\') => string.push('\\'),
+            Some('n') => string.push('\n'),
+            Some('r') => string.push('\r'),
+            Some('t') => string.push('\t'),
+            Some('u') => {
+                let hex: String = chars.by_ref().take(4).collect();
+                let c = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32).ok_or(format!("\\u{} isn't a character", hex))?;
+                string.push(c);
+            },
+            other => return Err(format!("Unknown escape \\{}", other.map(String::from).unwrap_or_default())),
+        }
+    }
+    return Ok(string);
+}
+
+// Whitespace separated, except inside a string, and stopping at a comment
+fn split_words(text: &str) -> Result<Vec<String>, String> {
+    let mut words = vec![];
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        }
+        else if c == ';' {
+            break;
+        }
+        else if c == '"' {
+            let mut word = String::from(chars.next().unwrap());
+            let mut closed = false;
+            while let Some(c) = chars.next() {
+                word.push(c);
+                if c == '\\' {
+                    word.extend(chars.next());
+                }
+                else if c == '"' {
+                    closed = true;
+                    break;
+                }
+            }
+            if !closed {
+                return Err("A string is missing its end quote".to_string());
+            }
+            words.push(word);
+        }
+        else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == ';' {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            words.push(word);
+        }
+    }
+    return Ok(words);
+}
@@ -0,0 +1,138 @@
+use std::collections::BTreeSet;
+
+use crate::chunk::{Chunk, OpCode, OperandLayout, jump_target, operand_layout};
+use crate::disassembler::{instruction_text, is_instruction, jump_labels, size_at};
+
+
+// A run of instructions that's only ever entered at the top and left at the bottom
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasicBlock {
+    // offsets into the code, end is one past the last byte
+    pub start: usize,
+    pub end: usize,
+    pub successors: Vec<Edge>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EdgeKind {
+    // the jump was taken, for JumpIfFalse that's when the condition was falsey
+    Taken,
+    Fallthrough,
+    // a Return ends the chunk wherever it is
+    Return,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Edge {
+    // index of the block it goes to, None for leaving the chunk
+    pub to: Option<usize>,
+    pub kind: EdgeKind,
+}
+
+// Blocks start at the beginning, at every jump target and right after every jump or Return, and
+// they're in code order
+pub fn basic_blocks(chunk: &Chunk) -> Vec<BasicBlock> {
+    let mut starts = BTreeSet::new();
+    if !chunk.code.is_empty() {
+        starts.insert(0);
+    }
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        let size = size_at(chunk, offset);
+        if ends_block(chunk, offset) {
+            if operand_layout(chunk.code[offset]) == OperandLayout::Jump {
+                starts.insert(jump_target(chunk, offset));
+            }
+            starts.insert(offset + size);
+        }
+        offset += size;
+    }
+    // a jump to the very end (or past it, in a chunk that doesn't verify) leaves the chunk
+    let starts: Vec<usize> = starts.into_iter().filter(|start| *start < chunk.code.len()).collect();
+    let block_at = |offset: usize| starts.iter().position(|start| *start == offset);
+
+    let mut blocks = vec![];
+    for (index, start) in starts.iter().enumerate() {
+        let end = starts.get(index + 1).copied().unwrap_or(chunk.code.len());
+        // the last instruction is the one that decides where to go next
+        let mut last = *start;
+        while last + size_at(chunk, last) < end {
+            last += size_at(chunk, last);
+        }
+
+        let mut successors = vec![];
+        let instruction = chunk.code[last];
+        if ends_block(chunk, last) && operand_layout(instruction) == OperandLayout::Jump {
+            successors.push(Edge { to: block_at(jump_target(chunk, last)), kind: EdgeKind::Taken });
+        }
+        if ends_block(chunk, last) && instruction == OpCode::Return as u8 {
+            successors.push(Edge { to: None, kind: EdgeKind::Return });
+        }
+        else if !(ends_block(chunk, last) && instruction == OpCode::Jump as u8) {
+            successors.push(Edge { to: block_at(end), kind: EdgeKind::Fallthrough });
+        }
+        blocks.push(BasicBlock { start: *start, end: end, successors: successors });
+    }
+    return blocks;
+}
+
+fn ends_block(chunk: &Chunk, offset: usize) -> bool {
+    if !is_instruction(chunk, offset) {
+        return false;
+    }
+    let instruction = chunk.code[offset];
+    return operand_layout(instruction) == OperandLayout::Jump || instruction == OpCode::Return as u8;
+}
+
+// Graphviz wants quotes and backslashes escaped, and \l ends a left justified line
+fn dot_escape(text: &str) -> String {
+    return text.replace('\\', "\\\\").replace('"', "\\\"");
+}
+
+// Every block is a box of its disassembled instructions, jump labels included so it lines up
+// with `rlox disasm`. Taken edges are solid and fallthrough edges dashed.
+pub fn cfg_dot(chunk: &Chunk, name: &str) -> String {
+    let labels = jump_labels(chunk);
+    let blocks = basic_blocks(chunk);
+
+    let mut dot = format!("digraph \"{}\" {{\n", dot_escape(name));
+    dot += "    node [shape=box, fontname=\"monospace\"];\n";
+    dot += "    entry [shape=circle];\n";
+    dot += "    exit [shape=doublecircle];\n";
+    for (index, block) in blocks.iter().enumerate() {
+        let mut label = String::new();
+        if let Some(name) = labels.get(&block.start) {
+            label += &format!("{}:\\l", name);
+        }
+        let mut offset = block.start;
+        while offset < block.end {
+            let (text, size) = instruction_text(chunk, offset, &labels);
+            label += &format!("{:04}  {}\\l", offset, dot_escape(&text));
+            offset += size;
+        }
+        dot += &format!("    block{} [label=\"{}\"];\n", index, label);
+    }
+
+    if blocks.is_empty() {
+        dot += "    entry -> exit;\n";
+    }
+    else {
+        dot += "    entry -> block0;\n";
+    }
+    for (index, block) in blocks.iter().enumerate() {
+        for edge in &block.successors {
+            let to = match edge.to {
+                Some(to) => format!("block{}", to),
+                None => "exit".to_string(),
+            };
+            let style = match edge.kind {
+                EdgeKind::Taken => "label=\"taken\"",
+                EdgeKind::Fallthrough => "label=\"fallthrough\", style=dashed",
+                EdgeKind::Return => "label=\"return\"",
+            };
+            dot += &format!("    block{} -> {} [{}];\n", index, to, style);
+        }
+    }
+    dot += "}\n";
+    return dot;
+}
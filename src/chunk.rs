@@ -0,0 +1,150 @@
+use enum_map::Enum;
+
+use crate::value::{ObjData, Value, ValueKind};
+
+
+#[repr(u8)]
+#[derive(Debug, Enum, Clone, Copy)]
+pub enum OpCode {
+    Return,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Negate,
+    Constant,
+    Null,
+    True,
+    False,
+    Equal,
+    Greater,
+    Less,
+    Not,
+    Print,
+    JumpIfFalse,
+    Pop,
+    DefineGlobal,
+    GetGlobal,
+    SetGlobal,
+    Jump,
+    NotEqual,
+    GreaterEqual,
+    LessEqual,
+    Call,
+    Invoke,
+    // Superinstructions, only emitted at -O2, see optimizer::fuse_superinstructions
+    ConstantAdd,
+    ConstantPrint,
+    GetGlobalPrint,
+    SetGlobalPop,
+}
+
+
+pub fn opcode_name(instruction: u8) -> String {
+    if (instruction as usize) < OpCode::LENGTH {
+        return format!("{:?}", OpCode::from_usize(instruction as usize));
+    }
+    return format!("UNKNOWN({})", instruction);
+}
+
+// What the bytes after an opcode are
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OperandLayout {
+    None,
+    // a constant index
+    Constant,
+    // a constant index naming a global, the constant has to be a string
+    Global,
+    // how many arguments a call has
+    ArgCount,
+    // a constant index naming the method, then the argument count
+    Method,
+    // two bytes big endian, how far forward to jump from the end of the instruction
+    Jump,
+}
+
+pub fn operand_layout(instruction: u8) -> OperandLayout {
+    if instruction == OpCode::Constant as u8 || instruction == OpCode::ConstantAdd as u8 || instruction == OpCode::ConstantPrint as u8 {
+        return OperandLayout::Constant;
+    }
+    if instruction == OpCode::DefineGlobal as u8 
+        || instruction == OpCode::GetGlobal as u8 
+        || instruction == OpCode::SetGlobal as u8 
+        || instruction == OpCode::GetGlobalPrint as u8 
+        || instruction == OpCode::SetGlobalPop as u8 {
+        return OperandLayout::Global;
+    }
+    if instruction == OpCode::Call as u8 {
+        return OperandLayout::ArgCount;
+    }
+    if instruction == OpCode::Invoke as u8 {
+        return OperandLayout::Method;
+    }
+    if instruction == OpCode::JumpIfFalse as u8 || instruction == OpCode::Jump as u8 {
+        return OperandLayout::Jump;
+    }
+    return OperandLayout::None;
+}
+
+// How many bytes an instruction takes up, opcode included
+pub fn instruction_size(instruction: u8) -> usize {
+    match operand_layout(instruction) {
+        OperandLayout::None => 1,
+        OperandLayout::Constant | OperandLayout::Global | OperandLayout::ArgCount => 2,
+        OperandLayout::Method | OperandLayout::Jump => 3,
+    }
+}
+
+// Where the jump at offset goes, as an offset into the code
+pub fn jump_target(chunk: &Chunk, offset: usize) -> usize {
+    let jump = ((chunk.code[offset + 1] as usize) << 8) + chunk.code[offset + 2] as usize;
+    return offset + 3 + jump;
+}
+
+
+// impl std::fmt::Display for OpCode {
+//     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+//         write!(f, "{:?}", self)
+//     }
+// }
+
+
+#[derive(Default, Clone, Debug)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub lines: Vec<i64>,
+    pub constants: Vec<Value>,
+}
+
+
+// Constants that can share a slot, numbers down to the bits so 0 and -0 stay apart
+pub fn same_constant(value1: &Value, value2: &Value) -> bool {
+    match (value1.kind(), value2.kind()) {
+        (ValueKind::Number(num1), ValueKind::Number(num2)) => return num1.to_bits() == num2.to_bits(),
+        (ValueKind::Bool(b1), ValueKind::Bool(b2)) => return b1 == b2,
+        (ValueKind::Null, ValueKind::Null) => return true,
+        (ValueKind::Obj(ObjData::String(str1)), ValueKind::Obj(ObjData::String(str2))) => return str1 == str2,
+        _ => return false,
+    }
+}
+
+pub fn add_constant(chunk: &mut Chunk, value: Value, line: i64) -> Result<u8, String> {
+    let index = add_constant_dont_emit(chunk, value)?;
+    chunk.code.push(OpCode::Constant as u8);
+    chunk.code.push(index);
+    chunk.lines.push(line);
+    chunk.lines.push(line);
+    return Ok(index);
+}
+
+// Reuses the slot of an identical constant, so a name used all over a script only takes up one
+pub fn add_constant_dont_emit(chunk: &mut Chunk, value: Value) -> Result<u8, String> {
+    if let Some(index) = chunk.constants.iter().position(|constant| same_constant(constant, &value)) {
+        return Ok(index as u8);
+    }
+    if chunk.constants.len() >= u8::MAX as usize {
+        return Err(format!("Too many constants in one chunk, only {} are allowed", u8::MAX));
+    }
+    chunk.constants.push(value);
+    return Ok(chunk.constants.len() as u8 - 1);
+}
@@ -0,0 +1,616 @@
+use enum_map::{enum_map, Enum};
+
+use crate::LoxError;
+use crate::chunk::{Chunk, OpCode, add_constant, add_constant_dont_emit};
+use crate::disassembler::disassemble_chunk;
+use crate::optimizer;
+use crate::scanner::{Token, TokenType, scan};
+use crate::value::{ObjData, Value};
+
+
+// Where the parse functions are in the token list. Only the first error is kept, after that
+// everything unwinds back out to compile() which hands it to the caller. Comments are stepped
+// over, index is never left on one.
+pub struct Parser<'a> {
+    tokens: &'a [Token],
+    index: usize,
+    // the token advance() last stepped past, None before the first
+    previous: Option<usize>,
+    error: Option<LoxError>,
+    // see CompileOptions::repl
+    repl: bool,
+    // statements the parser is inside of, 1 for one at the top level
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn current(&self) -> Option<&'a Token> {
+        return self.tokens.get(self.index);
+    }
+
+    fn previous(&self) -> &'a Token {
+        return &self.tokens[self.previous.expect("Nothing's been consumed yet")];
+    }
+
+    fn advance(&mut self) {
+        self.previous = Some(self.index);
+        self.index += 1;
+        self.skip_comments();
+    }
+
+    fn skip_comments(&mut self) {
+        while self.current().is_some_and(|token| token.token_type == TokenType::Comment) {
+            self.index += 1;
+        }
+    }
+
+    // for error messages
+    fn current_data(&self) -> String {
+        match self.current() {
+            Some(token) => format!("\"{}\"", token.data),
+            None => "the end of the input".to_string(),
+        }
+    }
+
+    fn at_end(&self) -> bool {
+        return self.index >= self.tokens.len();
+    }
+
+    // out of tokens at the top level of a repl entry
+    fn at_repl_end(&self) -> bool {
+        return self.repl && self.depth == 1 && self.at_end();
+    }
+
+    fn error(&mut self, message: String) {
+        if self.error.is_some() {
+            return;
+        }
+        let line = match self.current() {
+            Some(token) => token.line,
+            None => self.tokens.iter().rev().find(|token| token.token_type != TokenType::Comment).map(|token| token.line).unwrap_or(1),
+        };
+        self.error = Some(LoxError::Compile { line: line, message: message });
+    }
+}
+
+
+fn emit_byte(chunk: &mut Chunk, byte: u8, line: i64) -> usize {
+    chunk.code.push(byte);
+    chunk.lines.push(line);
+    return chunk.code.len() - 1;
+}
+fn emit_bytes(chunk: &mut Chunk, byte: u8, byte2: u8, line: i64) -> usize {
+    emit_byte(chunk, byte, line);
+    return emit_byte(chunk, byte2, line);
+}
+
+// Line of the token that was just consumed, what everything emitted right now gets tagged with
+fn previous_line(parser: &Parser) -> i64 {
+    if parser.previous.is_none() {
+        // only happens when the very first token was an error
+        return parser.current().map(|token| token.line).unwrap_or(1);
+    }
+    return parser.previous().line;
+}
+
+
+#[repr(u8)]
+#[allow(dead_code)]
+#[derive(Debug, Enum, Copy, Clone)]
+enum Precedence {
+    None,
+    Assignment, // =
+    Or, // Or
+    And, // And
+    Equality, // ==!=
+    Comparison, // <><=>=
+    Term, // +-
+    Factor, // */
+    Unary, // !-
+    Call, // .()
+    Primary,
+}
+
+fn next_prec(precedence: Precedence) -> Precedence {
+    let next_prec_map = enum_map! {
+        Precedence::None => Precedence::Assignment,
+        Precedence::Assignment => Precedence::Or,
+        Precedence::Or => Precedence::And,
+        Precedence::And => Precedence::Equality,
+        Precedence::Equality => Precedence::Comparison,
+        Precedence::Comparison => Precedence::Term,
+        Precedence::Term => Precedence::Factor,
+        Precedence::Factor => Precedence::Unary,
+        Precedence::Unary => Precedence::Call,
+        Precedence::Call => Precedence::Primary,
+        Precedence::Primary => Precedence::Primary,
+    };
+    return next_prec_map[precedence];
+}
+
+
+#[derive(Copy, Clone)]
+struct ParseRule {
+    prefix: Option<fn(&mut Chunk, &mut Parser)>,
+    infix: Option<fn(&mut Chunk, &mut Parser)>,
+    precedence: Precedence
+}
+
+fn get_rule(token_type: TokenType) -> ParseRule {
+    let rules = enum_map! {
+        TokenType::LeftParen => ParseRule {prefix: Some(grouping), infix: Some(call), precedence: Precedence::Call},
+        TokenType::RightParen => ParseRule {prefix: None, infix: None, precedence: Precedence::None},
+        TokenType::LeftBrace => ParseRule {prefix: None, infix: None, precedence: Precedence::None},
+        TokenType::RightBrace => ParseRule {prefix: None, infix: None, precedence: Precedence::None},
+        TokenType::Comma => ParseRule {prefix: None, infix: None, precedence: Precedence::None},
+        TokenType::Dot => ParseRule {prefix: None, infix: Some(dot), precedence: Precedence::Call},
+        TokenType::Minus => ParseRule {prefix: Some(unary), infix: Some(binary), precedence: Precedence::Term},
+        TokenType::Plus => ParseRule {prefix: None, infix: Some(binary), precedence: Precedence::Term},
+        TokenType::Semicolon => ParseRule {prefix: None, infix: None, precedence: Precedence::None},
+        TokenType::Slash => ParseRule {prefix: None, infix: Some(binary), precedence: Precedence::Factor},
+        TokenType::Star => ParseRule {prefix: None, infix: Some(binary), precedence: Precedence::Factor},
+        TokenType::Bang => ParseRule {prefix: None, infix: None, precedence: Precedence::None},
+        TokenType::BangEqual => ParseRule {prefix: None, infix: Some(binary), precedence: Precedence::Equality},
+        TokenType::Equal => ParseRule {prefix: None, infix: None, precedence: Precedence::None},
+        TokenType::EqualEqual => ParseRule {prefix: None, infix: Some(binary), precedence: Precedence::Equality},
+        TokenType::Greater => ParseRule {prefix: None, infix: Some(binary), precedence: Precedence::Comparison},
+        TokenType::GreaterEqual => ParseRule {prefix: None, infix: Some(binary), precedence: Precedence::Comparison},
+        TokenType::Less => ParseRule {prefix: None, infix: Some(binary), precedence: Precedence::Comparison},
+        TokenType::LessEqual => ParseRule {prefix: None, infix: Some(binary), precedence: Precedence::Comparison},
+        TokenType::Identifier => ParseRule {prefix: Some(variable), infix: None, precedence: Precedence::None},
+        TokenType::String => ParseRule {prefix: Some(string), infix: None, precedence: Precedence::None},
+        TokenType::Number => ParseRule {prefix: Some(number), infix: None, precedence: Precedence::None},
+        TokenType::And => ParseRule {prefix: None, infix: None, precedence: Precedence::None},
+        TokenType::Class => ParseRule {prefix: None, infix: None, precedence: Precedence::None},
+        TokenType::Else => ParseRule {prefix: None, infix: None, precedence: Precedence::None},
+        TokenType::False => ParseRule {prefix: Some(literal), infix: None, precedence: Precedence::None},
+        TokenType::For => ParseRule {prefix: None, infix: None, precedence: Precedence::None},
+        TokenType::Fun => ParseRule {prefix: None, infix: None, precedence: Precedence::None},
+        TokenType::If => ParseRule {prefix: None, infix: None, precedence: Precedence::None},
+        TokenType::Null => ParseRule {prefix: Some(literal), infix: None, precedence: Precedence::None},
+        TokenType::Or => ParseRule {prefix: None, infix: None, precedence: Precedence::None},
+        TokenType::Print => ParseRule {prefix: None, infix: None, precedence: Precedence::None},
+        TokenType::Return => ParseRule {prefix: None, infix: None, precedence: Precedence::None},
+        TokenType::Super => ParseRule {prefix: None, infix: None, precedence: Precedence::None},
+        TokenType::This => ParseRule {prefix: None, infix: None, precedence: Precedence::None},
+        TokenType::True => ParseRule {prefix: Some(literal), infix: None, precedence: Precedence::None},
+        TokenType::Var => ParseRule {prefix: None, infix: None, precedence: Precedence::None},
+        TokenType::While => ParseRule {prefix: None, infix: None, precedence: Precedence::None},
+        TokenType::Error => ParseRule {prefix: None, infix: None, precedence: Precedence::None},
+
+        // THIS IS JUNK
+        TokenType::Comment => ParseRule {prefix: Some(binary), infix: None, precedence: Precedence::None},
+    };
+    return rules[token_type];
+}
+
+fn parse_precedence(chunk: &mut Chunk, parser: &mut Parser, precedence: Precedence) {
+    trace!("PRECEDENCE: {:?}, index: {}, precedence value of {}", precedence, parser.index, precedence as u8);
+    if parser.at_end() {
+        parser.error("Expected an expression but the input ended".to_string());
+        return;
+    }
+    let prefix_function = parser.current().and_then(|token| get_rule(token.token_type).prefix);
+    match prefix_function {
+        Some(x) => {
+            parser.advance();
+            x(chunk, parser);
+        },
+        None => {
+            let message = format!("Expected an expression but got {}", parser.current_data());
+            parser.error(message);
+            return;
+        },
+    };
+
+    while let Some(token) = parser.current() {
+        if parser.error.is_some() {
+            return;
+        }
+        if precedence as u8 <= get_rule(token.token_type).precedence as u8 {
+            let parse_rule = get_rule(token.token_type);
+            trace!("infix time! token: {:?}, token_type: {:?}, index: {}, parse_rule here is {:?}", token.data, token.token_type, parser.index, parse_rule.precedence);
+            parser.advance();
+            if let Some(x) = parse_rule.infix {
+                x(chunk, parser);
+            }
+        }
+        else {
+            trace!("No proper infix at index: {}, precedence is {:?}", parser.index, get_rule(token.token_type).precedence);
+            return;
+        }
+    }
+    trace!("Index went out of range!");
+}
+
+// A full constant pool is a compile error, the index is a placeholder then and never gets used
+fn emit_constant(chunk: &mut Chunk, parser: &mut Parser, value: Value, line: i64) {
+    if let Err(message) = add_constant(chunk, value, line) {
+        parser.error(message);
+    }
+}
+
+fn create_string(chunk: &mut Chunk, parser: &mut Parser, string_token: &Token) {
+    // without the quotes
+    let mut stuff: Vec<char> = string_token.data.chars().skip(1).collect();
+    stuff.pop();
+    let value = Value::from_obj(ObjData::String(stuff));
+    emit_constant(chunk, parser, value, string_token.line);
+}
+
+fn variable(chunk: &mut Chunk, parser: &mut Parser) {
+    let constant_index = identifier_constant(chunk, parser);
+    let line = previous_line(parser);
+
+    if advance_true_if_match(TokenType::Equal, parser) {
+        expression(chunk, parser);
+        emit_byte(chunk, OpCode::SetGlobal as u8, line);
+    }
+    else {
+        emit_byte(chunk, OpCode::GetGlobal as u8, line);
+    }
+    emit_byte(chunk, constant_index, line);
+}
+
+fn string(chunk: &mut Chunk, parser: &mut Parser) {
+    match &parser.previous().token_type {
+        TokenType::String => create_string(chunk, parser, parser.previous()),
+        token_type => panic!("{:?} Not a literal, crashing", token_type),
+    }
+}
+
+fn literal(chunk: &mut Chunk, parser: &mut Parser) {
+    let line = previous_line(parser);
+    match &parser.previous().token_type {
+        TokenType::True => emit_byte(chunk, OpCode::True as u8, line),
+        TokenType::False => emit_byte(chunk, OpCode::False as u8, line),
+        TokenType::Null => emit_byte(chunk, OpCode::Null as u8, line),
+        token_type => panic!("{:?} Not a literal, crashing", token_type),
+    };
+}
+
+
+fn number(chunk: &mut Chunk, parser: &mut Parser) {
+    let str_data = &parser.previous().data;
+    let num = match str_data.parse::<f64>() {
+        Ok(num) => num,
+        Err(_) => {
+            let message = format!("\"{}\" isn't a valid number", str_data);
+            parser.error(message);
+            return;
+        },
+    };
+    let value: Value = Value::from_number(num);
+    trace!("Running number at index {}, number is: {:?}", parser.previous.unwrap_or(0), value);
+    let line = previous_line(parser);
+    emit_constant(chunk, parser, value, line);
+}
+
+fn binary(chunk: &mut Chunk, parser: &mut Parser) {
+    trace!("Running binary at index {}", parser.index);
+    let last_token_type: TokenType = parser.previous().token_type;
+    let line = previous_line(parser);
+
+    let rule: ParseRule = get_rule(last_token_type);
+    parse_precedence(chunk, parser, next_prec(rule.precedence));
+
+    match last_token_type {
+        TokenType::EqualEqual => emit_byte(chunk, OpCode::Equal as u8, line),
+        TokenType::BangEqual => emit_bytes(chunk, OpCode::Equal as u8, OpCode::Not as u8, line),
+        TokenType::Greater => emit_byte(chunk, OpCode::Greater as u8, line),
+        TokenType::GreaterEqual => emit_bytes(chunk, OpCode::Less as u8, OpCode::Not as u8, line),
+        TokenType::Less => emit_byte(chunk, OpCode::Less as u8, line),
+        TokenType::LessEqual => emit_bytes(chunk, OpCode::Greater as u8, OpCode::Not as u8, line),
+        TokenType::Plus => emit_byte(chunk, OpCode::Add as u8, line),
+        TokenType::Minus => emit_byte(chunk, OpCode::Subtract as u8, line),
+        TokenType::Star => emit_byte(chunk, OpCode::Multiply as u8, line),
+        TokenType::Slash => emit_byte(chunk, OpCode::Divide as u8, line),
+        _ => panic!("Not implemented lol"),
+    };
+}
+
+fn unary(chunk: &mut Chunk, parser: &mut Parser) {
+    trace!("Running unary at index {}", parser.index);
+    let line = previous_line(parser);
+    // expression(chunk, parser);
+    parse_precedence(chunk, parser, Precedence::Unary);
+    emit_byte(chunk, OpCode::Negate as u8, line);
+}
+
+fn grouping(chunk: &mut Chunk, parser: &mut Parser) {
+    trace!("Running grouping at index {}", parser.index);
+    expression(chunk, parser);
+    consume(parser, TokenType::RightParen, "Expected a right parenthesis to end the group");
+}
+
+
+fn call(chunk: &mut Chunk, parser: &mut Parser) {
+    trace!("Running call at index {}", parser.index);
+    let line = previous_line(parser);
+    let arg_count = argument_list(chunk, parser);
+    emit_bytes(chunk, OpCode::Call as u8, arg_count, line);
+}
+
+// Only method calls for now, the only things with methods are userdata
+fn dot(chunk: &mut Chunk, parser: &mut Parser) {
+    let line = previous_line(parser);
+    if !consume(parser, TokenType::Identifier, "Expected a method name after the dot") {
+        return;
+    }
+    let name = identifier_constant(chunk, parser);
+    if !consume(parser, TokenType::LeftParen, "Expected a left parenthesis to call the method") {
+        return;
+    }
+    let arg_count = argument_list(chunk, parser);
+    emit_byte(chunk, OpCode::Invoke as u8, line);
+    emit_bytes(chunk, name, arg_count, line);
+}
+
+fn argument_list(chunk: &mut Chunk, parser: &mut Parser) -> u8 {
+    let mut arg_count: usize = 0;
+    if !advance_true_if_match(TokenType::RightParen, parser) {
+        loop {
+            expression(chunk, parser);
+            arg_count += 1;
+            if !advance_true_if_match(TokenType::Comma, parser) {
+                break;
+            }
+        }
+        consume(parser, TokenType::RightParen, "Expected a right parenthesis after the arguments");
+    }
+    if arg_count > u8::MAX as usize {
+        parser.error(format!("Can't have more than {} arguments", u8::MAX));
+    }
+    return arg_count as u8;
+}
+
+
+fn consume(parser: &mut Parser, expected_token: TokenType, error_message: &str) -> bool {
+    if let Some(token) = parser.current() {
+        if token.token_type == expected_token {
+            parser.advance();
+            return true;
+        }
+    }
+    let message = format!("{}, expected {:?} but got {}", error_message, expected_token, parser.current_data());
+    parser.error(message);
+    return false;
+}
+
+
+
+fn expression(chunk: &mut Chunk, parser: &mut Parser) {
+    parse_precedence(chunk, parser, Precedence::Assignment)
+}
+
+fn expression_statement(chunk: &mut Chunk, parser: &mut Parser) {
+    expression(chunk, parser);
+    if !parser.at_repl_end() {
+        consume(parser, TokenType::Semicolon, "needed a semicolon here bud");
+    }
+    // the last thing typed into the repl keeps its value to be printed
+    let opcode = if parser.at_repl_end() { OpCode::Return } else { OpCode::Pop };
+    emit_byte(chunk, opcode as u8, previous_line(parser));
+}
+
+fn advance_true_if_match(token_type: TokenType, parser: &mut Parser) -> bool {
+    match parser.current() {
+        Some(token) if token.token_type == token_type => {
+            parser.advance();
+            return true;
+        },
+        _ => return false,
+    }
+}
+
+fn print_statement(chunk: &mut Chunk, parser: &mut Parser) {
+    expression(chunk, parser);
+    emit_byte(chunk, OpCode::Print as u8, previous_line(parser));
+    consume(parser, TokenType::Semicolon, "needed a semicolon here bud");
+}
+
+// Emits a jump with a placeholder distance, returns where the distance goes for patch_jump
+fn emit_jump(chunk: &mut Chunk, instruction: u8, line: i64) -> usize {
+    emit_byte(chunk, instruction, line);
+    let offset = emit_byte(chunk, 0xffu8, line);
+    emit_byte(chunk, 0xffu8, line);
+    return offset;
+}
+
+fn patch_jump(chunk: &mut Chunk, offset: usize) {
+    let jump = chunk.code.len() - (offset + 2);
+    chunk.code[offset] = ((jump >> 8) & 0xff) as u8;
+    chunk.code[offset + 1] = (jump & 0xff) as u8;
+}
+
+fn if_statement(chunk: &mut Chunk, parser: &mut Parser) {
+    trace!("If statement");
+    expression(chunk, parser);
+    let then_jump = emit_jump(chunk, OpCode::JumpIfFalse as u8, previous_line(parser));
+
+    statement(chunk, parser);
+
+    if advance_true_if_match(TokenType::Else, parser) {
+        let else_jump = emit_jump(chunk, OpCode::Jump as u8, previous_line(parser));
+        patch_jump(chunk, then_jump);
+        statement(chunk, parser);
+        patch_jump(chunk, else_jump);
+    }
+    else {
+        patch_jump(chunk, then_jump);
+    }
+}
+
+fn block(chunk: &mut Chunk, parser: &mut Parser) {
+    trace!("block begin");
+    while parser.error.is_none() && parser.current().is_some_and(|token| token.token_type != TokenType::RightBrace) {
+        declaration(chunk, parser);
+    }
+    trace!("block end");
+    consume(parser, TokenType::RightBrace, "Expected a right brace to end the block");
+}
+
+fn statement(chunk: &mut Chunk, parser: &mut Parser) {
+    parser.depth += 1;
+    if advance_true_if_match(TokenType::Print, parser) {
+        print_statement(chunk, parser);
+    }
+    else if advance_true_if_match(TokenType::If, parser) {
+        if_statement(chunk, parser);
+    }
+    else if advance_true_if_match(TokenType::LeftBrace, parser) {
+        block(chunk, parser);
+    }
+    else {
+        expression_statement(chunk, parser);
+    }
+    parser.depth -= 1;
+}
+
+fn identifier_constant(chunk: &mut Chunk, parser: &mut Parser) -> u8{
+    let last_token = parser.previous();
+    let mut new_string = vec![];
+    for i in last_token.data.chars() {
+        new_string.push(i);
+    }
+    let var_name = Value::from_obj(ObjData::String(new_string));
+    match add_constant_dont_emit(chunk, var_name) {
+        Ok(index) => return index,
+        Err(message) => {
+            parser.error(message);
+            return 0;
+        },
+    }
+}
+
+fn parse_variable(chunk: &mut Chunk, parser: &mut Parser) -> Option<u8> {
+    if !consume(parser, TokenType::Identifier, "Expected to see an identifier here for a variable name") {
+        return None;
+    }
+    return Some(identifier_constant(chunk, parser));
+}
+
+fn var_declaration(chunk: &mut Chunk, parser: &mut Parser) {
+    let global_constant_index = match parse_variable(chunk, parser) {
+        Some(index) => index,
+        None => return,
+    };
+    if advance_true_if_match(TokenType::Equal, parser) {
+        expression(chunk, parser);
+    }
+    else {
+        emit_byte(chunk, OpCode::Null as u8, previous_line(parser));
+    }
+    consume(parser, TokenType::Semicolon, "needed a semicolon here bud");
+    let line = previous_line(parser);
+    emit_bytes(chunk, OpCode::DefineGlobal as u8, global_constant_index, line);
+}
+
+fn declaration(chunk: &mut Chunk, parser: &mut Parser) {
+    if advance_true_if_match(TokenType::Var, parser) {
+        trace!("Lookin like a variable declaration aint it?");
+        var_declaration(chunk, parser);
+    }
+    else {
+        trace!("Just a statement");
+        statement(chunk, parser);
+    }
+}
+
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Backend {
+    Stack,
+    Register,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CompileOptions {
+    pub fold: bool,
+    pub peephole: bool,
+    pub superinstructions: bool,
+    pub print_code: bool,
+    pub backend: Backend,
+    pub profile_pairs: bool,
+    // a trailing expression statement ends the chunk with Return instead of popping its value,
+    // and can leave off its semicolon
+    pub repl: bool,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        CompileOptions {
+            fold: true,
+            peephole: true,
+            superinstructions: false,
+            print_code: false,
+            backend: Backend::Stack,
+            profile_pairs: false,
+            repl: false,
+        }
+    }
+}
+
+impl CompileOptions {
+    // -O0 is the bytecode straight out of the compiler, -O1 (the default) folds and runs the
+    // peephole pass, -O2 also fuses superinstructions
+    pub fn set_optimization_level(&mut self, level: u8) {
+        self.fold = level >= 1;
+        self.peephole = level >= 1;
+        self.superinstructions = level >= 2;
+    }
+}
+
+
+// Compiles with the default options, the chunk can be run as many times as you like with
+// VirtualMachine::run_chunk
+pub fn compile(source: &str) -> Result<Chunk, LoxError> {
+    return compile_with_options(source, &Default::default());
+}
+
+pub fn compile_with_options(source: &str, options: &CompileOptions) -> Result<Chunk, LoxError> {
+    let (_, all_tokens) = scan(&source.to_string());
+    return compile_tokens(&all_tokens, options);
+}
+
+// For tools that keep their own tokens around, comments and all
+pub fn compile_tokens(all_tokens: &[Token], options: &CompileOptions) -> Result<Chunk, LoxError> {
+    if let Some(bad_token) = all_tokens.iter().find(|token| token.token_type == TokenType::Error) {
+        trace!("Scanner failed parsing a token somewhere");
+        return Err(LoxError::Compile { line: bad_token.line, message: bad_token.data.clone() });
+    }
+
+    let mut chunk = Chunk {
+        code: vec!(),
+        lines: vec!(),
+        constants: vec!(),
+    };
+
+    trace!("=== Starting compile ===");
+    let mut parser = Parser { tokens: all_tokens, index: 0, previous: None, error: None, repl: options.repl, depth: 0 };
+    parser.skip_comments();
+    while !parser.at_end() && parser.error.is_none() {
+        declaration(&mut chunk, &mut parser);
+    }
+    if let Some(error) = parser.error {
+        return Err(error);
+    }
+    // emit_byte(&mut chunk, OpCode::Return as u8);
+
+    if options.print_code {
+        disassemble_chunk(&chunk, "before optimization");
+    }
+    if options.fold {
+        optimizer::fold_constants(&mut chunk);
+    }
+    if options.peephole {
+        optimizer::peephole(&mut chunk);
+    }
+    if options.superinstructions {
+        optimizer::fuse_superinstructions(&mut chunk);
+    }
+    if options.print_code {
+        disassemble_chunk(&chunk, "after optimization");
+    }
+    return Ok(chunk);
+}
@@ -0,0 +1,164 @@
+use crate::value::{ObjData, Value, ValueKind, get_value_str_with_quotes};
+
+
+// Turning Rust values into Lox values and back, so embedders (and natives, see native.rs)
+// don't have to pick apart Value::kind() themselves. FromLox is strict, a bool only comes from
+// a Lox bool and not from anything truthy, and the error says what it got instead.
+
+pub trait IntoLox {
+    fn into_lox(self) -> Value;
+}
+
+pub trait FromLox: Sized {
+    fn from_lox(value: &Value) -> Result<Self, String>;
+}
+
+
+fn mismatch(expected: &str, value: &Value) -> String {
+    return format!("expected {} but got {}", expected, get_value_str_with_quotes(value));
+}
+
+
+impl IntoLox for Value {
+    fn into_lox(self) -> Value {
+        return self;
+    }
+}
+
+impl FromLox for Value {
+    fn from_lox(value: &Value) -> Result<Self, String> {
+        return Ok(value.clone());
+    }
+}
+
+
+impl IntoLox for f64 {
+    fn into_lox(self) -> Value {
+        return Value::from_number(self);
+    }
+}
+
+impl FromLox for f64 {
+    fn from_lox(value: &Value) -> Result<Self, String> {
+        match value.kind() {
+            ValueKind::Number(num) => return Ok(num),
+            _ => return Err(mismatch("a number", value)),
+        }
+    }
+}
+
+impl IntoLox for f32 {
+    fn into_lox(self) -> Value {
+        return Value::from_number(self as f64);
+    }
+}
+
+impl FromLox for f32 {
+    fn from_lox(value: &Value) -> Result<Self, String> {
+        return Ok(f64::from_lox(value)? as f32);
+    }
+}
+
+
+// Lox only has f64, so integers have to be whole and fit in the type on the way back
+macro_rules! impl_integer {
+    ($($int:ty),*) => {
+        $(
+            impl IntoLox for $int {
+                fn into_lox(self) -> Value {
+                    return Value::from_number(self as f64);
+                }
+            }
+
+            impl FromLox for $int {
+                fn from_lox(value: &Value) -> Result<Self, String> {
+                    let num = f64::from_lox(value)?;
+                    // MIN is exact as an f64 but MAX rounds up to the power of two past it, and
+                    // `as` would quietly saturate anything from there on, so that's the bound
+                    let signed = (<$int>::MIN != 0) as i32;
+                    let end = 2f64.powi(<$int>::BITS as i32 - signed);
+                    if num.fract() != 0.0 || num < <$int>::MIN as f64 || num >= end {
+                        return Err(mismatch(concat!("a whole number that fits in ", stringify!($int)), value));
+                    }
+                    return Ok(num as $int);
+                }
+            }
+        )*
+    };
+}
+
+impl_integer!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+
+impl IntoLox for bool {
+    fn into_lox(self) -> Value {
+        return Value::from_bool(self);
+    }
+}
+
+impl FromLox for bool {
+    fn from_lox(value: &Value) -> Result<Self, String> {
+        match value.kind() {
+            ValueKind::Bool(b) => return Ok(b),
+            _ => return Err(mismatch("a bool", value)),
+        }
+    }
+}
+
+
+impl IntoLox for String {
+    fn into_lox(self) -> Value {
+        return Value::from_obj(ObjData::String(self.chars().collect()));
+    }
+}
+
+impl IntoLox for &str {
+    fn into_lox(self) -> Value {
+        return Value::from_obj(ObjData::String(self.chars().collect()));
+    }
+}
+
+impl FromLox for String {
+    fn from_lox(value: &Value) -> Result<Self, String> {
+        match value.kind() {
+            ValueKind::Obj(ObjData::String(string)) => return Ok(string.iter().collect()),
+            _ => return Err(mismatch("a string", value)),
+        }
+    }
+}
+
+
+// None is null
+impl<T: IntoLox> IntoLox for Option<T> {
+    fn into_lox(self) -> Value {
+        match self {
+            Some(x) => return x.into_lox(),
+            None => return Value::null(),
+        }
+    }
+}
+
+impl<T: FromLox> FromLox for Option<T> {
+    fn from_lox(value: &Value) -> Result<Self, String> {
+        match value.kind() {
+            ValueKind::Null => return Ok(None),
+            _ => return Ok(Some(T::from_lox(value)?)),
+        }
+    }
+}
+
+
+impl IntoLox for () {
+    fn into_lox(self) -> Value {
+        return Value::null();
+    }
+}
+
+impl FromLox for () {
+    fn from_lox(value: &Value) -> Result<Self, String> {
+        match value.kind() {
+            ValueKind::Null => return Ok(()),
+            _ => return Err(mismatch("null", value)),
+        }
+    }
+}
@@ -0,0 +1,335 @@
+use std::cell::Cell;
+use std::io::{BufRead, Write};
+use std::rc::Rc;
+
+use crate::chunk::Chunk;
+use crate::compiler::{Backend, CompileOptions, compile_with_options};
+use crate::debugger::{Debugger, Step, Stopped, breakpoint_line, current_line};
+use crate::json::Json;
+use crate::loxc::{chunk_from_bytes, is_loxc};
+use crate::output::MemorySink;
+use crate::protocol::{read_message, write_message};
+use crate::stdlib::Profile;
+use crate::value::get_value_str_with_quotes;
+use crate::vm::Vm;
+
+
+// A Debug Adapter Protocol server, for debugging scripts from an editor. It goes
+//     initialize, launch {program, stopOnEntry}, initialized event, setBreakpoints...,
+//     configurationDone, then stopped events and continue/next/stepIn until terminated
+// There's one thread and one stack frame, the script. Its variables are in two scopes, the
+// globals and the values on the vm's stack. What the script prints comes back as output events.
+// Requests are handled one at a time, so a script that's running can't be paused.
+
+const THREAD_ID: i64 = 1;
+const GLOBALS_REFERENCE: i64 = 1;
+const STACK_REFERENCE: i64 = 2;
+
+// What to do once the response to a request has gone out
+enum After {
+    Nothing,
+    Initialized,
+    Run(Step),
+}
+
+struct Session {
+    seq: i64,
+    outbox: Vec<Json>,
+    options: CompileOptions,
+    profile: Profile,
+    program: String,
+    chunk: Option<Chunk>,
+    vm: Option<Vm>,
+    debugger: Debugger,
+    stop_on_entry: bool,
+    started: bool,
+    // paused somewhere with more of the script to run
+    paused: bool,
+    printed: MemorySink,
+    errors: MemorySink,
+    // what the script passed to exit(), which stops it without taking the adapter down too
+    exit_code: Rc<Cell<Option<i32>>>,
+    // 1 unless the client counts lines from 0
+    first_line: i64,
+}
+
+// Serves requests until disconnect or the end of input
+pub fn serve(input: &mut impl BufRead, output: &mut impl Write, options: &CompileOptions, profile: Profile) -> Result<(), String> {
+    let mut session = Session {
+        seq: 1,
+        outbox: vec![],
+        options: *options,
+        profile: profile,
+        program: String::new(),
+        chunk: None,
+        vm: None,
+        debugger: Debugger::new(),
+        stop_on_entry: false,
+        started: false,
+        paused: false,
+        printed: MemorySink::new(),
+        errors: MemorySink::new(),
+        exit_code: Rc::new(Cell::new(None)),
+        first_line: 1,
+    };
+    while let Some(message) = read_message(input)? {
+        let keep_going = session.handle(&message);
+        for message in session.outbox.drain(..) {
+            write_message(output, &message).map_err(|error| format!("Couldn't write a message: {}", error))?;
+        }
+        if !keep_going {
+            break;
+        }
+    }
+    return Ok(());
+}
+
+impl Session {
+    fn send(&mut self, mut fields: Vec<(&str, Json)>) {
+        fields.insert(0, ("seq", Json::from(self.seq)));
+        self.seq += 1;
+        self.outbox.push(Json::object(fields));
+    }
+
+    fn event(&mut self, event: &str, body: Json) {
+        let mut fields = vec![("type", Json::from("event")), ("event", Json::from(event))];
+        if !body.is_null() {
+            fields.push(("body", body));
+        }
+        self.send(fields);
+    }
+
+    fn handle(&mut self, request: &Json) -> bool {
+        if request.get("type").as_str() != Some("request") {
+            return true;
+        }
+        let command = request.get("command").as_str().unwrap_or("").to_string();
+        let arguments = request.get("arguments");
+        let mut after = After::Nothing;
+        let result = match command.as_str() {
+            "initialize" => {
+                if arguments.get("linesStartAt1").as_bool() == Some(false) {
+                    self.first_line = 0;
+                }
+                Ok(Json::object(vec![("supportsConfigurationDoneRequest", Json::from(true))]))
+            },
+            "launch" => {
+                after = After::Initialized;
+                self.launch(arguments)
+            },
+            "setBreakpoints" => self.set_breakpoints(arguments),
+            "configurationDone" => {
+                after = After::Run(if self.stop_on_entry { Step::Instruction } else { Step::Continue });
+                Ok(Json::Null)
+            },
+            "threads" => {
+                let thread = Json::object(vec![("id", Json::from(THREAD_ID)), ("name", Json::from("main"))]);
+                Ok(Json::object(vec![("threads", Json::from(vec![thread]))]))
+            },
+            "stackTrace" => Ok(self.stack_trace()),
+            "scopes" => Ok(self.scopes()),
+            "variables" => Ok(self.variables(arguments.get("variablesReference").as_i64().unwrap_or(0))),
+            "continue" | "next" | "stepIn" => {
+                if self.paused {
+                    after = After::Run(if command == "continue" { Step::Continue } else { Step::Line });
+                    Ok(Json::object(vec![("allThreadsContinued", Json::from(true))]))
+                }
+                else {
+                    Err("The script isn't paused".to_string())
+                }
+            },
+            "evaluate" => self.evaluate(arguments.get("expression").as_str().unwrap_or("")),
+            "disconnect" | "terminate" => Ok(Json::Null),
+            _ => Err(format!("rlox can't do {}", command)),
+        };
+
+        let mut response = vec![
+            ("type", Json::from("response")),
+            ("request_seq", request.get("seq").clone()),
+            ("success", Json::from(result.is_ok())),
+            ("command", Json::from(command.as_str())),
+        ];
+        match result {
+            Ok(body) if !body.is_null() => response.push(("body", body)),
+            Ok(_) => (),
+            Err(message) => response.push(("message", Json::from(message))),
+        }
+        self.send(response);
+
+        match after {
+            After::Nothing => (),
+            After::Initialized if self.chunk.is_some() => self.event("initialized", Json::Null),
+            After::Initialized => (),
+            After::Run(step) => self.run(step),
+        }
+        return command != "disconnect" && command != "terminate";
+    }
+
+    fn launch(&mut self, arguments: &Json) -> Result<Json, String> {
+        let program = arguments.get("program").as_str().ok_or("launch needs a program")?;
+        let bytes = std::fs::read(program).map_err(|error| format!("Couldn't read {}: {}", program, error))?;
+        let chunk = if is_loxc(&bytes) {
+            chunk_from_bytes(&bytes).map_err(|message| format!("Couldn't load {}: {}", program, message))?
+        }
+        else {
+            let source = String::from_utf8(bytes).map_err(|_| format!("{} isn't valid utf-8", program))?;
+            compile_with_options(&source, &self.options).map_err(|error| error.to_string())?
+        };
+
+        // stepping goes by the chunk's instructions, so it's always the stack backend
+        let mut vm = Vm::with_profile(CompileOptions { backend: Backend::Stack, ..self.options }, self.profile);
+        vm.set_output(self.printed.clone());
+        vm.set_errors(self.errors.clone());
+        // stdin is where the requests come from
+        if vm.globals.contains_key("input") {
+            vm.register_native("input", || -> Result<Option<String>, String> {
+                return Err("input() can't read stdin while debugging, the debugger is using it".to_string());
+            });
+        }
+        // the error only stops the vm, run() sees the code and ends the session with it
+        if vm.globals.contains_key("exit") {
+            let exit_code = self.exit_code.clone();
+            vm.register_native("exit", move |code: i32| -> Result<(), String> {
+                exit_code.set(Some(code));
+                return Err(format!("exit({})", code));
+            });
+        }
+        self.program = program.to_string();
+        self.stop_on_entry = arguments.get("stopOnEntry").as_bool().unwrap_or(false);
+        self.chunk = Some(chunk);
+        self.vm = Some(vm);
+        return Ok(Json::Null);
+    }
+
+    // Breakpoints on lines without code move down to the next line that has some
+    fn set_breakpoints(&mut self, arguments: &Json) -> Result<Json, String> {
+        let chunk = self.chunk.as_ref().ok_or("Breakpoints can only be set after launch")?;
+        self.debugger.breakpoints.clear();
+        let mut breakpoints = vec![];
+        for breakpoint in arguments.get("breakpoints").as_array() {
+            let requested = breakpoint.get("line").as_i64().unwrap_or(0) - self.first_line + 1;
+            match breakpoint_line(chunk, requested) {
+                Some(line) => {
+                    self.debugger.breakpoints.insert(line);
+                    breakpoints.push(Json::object(vec![("verified", Json::from(true)), ("line", Json::from(line - 1 + self.first_line))]));
+                },
+                None => {
+                    breakpoints.push(Json::object(vec![("verified", Json::from(false)), ("message", Json::from("There's no code on or after this line"))]));
+                },
+            }
+        }
+        return Ok(Json::object(vec![("breakpoints", Json::from(breakpoints))]));
+    }
+
+    fn run(&mut self, step: Step) {
+        let (Some(vm), Some(chunk)) = (self.vm.as_mut(), self.chunk.as_ref()) else {
+            return;
+        };
+        let first = !self.started;
+        let result = if first {
+            self.started = true;
+            self.debugger.start(vm, chunk, step)
+        }
+        else {
+            self.debugger.resume(vm, step)
+        };
+        let exit_code = self.exit_code.take();
+        if exit_code.is_some() {
+            // it's not an error, the script asked to stop
+            self.errors.clear();
+        }
+        self.send_output();
+
+        self.paused = matches!(result, Ok(Stopped::Breakpoint(_)) | Ok(Stopped::Step));
+        let reason = match result {
+            Ok(Stopped::Breakpoint(_)) => "breakpoint",
+            Ok(Stopped::Step) if first => "entry",
+            Ok(Stopped::Step) => "step",
+            Ok(Stopped::Finished) => {
+                self.exited(0);
+                return;
+            },
+            Err(_) => {
+                self.exited(exit_code.map_or(70, |code| code as i64));
+                return;
+            },
+        };
+        let body = Json::object(vec![("reason", Json::from(reason)), ("threadId", Json::from(THREAD_ID)), ("allThreadsStopped", Json::from(true))]);
+        self.event("stopped", body);
+    }
+
+    // Exit codes are the ones `rlox file.lox` would have
+    fn exited(&mut self, code: i64) {
+        self.event("exited", Json::object(vec![("exitCode", Json::from(code))]));
+        self.event("terminated", Json::object(vec![]));
+    }
+
+    fn send_output(&mut self) {
+        for (sink, category) in [(self.printed.clone(), "stdout"), (self.errors.clone(), "stderr")] {
+            let text = sink.contents();
+            sink.clear();
+            if !text.is_empty() {
+                self.event("output", Json::object(vec![("category", Json::from(category)), ("output", Json::from(text))]));
+            }
+        }
+    }
+
+    fn stack_trace(&self) -> Json {
+        let mut frames = vec![];
+        if let (true, Some(vm)) = (self.paused, &self.vm) {
+            let name = std::path::Path::new(&self.program).file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+            let source = Json::object(vec![("name", Json::from(name)), ("path", Json::from(self.program.as_str()))]);
+            frames.push(Json::object(vec![
+                ("id", Json::from(1_i64)),
+                ("name", Json::from("script")),
+                ("source", source),
+                ("line", Json::from(current_line(vm) - 1 + self.first_line)),
+                ("column", Json::from(self.first_line)),
+            ]));
+        }
+        let total = frames.len();
+        return Json::object(vec![("stackFrames", Json::from(frames)), ("totalFrames", Json::from(total))]);
+    }
+
+    fn scopes(&self) -> Json {
+        let scope = |name: &str, reference: i64| {
+            return Json::object(vec![("name", Json::from(name)), ("variablesReference", Json::from(reference)), ("expensive", Json::from(false))]);
+        };
+        return Json::object(vec![("scopes", Json::from(vec![scope("Globals", GLOBALS_REFERENCE), scope("Stack", STACK_REFERENCE)]))]);
+    }
+
+    // Globals by name, and the stack from the bottom up with the top last
+    fn variables(&self, reference: i64) -> Json {
+        let variable = |name: &str, value: String| {
+            return Json::object(vec![("name", Json::from(name)), ("value", Json::from(value)), ("variablesReference", Json::from(0_i64))]);
+        };
+        let mut variables = vec![];
+        if let Some(vm) = &self.vm {
+            if reference == GLOBALS_REFERENCE {
+                let mut names: Vec<&String> = vm.globals.keys().collect();
+                names.sort();
+                for name in names {
+                    variables.push(variable(name, get_value_str_with_quotes(&vm.globals[name])));
+                }
+            }
+            else if reference == STACK_REFERENCE {
+                for (index, value) in vm.stack.iter().enumerate() {
+                    variables.push(variable(&index.to_string(), get_value_str_with_quotes(value)));
+                }
+            }
+        }
+        return Json::object(vec![("variables", Json::from(variables))]);
+    }
+
+    // With the script's globals, wherever it's paused
+    fn evaluate(&mut self, expression: &str) -> Result<Json, String> {
+        let vm = self.vm.as_mut().ok_or("There's no script to evaluate in before launch")?;
+        let result = vm.eval_while_suspended(expression);
+        // the error goes back in the response rather than as output
+        self.errors.clear();
+        self.send_output();
+        let value = result.map_err(|error| error.to_string())?;
+        let text = value.map(|value| get_value_str_with_quotes(&value)).unwrap_or_default();
+        return Ok(Json::object(vec![("result", Json::from(text)), ("variablesReference", Json::from(0_i64))]));
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::BTreeMap;
+
+use colored::Colorize;
+
+use rlox::debugger::{breakpoint_line, current_line};
+use rlox::disassembler::instruction_text;
+use rlox::{Chunk, CompileOptions, Debugger, Profile, Step, Stopped, Vm, get_value_str_with_quotes};
+
+use crate::editor::{LineEditor, ReadResult};
+
+
+const HELP: &str = "\
+break <line>    pause before <line> runs, or the first line after it with code
+delete [<line>] remove the breakpoint on <line>, or all of them
+breakpoints     list the breakpoints
+step            run until the next line starts
+next            the same as step, natives can't be stepped into
+stepi           run one instruction
+continue        run until a breakpoint or the end
+stack           show the stack, the top is last
+globals         list the globals and their values
+print <expr>    evaluate <expr> with the script's globals
+where           show where the script is paused
+help            show this
+quit            stop debugging
+An empty line does the last command again. Commands can be shortened to b, d, s, n, si, c, p, w and q.";
+
+// Pauses before the first instruction, then takes commands until quit or the end of input.
+// source is for showing lines, a .loxc file doesn't have any.
+pub fn debug(chunk: &Chunk, source: Option<&str>, options: &CompileOptions, profile: Profile) {
+    let lines: Vec<&str> = source.map(|source| source.lines().collect()).unwrap_or_default();
+    // stepping goes by the chunk's instructions, the register backend would do them differently
+    let mut vm = Vm::with_profile(CompileOptions { backend: rlox::Backend::Stack, ..*options }, profile);
+    let mut debugger = Debugger::new();
+    let mut running = match debugger.start(&mut vm, chunk, Step::Instruction) {
+        Ok(stopped) => show_stop(&vm, stopped, &lines),
+        Err(_) => false,
+    };
+
+    let mut editor = LineEditor::new(None);
+    let mut last_command = String::new();
+    loop {
+        let mut command = match editor.read_line("(rlox) ") {
+            ReadResult::Line(line) => line.trim().to_string(),
+            ReadResult::Interrupted => continue,
+            ReadResult::Eof => return,
+        };
+        if command.is_empty() {
+            command = last_command.clone();
+        }
+        else {
+            editor.add_history(&command);
+        }
+        last_command = command.clone();
+
+        let (name, argument) = match command.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, argument.trim()),
+            None => (command.as_str(), ""),
+        };
+        let step = match name {
+            "step" | "s" | "next" | "n" => Some(Step::Line),
+            "stepi" | "si" => Some(Step::Instruction),
+            "continue" | "c" => Some(Step::Continue),
+            _ => None,
+        };
+        if let Some(step) = step {
+            if !running {
+                println!("{}", "The script isn't running any more".red());
+                continue;
+            }
+            running = match debugger.resume(&mut vm, step) {
+                Ok(stopped) => show_stop(&vm, stopped, &lines),
+                Err(_) => false,
+            };
+            continue;
+        }
+
+        match name {
+            "" => (),
+            "break" | "b" => {
+                match argument.parse::<i64>() {
+                    Ok(line) => {
+                        match breakpoint_line(&vm.chunk, line) {
+                            Some(code_line) => {
+                                debugger.breakpoints.insert(code_line);
+                                println!("Breakpoint on line {}", code_line);
+                            },
+                            None => println!("{}", format!("There's no code on line {} or after it", line).red()),
+                        }
+                    },
+                    Err(_) => println!("{}", "break needs a line number".red()),
+                }
+            },
+            "delete" | "d" => {
+                if argument.is_empty() {
+                    debugger.breakpoints.clear();
+                }
+                else if !argument.parse::<i64>().is_ok_and(|line| debugger.breakpoints.remove(&line)) {
+                    println!("{}", format!("There's no breakpoint on line {}", argument).red());
+                }
+            },
+            "breakpoints" => {
+                for line in &debugger.breakpoints {
+                    println!("line {}", line);
+                }
+            },
+            "stack" => {
+                for (index, value) in vm.stack.iter().enumerate() {
+                    println!("{:>4}  {}", index, get_value_str_with_quotes(value));
+                }
+            },
+            "globals" => {
+                let mut names: Vec<&String> = vm.globals.keys().collect();
+                names.sort();
+                for name in names {
+                    println!("{} = {}", name, get_value_str_with_quotes(&vm.globals[name]));
+                }
+            },
+            "print" | "p" => {
+                // errors have been reported by the vm
+                if let Ok(Some(value)) = vm.eval_while_suspended(argument) {
+                    println!("{}", get_value_str_with_quotes(&value));
+                }
+            },
+            "where" | "w" => {
+                if running {
+                    show_position(&vm, &lines);
+                }
+                else {
+                    println!("The script isn't running any more");
+                }
+            },
+            "help" | "h" => println!("{}", HELP),
+            "quit" | "q" => return,
+            _ => println!("{}", format!("Unknown command {}, help lists them", name).red()),
+        }
+    }
+}
+
+// Says why it stopped, and whether there's any more to run
+fn show_stop(vm: &Vm, stopped: Stopped, lines: &[&str]) -> bool {
+    match stopped {
+        Stopped::Breakpoint(line) => println!("Breakpoint on line {}", line),
+        Stopped::Step => (),
+        Stopped::Finished => {
+            println!("The script finished");
+            return false;
+        },
+    }
+    show_position(vm, lines);
+    return true;
+}
+
+// The line it's paused in and the instruction that runs next
+fn show_position(vm: &Vm, lines: &[&str]) {
+    let line = current_line(vm);
+    match usize::try_from(line - 1).ok().and_then(|index| lines.get(index)) {
+        Some(text) => println!("[line {}] {}", line, text.trim()),
+        None => println!("[line {}]", line),
+    }
+    let (text, _) = instruction_text(&vm.chunk, vm.ip, &BTreeMap::new());
+    println!("    {:04}  {}", vm.ip, text);
+}
@@ -0,0 +1,99 @@
+use std::collections::BTreeSet;
+
+use crate::LoxError;
+use crate::chunk::Chunk;
+use crate::vm::{DebugHook, VirtualMachine};
+
+
+// How far to let the script go before pausing again
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Step {
+    // until a breakpoint
+    Continue,
+    // until another line starts. Natives can't be stepped into, so until Lox has functions of its
+    // own stepping over a line and into it end up in the same place.
+    Line,
+    Instruction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stopped {
+    Breakpoint(i64),
+    Step,
+    // ran to the end, or into an error that the vm has already reported
+    Finished,
+}
+
+// Breakpoints and stepping, on top of the vm's DebugHook. Breakpoints are by line and pause
+// before the first instruction of that line runs.
+#[derive(Debug, Default)]
+pub struct Debugger {
+    pub breakpoints: BTreeSet<i64>,
+    step: Option<Step>,
+    stopped: Option<Stopped>,
+    // the line of the instruction asked about last time, a line starts when that changes
+    last_line: Option<i64>,
+    // resuming asks about the instruction it paused before again, which mustn't pause twice
+    paused_at: Option<usize>,
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        return Default::default();
+    }
+
+    // Sets the vm up with the chunk and runs it like resume does. Step::Instruction stops
+    // before anything has run.
+    pub fn start(&mut self, vm: &mut VirtualMachine, chunk: &Chunk, step: Step) -> Result<Stopped, LoxError> {
+        vm.start_chunk(chunk)?;
+        self.last_line = None;
+        self.paused_at = None;
+        return self.resume(vm, step);
+    }
+
+    pub fn resume(&mut self, vm: &mut VirtualMachine, step: Step) -> Result<Stopped, LoxError> {
+        self.step = Some(step);
+        self.stopped = None;
+        let paused = vm.resume_with_hook(self)?;
+        if !paused {
+            self.paused_at = None;
+            return Ok(Stopped::Finished);
+        }
+        return Ok(self.stopped.expect("The hook says why whenever it pauses"));
+    }
+}
+
+impl DebugHook for Debugger {
+    fn before_instruction(&mut self, vm: &VirtualMachine) -> bool {
+        let line = vm.chunk.lines.get(vm.ip).copied().unwrap_or(0);
+        let new_line = self.last_line != Some(line);
+        self.last_line = Some(line);
+        if self.paused_at.take() == Some(vm.ip) {
+            return false;
+        }
+
+        let stopped = if new_line && self.breakpoints.contains(&line) {
+            Some(Stopped::Breakpoint(line))
+        }
+        else if self.step == Some(Step::Instruction) || (new_line && self.step == Some(Step::Line)) {
+            Some(Stopped::Step)
+        }
+        else {
+            None
+        };
+        if stopped.is_some() {
+            self.stopped = stopped;
+            self.paused_at = Some(vm.ip);
+        }
+        return stopped.is_some();
+    }
+}
+
+// Where a breakpoint asked for on line ends up, the first line from there on that has code
+pub fn breakpoint_line(chunk: &Chunk, line: i64) -> Option<i64> {
+    return chunk.lines.iter().copied().filter(|code_line| *code_line >= line).min();
+}
+
+pub fn current_line(vm: &VirtualMachine) -> i64 {
+    return vm.chunk.lines.get(vm.ip).copied().unwrap_or(0);
+}
@@ -0,0 +1,254 @@
+use std::collections::BTreeMap;
+
+use enum_map::Enum;
+
+use crate::chunk::{Chunk, OpCode, OperandLayout, instruction_size, jump_target, opcode_name, operand_layout};
+use crate::json::{json_number, json_string};
+use crate::value::{ObjData, Value, ValueKind, get_value_str};
+
+
+// A listing looks like
+//     === name ===
+//     constants:
+//         0  "a"
+//         1  1
+//     code:
+//     0000     1  Constant       1        ; 1
+//     0002     |  DefineGlobal   0        ; a
+//     0004     2  GetGlobal      0        ; a
+//     0006     |  JumpIfFalse    L0       ; 0012
+//     0009     |  GetGlobalPrint 0        ; a
+//     L0:
+//     0011     3  Return
+// Line numbers are | when they're the same as the instruction before. Everything after a ; is
+// only there for reading. Bytes that aren't an instruction (in a chunk that wouldn't verify)
+// come out as `.byte N`.
+
+// Jump targets in the order they're in the code, named L0, L1, ...
+pub fn jump_labels(chunk: &Chunk) -> BTreeMap<usize, String> {
+    let mut targets = vec![];
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        if is_instruction(chunk, offset) && operand_layout(chunk.code[offset]) == OperandLayout::Jump {
+            targets.push(jump_target(chunk, offset));
+        }
+        offset += size_at(chunk, offset);
+    }
+    targets.sort();
+    targets.dedup();
+    return targets.into_iter().enumerate().map(|(index, target)| (target, format!("L{}", index))).collect();
+}
+
+// A real opcode with all of its operands before the end of the code
+pub fn is_instruction(chunk: &Chunk, offset: usize) -> bool {
+    let instruction = chunk.code[offset];
+    return (instruction as usize) < OpCode::LENGTH && offset + instruction_size(instruction) <= chunk.code.len();
+}
+
+// How many bytes to take for the instruction at offset, 1 for a byte that isn't one
+pub fn size_at(chunk: &Chunk, offset: usize) -> usize {
+    if is_instruction(chunk, offset) {
+        return instruction_size(chunk.code[offset]);
+    }
+    return 1;
+}
+
+// How a constant is written in the constants section, strings are escaped so they can be read
+// back in
+pub fn constant_literal(value: &Value) -> String {
+    match value.kind() {
+        ValueKind::Obj(ObjData::String(string)) => return json_string(&string.iter().collect::<String>()),
+        _ => return get_value_str(value),
+    }
+}
+
+// Comments are escaped too, a newline in one would break the listing
+fn constant_comment(chunk: &Chunk, index: u8) -> String {
+    match chunk.constants.get(index as usize) {
+        Some(value) => return constant_literal(value),
+        None => return "out of range".to_string(),
+    }
+}
+
+// A global's or method's name, without the quotes
+fn constant_name(chunk: &Chunk, index: u8) -> String {
+    match chunk.constants.get(index as usize) {
+        Some(value) => return get_value_str(value),
+        None => return "out of range".to_string(),
+    }
+}
+
+fn name_comment(chunk: &Chunk, index: u8) -> String {
+    return constant_name(chunk, index).escape_debug().to_string();
+}
+
+// One instruction as a line of the listing (without the offset and line), and how many bytes it
+// took up. Jumps go to their label when there is one, otherwise to the offset.
+pub fn instruction_text(chunk: &Chunk, offset: usize, labels: &BTreeMap<usize, String>) -> (String, usize) {
+    let instruction = chunk.code[offset];
+    if !is_instruction(chunk, offset) {
+        return (format!(".byte {}", instruction), 1);
+    }
+    let size = instruction_size(instruction);
+
+    let (operands, comment) = match operand_layout(instruction) {
+        OperandLayout::None => (String::new(), None),
+        OperandLayout::Constant => {
+            let index = chunk.code[offset + 1];
+            (index.to_string(), Some(constant_comment(chunk, index)))
+        },
+        OperandLayout::Global => {
+            let index = chunk.code[offset + 1];
+            (index.to_string(), Some(name_comment(chunk, index)))
+        },
+        OperandLayout::ArgCount => (chunk.code[offset + 1].to_string(), None),
+        OperandLayout::Method => {
+            let index = chunk.code[offset + 1];
+            (format!("{} {}", index, chunk.code[offset + 2]), Some(name_comment(chunk, index)))
+        },
+        OperandLayout::Jump => {
+            let target = jump_target(chunk, offset);
+            match labels.get(&target) {
+                Some(label) => (label.clone(), Some(format!("{:04}", target))),
+                None => (format!("{:04}", target), None),
+            }
+        },
+    };
+    let text = match comment {
+        Some(comment) => format!("{:<14} {:<8} ; {}", opcode_name(instruction), operands, comment),
+        None => format!("{:<14} {}", opcode_name(instruction), operands).trim_end().to_string(),
+    };
+    return (text, size);
+}
+
+fn line_column(chunk: &Chunk, offset: usize, previous: Option<usize>) -> String {
+    let line = chunk.lines.get(offset);
+    if line.is_some() && previous.is_some_and(|previous| chunk.lines.get(previous) == line) {
+        return "|".to_string();
+    }
+    match line {
+        Some(line) => return line.to_string(),
+        None => return "?".to_string(),
+    }
+}
+
+pub fn disassemble(chunk: &Chunk, name: &str) -> String {
+    let labels = jump_labels(chunk);
+    let mut listing = format!("=== {} ===\n", name);
+    listing += "constants:\n";
+    for (index, constant) in chunk.constants.iter().enumerate() {
+        listing += &format!("    {:<3}{}\n", index, constant_literal(constant));
+    }
+    listing += "code:\n";
+    let mut offset = 0;
+    let mut previous = None;
+    while offset < chunk.code.len() {
+        if let Some(label) = labels.get(&offset) {
+            listing += &format!("{}:\n", label);
+        }
+        let (text, size) = instruction_text(chunk, offset, &labels);
+        listing += &format!("{:04} {:>5}  {}\n", offset, line_column(chunk, offset, previous), text);
+        previous = Some(offset);
+        offset += size;
+    }
+    // a jump to the very end
+    if let Some(label) = labels.get(&chunk.code.len()) {
+        listing += &format!("{}:\n", label);
+    }
+    return listing;
+}
+
+pub fn disassemble_chunk(chunk: &Chunk, name: &str) {
+    print!("{}", disassemble(chunk, name));
+}
+
+// For tracing while the vm runs, so there's no labels
+pub fn disassemble_and_print_instruction(chunk: &Chunk, offset: usize) -> usize {
+    let previous = if offset > 0 { Some(offset - 1) } else { None };
+    let (text, size) = instruction_text(chunk, offset, &BTreeMap::new());
+    println!("{:04} {:>5}  {}", offset, line_column(chunk, offset, previous), text);
+    return size;
+}
+
+
+fn constant_json(value: &Value) -> String {
+    match value.kind() {
+        ValueKind::Null => return "{\"type\": \"null\", \"value\": null}".to_string(),
+        ValueKind::Bool(boolean) => return format!("{{\"type\": \"bool\", \"value\": {}}}", boolean),
+        ValueKind::Number(number) => return format!("{{\"type\": \"number\", \"value\": {}}}", json_number(number)),
+        ValueKind::Obj(ObjData::String(string)) => return format!("{{\"type\": \"string\", \"value\": {}}}", json_string(&string.iter().collect::<String>())),
+        ValueKind::Obj(ObjData::Native(native)) => return format!("{{\"type\": \"native\", \"value\": {}}}", json_string(&native.name)),
+        ValueKind::Obj(ObjData::UserData(data)) => return format!("{{\"type\": {}, \"value\": {}}}", json_string(data.type_name()), json_string(&data.display())),
+    }
+}
+
+fn instruction_json(chunk: &Chunk, offset: usize, labels: &BTreeMap<usize, String>) -> (String, usize) {
+    let instruction = chunk.code[offset];
+    let mut fields = vec![format!("\"offset\": {}", offset)];
+    if let Some(line) = chunk.lines.get(offset) {
+        fields.push(format!("\"line\": {}", line));
+    }
+    if !is_instruction(chunk, offset) {
+        fields.push(format!("\"byte\": {}", instruction));
+        return (format!("{{{}}}", fields.join(", ")), 1);
+    }
+    let size = instruction_size(instruction);
+    fields.push(format!("\"opcode\": {}", json_string(&opcode_name(instruction))));
+    fields.push(format!("\"size\": {}", size));
+    match operand_layout(instruction) {
+        OperandLayout::None => (),
+        OperandLayout::Constant => {
+            let index = chunk.code[offset + 1];
+            fields.push(format!("\"constant\": {}", index));
+        },
+        OperandLayout::Global => {
+            let index = chunk.code[offset + 1];
+            fields.push(format!("\"constant\": {}", index));
+            fields.push(format!("\"name\": {}", json_string(&constant_name(chunk, index))));
+        },
+        OperandLayout::ArgCount => fields.push(format!("\"arg_count\": {}", chunk.code[offset + 1])),
+        OperandLayout::Method => {
+            let index = chunk.code[offset + 1];
+            fields.push(format!("\"constant\": {}", index));
+            fields.push(format!("\"name\": {}", json_string(&constant_name(chunk, index))));
+            fields.push(format!("\"arg_count\": {}", chunk.code[offset + 2]));
+        },
+        OperandLayout::Jump => {
+            let target = jump_target(chunk, offset);
+            fields.push(format!("\"target\": {}", target));
+            if let Some(label) = labels.get(&target) {
+                fields.push(format!("\"label\": {}", json_string(label)));
+            }
+        },
+    }
+    return (format!("{{{}}}", fields.join(", ")), size);
+}
+
+// The same as the listing, for tools. Constants are referred to by index into "constants".
+pub fn disassemble_json(chunk: &Chunk, name: &str) -> String {
+    let labels = jump_labels(chunk);
+    let constants: Vec<String> = chunk.constants.iter().map(constant_json).collect();
+    let mut instructions = vec![];
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        let (json, size) = instruction_json(chunk, offset, &labels);
+        instructions.push(json);
+        offset += size;
+    }
+    let label_fields: Vec<String> = labels.iter().map(|(target, label)| format!("{}: {}", json_string(label), target)).collect();
+
+    let mut json = "{\n".to_string();
+    json += &format!("  \"name\": {},\n", json_string(name));
+    json += &format!("  \"constants\": [{}],\n", list(&constants));
+    json += &format!("  \"code\": [{}],\n", list(&instructions));
+    json += &format!("  \"labels\": {{{}}}\n", label_fields.join(", "));
+    json += "}\n";
+    return json;
+}
+
+fn list(items: &[String]) -> String {
+    if items.is_empty() {
+        return String::new();
+    }
+    return format!("\n    {}\n  ", items.join(",\n    "));
+}
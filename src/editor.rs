@@ -0,0 +1,313 @@
+use std::io::{BufRead, IsTerminal, Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+
+// A small line editor for the repl: arrow keys, home/end, history that's kept in a dotfile,
+// Ctrl-C to drop what's been typed and Ctrl-D to leave. The terminal is put in raw mode with
+// stty so there's nothing to fetch, and when stdin isn't a terminal (or there's no stty)
+// lines are just read as they come.
+
+pub enum ReadResult {
+    Line(String),
+    // Ctrl-C
+    Interrupted,
+    // Ctrl-D on an empty line, or stdin closed
+    Eof,
+}
+
+const MAX_HISTORY: usize = 1000;
+
+pub struct LineEditor {
+    history: Vec<String>,
+    history_path: Option<PathBuf>,
+}
+
+impl LineEditor {
+    pub fn new(history_path: Option<PathBuf>) -> LineEditor {
+        let mut history = vec![];
+        if let Some(path) = &history_path {
+            if let Ok(contents) = std::fs::read_to_string(path) {
+                history = contents.lines().map(|line| line.to_string()).collect();
+            }
+        }
+        let start = history.len().saturating_sub(MAX_HISTORY);
+        history.drain(..start);
+        return LineEditor { history: history, history_path: history_path };
+    }
+
+    pub fn add_history(&mut self, line: &str) {
+        if line.trim().is_empty() || self.history.last().is_some_and(|last| last == line) {
+            return;
+        }
+        self.history.push(line.to_string());
+        if let Some(path) = &self.history_path {
+            // losing history isn't worth stopping the repl over
+            if let Ok(mut file) = std::fs::OpenOptions::new().create(true).append(true).open(path) {
+                let _ = writeln!(file, "{}", line);
+            }
+        }
+    }
+
+    pub fn read_line(&mut self, prompt: &str) -> ReadResult {
+        print!("{}", prompt);
+        let _ = std::io::stdout().flush();
+        if std::io::stdin().is_terminal() {
+            if let Some(raw_mode) = RawMode::enable() {
+                let result = self.edit_line(prompt);
+                drop(raw_mode);
+                return result;
+            }
+        }
+        return read_plain_line();
+    }
+
+    fn edit_line(&mut self, prompt: &str) -> ReadResult {
+        let mut line: Vec<char> = vec![];
+        let mut cursor = 0;
+        // history.len() is the line being typed, saved so going down gets it back
+        let mut history_index = self.history.len();
+        let mut typed: Vec<char> = vec![];
+        let mut input = std::io::stdin().lock();
+
+        loop {
+            let key = match read_key(&mut input) {
+                Some(key) => key,
+                None => return ReadResult::Eof,
+            };
+            match key {
+                Key::Enter => {
+                    println!();
+                    return ReadResult::Line(line.iter().collect());
+                },
+                Key::Interrupt => {
+                    println!("^C");
+                    return ReadResult::Interrupted;
+                },
+                Key::EndOfInput => {
+                    if line.is_empty() {
+                        println!();
+                        return ReadResult::Eof;
+                    }
+                    if cursor < line.len() {
+                        line.remove(cursor);
+                    }
+                },
+                Key::Char(c) => {
+                    line.insert(cursor, c);
+                    cursor += 1;
+                },
+                Key::Backspace => {
+                    if cursor > 0 {
+                        cursor -= 1;
+                        line.remove(cursor);
+                    }
+                },
+                Key::Delete => {
+                    if cursor < line.len() {
+                        line.remove(cursor);
+                    }
+                },
+                Key::Left => cursor = cursor.saturating_sub(1),
+                Key::Right => cursor = (cursor + 1).min(line.len()),
+                Key::Home => cursor = 0,
+                Key::End => cursor = line.len(),
+                Key::KillToStart => {
+                    line.drain(..cursor);
+                    cursor = 0;
+                },
+                Key::KillToEnd => line.truncate(cursor),
+                Key::Up | Key::Down => {
+                    if history_index == self.history.len() {
+                        typed = line.clone();
+                    }
+                    if key == Key::Up && history_index > 0 {
+                        history_index -= 1;
+                    }
+                    else if key == Key::Down && history_index < self.history.len() {
+                        history_index += 1;
+                    }
+                    line = match self.history.get(history_index) {
+                        Some(entry) => entry.chars().collect(),
+                        None => typed.clone(),
+                    };
+                    cursor = line.len();
+                },
+                Key::Ignored => (),
+            }
+            redraw(prompt, &line, cursor);
+        }
+    }
+}
+
+fn read_plain_line() -> ReadResult {
+    let mut line = String::new();
+    match std::io::stdin().lock().read_line(&mut line) {
+        Ok(0) | Err(_) => {
+            println!();
+            return ReadResult::Eof;
+        },
+        Ok(_) => return ReadResult::Line(line.trim_end_matches(['\n', '\r']).to_string()),
+    }
+}
+
+fn redraw(prompt: &str, line: &[char], cursor: usize) {
+    let text: String = line.iter().collect();
+    // back to the start, rewrite everything, clear whatever was left over, then put the cursor back
+    print!("\r{}{}\x1b[K", prompt, text);
+    if cursor < line.len() {
+        print!("\x1b[{}D", line.len() - cursor);
+    }
+    let _ = std::io::stdout().flush();
+}
+
+
+#[derive(PartialEq)]
+enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    KillToStart,
+    KillToEnd,
+    Interrupt,
+    EndOfInput,
+    Ignored,
+}
+
+fn read_byte(input: &mut impl Read) -> Option<u8> {
+    let mut byte = [0u8];
+    match input.read(&mut byte) {
+        Ok(1) => return Some(byte[0]),
+        _ => return None,
+    }
+}
+
+fn read_key(input: &mut impl Read) -> Option<Key> {
+    let byte = read_byte(input)?;
+    let key = match byte {
+        b'\r' | b'\n' => Key::Enter,
+        0x7f | 0x08 => Key::Backspace,
+        0x01 => Key::Home,
+        0x05 => Key::End,
+        0x02 => Key::Left,
+        0x06 => Key::Right,
+        0x10 => Key::Up,
+        0x0e => Key::Down,
+        0x15 => Key::KillToStart,
+        0x0b => Key::KillToEnd,
+        0x03 => Key::Interrupt,
+        0x04 => Key::EndOfInput,
+        0x1b => read_escape(input)?,
+        _ if byte < 0x20 => Key::Ignored,
+        _ => {
+            // the rest of a utf-8 character
+            let length = if byte >= 0xf0 { 4 } else if byte >= 0xe0 { 3 } else if byte >= 0xc0 { 2 } else { 1 };
+            let mut bytes = vec![byte];
+            for _ in 1..length {
+                bytes.push(read_byte(input)?);
+            }
+            match std::str::from_utf8(&bytes).ok().and_then(|string| string.chars().next()) {
+                Some(c) => Key::Char(c),
+                None => Key::Ignored,
+            }
+        },
+    };
+    return Some(key);
+}
+
+// Arrow keys and friends come in as ESC [ something, or ESC O something on some terminals
+fn read_escape(input: &mut impl Read) -> Option<Key> {
+    let kind = read_byte(input)?;
+    if kind != b'[' && kind != b'O' {
+        return Some(Key::Ignored);
+    }
+    let key = match read_byte(input)? {
+        b'A' => Key::Up,
+        b'B' => Key::Down,
+        b'C' => Key::Right,
+        b'D' => Key::Left,
+        b'H' => Key::Home,
+        b'F' => Key::End,
+        number @ b'0'..=b'9' => {
+            // ESC [ 3 ~ and the like
+            let mut last = read_byte(input)?;
+            while last != b'~' && last.is_ascii_digit() {
+                last = read_byte(input)?;
+            }
+            match number {
+                b'1' | b'7' => Key::Home,
+                b'4' | b'8' => Key::End,
+                b'3' => Key::Delete,
+                _ => Key::Ignored,
+            }
+        },
+        _ => Key::Ignored,
+    };
+    return Some(key);
+}
+
+
+// Puts the terminal back how it was when dropped
+struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    fn enable() -> Option<RawMode> {
+        let saved = stty(&["-g"])?;
+        // no line buffering or echo, and Ctrl-C/Ctrl-D come through as keys
+        stty(&["-icanon", "-echo", "-isig", "min", "1", "time", "0"])?;
+        return Some(RawMode { saved: saved.trim().to_string() });
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = stty(&[&self.saved]);
+    }
+}
+
+fn stty(args: &[&str]) -> Option<String> {
+    let output = Command::new("stty").args(args).stdin(Stdio::inherit()).stderr(Stdio::null()).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    return Some(String::from_utf8_lossy(&output.stdout).to_string());
+}
+
+
+// Whether the repl should keep reading lines before running the entry, which it does while
+// there are unclosed parens or braces. Strings and comments don't count.
+pub fn needs_more_input(source: &str) -> bool {
+    let mut depth: i64 = 0;
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                for c in chars.by_ref() {
+                    if c == '"' {
+                        break;
+                    }
+                }
+            },
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            },
+            '(' | '{' => depth += 1,
+            ')' | '}' => depth -= 1,
+            _ => (),
+        }
+    }
+    return depth > 0;
+}
@@ -0,0 +1,147 @@
+use crate::chunk::Chunk;
+use crate::compiler::{CompileOptions, compile_with_options};
+use crate::scanner::{Token, TokenType, scan_with_comments};
+use crate::value::values_equal;
+
+
+// Lays scripts out one way, going by the tokens:
+//  - four spaces of indent for every brace the line is inside of
+//  - `{` ends the line it's on, `}` gets a line to itself except for `} else`
+//  - every ; ends a line, so a statement split over lines comes back together
+//  - a space either side of binary operators, none after unary ones or inside parens
+//  - comments stay where they were, at the end of a line or on one of their own
+//  - blank lines between statements are kept, but only one, and none just inside braces
+// Formatting something already formatted gives back the same thing.
+
+const INDENT: &str = "    ";
+
+// The formatted script. It has to compile, and afterwards it has to compile to the same code
+// and constants (only the lines can move), otherwise nothing's changed and this is an error.
+pub fn format_source(source: &str) -> Result<String, String> {
+    let before = compile_for_comparing(source).map_err(|error| format!("It has to compile before it can be formatted, {}", error))?;
+    let formatted = layout(&scan_with_comments(source));
+    let after = compile_for_comparing(&formatted).map_err(|error| format!("Formatting broke the script, {}", error))?;
+    if !same_program(&before, &after) {
+        return Err("Formatting would have changed what the script compiles to".to_string());
+    }
+    return Ok(formatted);
+}
+
+fn compile_for_comparing(source: &str) -> Result<Chunk, String> {
+    let mut options = CompileOptions::default();
+    options.set_optimization_level(0);
+    return compile_with_options(source, &options).map_err(|error| error.to_string());
+}
+
+fn same_program(before: &Chunk, after: &Chunk) -> bool {
+    return before.code == after.code
+        && before.constants.len() == after.constants.len()
+        && before.constants.iter().zip(&after.constants).all(|(a, b)| values_equal(a.clone(), b.clone()));
+}
+
+// Whether a - or ! after this token is binary, it is when there's a value to its left
+fn ends_value(token: Option<&Token>) -> bool {
+    return token.is_some_and(|token| matches!(token.token_type,
+        TokenType::Identifier | TokenType::String | TokenType::Number | TokenType::True | TokenType::False |
+        TokenType::Null | TokenType::This | TokenType::Super | TokenType::RightParen));
+}
+
+// Between two tokens on the same line
+fn space_between(previous: &Token, previous_unary: bool, next: &Token) -> bool {
+    if matches!(next.token_type, TokenType::Semicolon | TokenType::Comma | TokenType::RightParen) {
+        return false;
+    }
+    // 1 .x can't become 1.x, that's a different number
+    if next.token_type == TokenType::Dot {
+        return previous.token_type == TokenType::Number;
+    }
+    if matches!(previous.token_type, TokenType::LeftParen | TokenType::Dot) || previous_unary {
+        return false;
+    }
+    // a call
+    if next.token_type == TokenType::LeftParen && matches!(previous.token_type, TokenType::Identifier | TokenType::RightParen) {
+        return false;
+    }
+    return true;
+}
+
+fn layout(tokens: &[Token]) -> String {
+    let mut output = String::new();
+    let mut depth: usize = 0;
+    // there's something on the output line, and whether it's done with
+    let mut line_open = false;
+    let mut end_line = false;
+    // a comment cut a statement in two, the rest goes one indent further in
+    let mut continuation = false;
+    let mut previous: Option<&Token> = None;
+    let mut previous_end_line = 0;
+    let mut previous_code: Option<&Token> = None;
+    let mut previous_unary = false;
+
+    for token in tokens {
+        let in_statement = previous_code.is_some_and(|code| !matches!(code.token_type, TokenType::Semicolon | TokenType::LeftBrace | TokenType::RightBrace));
+        if token.token_type == TokenType::Comment {
+            continuation |= in_statement;
+            if previous.is_some() && token.line == previous_end_line && line_open {
+                output.push(' ');
+                output += token.data.trim_end();
+                end_line = true;
+                previous = Some(token);
+                continue;
+            }
+            end_line = true;
+        }
+        if token.token_type == TokenType::RightBrace {
+            depth = depth.saturating_sub(1);
+            end_line = true;
+        }
+        if token.token_type == TokenType::Else && previous.is_some_and(|previous| previous.token_type == TokenType::RightBrace) {
+            end_line = false;
+        }
+
+        if end_line && line_open {
+            output.push('\n');
+            line_open = false;
+            let after_open_brace = previous_code.is_some_and(|code| code.token_type == TokenType::LeftBrace) && previous.is_some_and(|previous| previous.token_type == TokenType::LeftBrace);
+            if token.line > previous_end_line + 1 && !after_open_brace && token.token_type != TokenType::RightBrace {
+                output.push('\n');
+            }
+        }
+        end_line = false;
+        if !line_open {
+            output += &INDENT.repeat(depth + continuation as usize);
+            line_open = true;
+        }
+        else if previous.is_some_and(|previous| space_between(previous, previous_unary, token)) {
+            output.push(' ');
+        }
+
+        if token.token_type == TokenType::Comment {
+            output += token.data.trim_end();
+            end_line = true;
+        }
+        else {
+            output += &token.data;
+            previous_unary = matches!(token.token_type, TokenType::Minus | TokenType::Bang) && !ends_value(previous_code);
+            match token.token_type {
+                TokenType::LeftBrace => {
+                    depth += 1;
+                    end_line = true;
+                    continuation = false;
+                },
+                TokenType::Semicolon | TokenType::RightBrace => {
+                    end_line = true;
+                    continuation = false;
+                },
+                _ => (),
+            }
+            previous_code = Some(token);
+        }
+        previous = Some(token);
+        previous_end_line = token.line + token.data.matches('\n').count() as i64;
+    }
+    if line_open {
+        output.push('\n');
+    }
+    return output;
+}
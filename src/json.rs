@@ -0,0 +1,334 @@
+// Just enough JSON for the tooling output and the editor protocols, written by hand so there's
+// nothing to fetch
+
+
+// A string as a JSON string literal, quotes included
+pub fn json_string(string: &str) -> String {
+    let mut escaped = String::with_capacity(string.len() + 2);
+    escaped.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            _ if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            _ => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    return escaped;
+}
+
+// JSON has no NaN or infinity, those become strings
+pub fn json_number(number: f64) -> String {
+    if number.is_finite() {
+        return format!("{}", number);
+    }
+    return json_string(&format!("{}", number));
+}
+
+
+// Parsed JSON, for the protocols that send it to us. Objects keep their fields in order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+static NULL: Json = Json::Null;
+
+impl Json {
+    pub fn object(fields: Vec<(&str, Json)>) -> Json {
+        return Json::Object(fields.into_iter().map(|(key, value)| (key.to_string(), value)).collect());
+    }
+
+    // Null when it isn't an object or doesn't have key, so lookups can be chained
+    pub fn get(&self, key: &str) -> &Json {
+        if let Json::Object(fields) = self {
+            for (field, value) in fields {
+                if field == key {
+                    return value;
+                }
+            }
+        }
+        return &NULL;
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(string) => return Some(string),
+            _ => return None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(number) => return Some(*number),
+            _ => return None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        return self.as_f64().filter(|number| number.fract() == 0.0).map(|number| number as i64);
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(boolean) => return Some(*boolean),
+            _ => return None,
+        }
+    }
+
+    // Empty when it isn't an array
+    pub fn as_array(&self) -> &[Json] {
+        match self {
+            Json::Array(items) => return items,
+            _ => return &[],
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        return *self == Json::Null;
+    }
+}
+
+impl From<bool> for Json {
+    fn from(boolean: bool) -> Json {
+        return Json::Bool(boolean);
+    }
+}
+
+impl From<f64> for Json {
+    fn from(number: f64) -> Json {
+        return Json::Number(number);
+    }
+}
+
+impl From<i64> for Json {
+    fn from(number: i64) -> Json {
+        return Json::Number(number as f64);
+    }
+}
+
+impl From<usize> for Json {
+    fn from(number: usize) -> Json {
+        return Json::Number(number as f64);
+    }
+}
+
+impl From<&str> for Json {
+    fn from(string: &str) -> Json {
+        return Json::String(string.to_string());
+    }
+}
+
+impl From<String> for Json {
+    fn from(string: String) -> Json {
+        return Json::String(string);
+    }
+}
+
+impl From<Vec<Json>> for Json {
+    fn from(items: Vec<Json>) -> Json {
+        return Json::Array(items);
+    }
+}
+
+// All on one line, the way it goes over the wire
+impl std::fmt::Display for Json {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Json::Null => return write!(f, "null"),
+            Json::Bool(boolean) => return write!(f, "{}", boolean),
+            Json::Number(number) => return write!(f, "{}", json_number(*number)),
+            Json::String(string) => return write!(f, "{}", json_string(string)),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (index, item) in items.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                return write!(f, "]");
+            },
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (index, (key, value)) in fields.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}:{}", json_string(key), value)?;
+                }
+                return write!(f, "}}");
+            },
+        }
+    }
+}
+
+pub fn parse_json(text: &str) -> Result<Json, String> {
+    let mut parser = JsonParser { chars: text.chars().collect(), current: 0 };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.current < parser.chars.len() {
+        return Err(format!("There's junk after the JSON at character {}", parser.current));
+    }
+    return Ok(value);
+}
+
+struct JsonParser {
+    chars: Vec<char>,
+    current: usize,
+}
+
+impl JsonParser {
+    fn skip_whitespace(&mut self) {
+        while self.current < self.chars.len() && self.chars[self.current].is_whitespace() {
+            self.current += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        return self.chars.get(self.current).copied();
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.peek() != Some(expected) {
+            return Err(format!("Expected {} at character {}", expected, self.current));
+        }
+        self.current += 1;
+        return Ok(());
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => return self.object(),
+            Some('[') => return self.array(),
+            Some('"') => return self.string().map(Json::String),
+            Some(c) if c == '-' || c.is_ascii_digit() => return self.number(),
+            Some(_) => {
+                for (word, value) in [("null", Json::Null), ("true", Json::Bool(true)), ("false", Json::Bool(false))] {
+                    let end = self.current + word.len();
+                    if end <= self.chars.len() && self.chars[self.current..end].iter().copied().eq(word.chars()) {
+                        self.current = end;
+                        return Ok(value);
+                    }
+                }
+                return Err(format!("Expected a value at character {}", self.current));
+            },
+            None => return Err("The JSON ends where a value should be".to_string()),
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.expect('{')?;
+        let mut fields = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.current += 1;
+            return Ok(Json::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('"') {
+                return Err(format!("Expected a key at character {}", self.current));
+            }
+            let key = self.string()?;
+            self.expect(':')?;
+            fields.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.current += 1,
+                Some('}') => {
+                    self.current += 1;
+                    return Ok(Json::Object(fields));
+                },
+                _ => return Err(format!("Expected , or }} at character {}", self.current)),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.expect('[')?;
+        let mut items = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.current += 1;
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.current += 1,
+                Some(']') => {
+                    self.current += 1;
+                    return Ok(Json::Array(items));
+                },
+                _ => return Err(format!("Expected , or ] at character {}", self.current)),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.current;
+        while self.peek().is_some_and(|c| c.is_ascii_digit() || "+-.eE".contains(c)) {
+            self.current += 1;
+        }
+        let text: String = self.chars[start..self.current].iter().collect();
+        return text.parse::<f64>().map(Json::Number).map_err(|_| format!("{} isn't a number", text));
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        if self.current + 4 > self.chars.len() {
+            return Err("A \\u escape is cut off".to_string());
+        }
+        let hex: String = self.chars[self.current..self.current + 4].iter().collect();
+        self.current += 4;
+        return u32::from_str_radix(&hex, 16).map_err(|_| format!("\\u{} isn't hex", hex));
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.current += 1;
+        let mut string = String::new();
+        loop {
+            let c = self.peek().ok_or("A string is missing its end quote")?;
+            self.current += 1;
+            match c {
+                '"' => return Ok(string),
+                '\\' => {
+                    let escape = self.peek().ok_or("A string is missing its end quote")?;
+                    self.current += 1;
+                    match escape {
+                        '"' | '\\' | '/' => string.push(escape),
+                        'b' => string.push('\u{8}'),
+                        'f' => string.push('\u{c}'),
+                        'n' => string.push('\n'),
+                        'r' => string.push('\r'),
+                        't' => string.push('\t'),
+                        'u' => {
+                            let mut code = self.hex4()?;
+                            // characters past the first 65536 come as a pair of surrogates
+                            if (0xd800..0xdc00).contains(&code) && self.chars.get(self.current..self.current + 2) == Some(&['\\', 'u']) {
+                                self.current += 2;
+                                let low = self.hex4()?;
+                                code = 0x10000 + ((code - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff);
+                            }
+                            string.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                        },
+                        other => return Err(format!("Unknown escape \\{}", other)),
+                    }
+                },
+                _ => string.push(c),
+            }
+        }
+    }
+}
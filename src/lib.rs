@@ -0,0 +1,89 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Everything the scanner, compiler and vm have to say about what they're doing goes through
+// trace!, it's silent unless the host (or `rlox --trace`) turns it on
+static TRACE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_trace(on: bool) {
+    TRACE.store(on, Ordering::Relaxed);
+}
+
+pub fn tracing() -> bool {
+    return TRACE.load(Ordering::Relaxed);
+}
+
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        if crate::tracing() {
+            println!($($arg)*);
+        }
+    };
+}
+
+pub mod assembler;
+pub mod cfg;
+pub mod chunk;
+pub mod compiler;
+pub mod convert;
+pub mod dap;
+pub mod debugger;
+pub mod disassembler;
+pub mod formatter;
+pub mod json;
+pub mod limits;
+pub mod linter;
+pub mod loxc;
+pub mod lsp;
+pub mod native;
+pub mod optimizer;
+pub mod output;
+pub mod protocol;
+pub mod register;
+pub mod scanner;
+pub mod stdlib;
+pub mod userdata;
+pub mod value;
+pub mod verify;
+pub mod vm;
+
+pub use assembler::assemble;
+pub use cfg::{basic_blocks, cfg_dot};
+pub use chunk::{Chunk, OpCode};
+pub use compiler::{Backend, CompileOptions, compile, compile_tokens, compile_with_options};
+pub use convert::{FromLox, IntoLox};
+pub use debugger::{Debugger, Step, Stopped};
+pub use disassembler::{disassemble, disassemble_chunk, disassemble_json};
+pub use formatter::format_source;
+pub use limits::{Limit, Limits};
+pub use linter::{Finding, LintConfig, Severity, lint};
+pub use loxc::{chunk_from_bytes, chunk_to_bytes};
+pub use native::{IntoNative, NativeFunction};
+pub use output::MemorySink;
+pub use stdlib::{Capability, Profile};
+pub use userdata::UserData;
+pub use value::{ObjData, Value, ValueKind, get_value_str, get_value_str_with_quotes, values_equal, values_greater, values_less};
+pub use verify::verify;
+pub use vm::{DebugHook, InterpretResult, VirtualMachine, Vm};
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoxError {
+    Compile { line: i64, message: String },
+    Runtime { line: i64, message: String },
+    Limit { line: i64, limit: Limit },
+    // a chunk that didn't pass verify(), offset is into its code
+    Invalid { offset: usize, message: String },
+}
+
+impl std::fmt::Display for LoxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LoxError::Compile { line, message } => write!(f, "[line {}] Compile error: {}", line, message),
+            LoxError::Runtime { line, message } => write!(f, "[line {}] Runtime error: {}", line, message),
+            LoxError::Limit { line, limit } => write!(f, "[line {}] Stopped, the script {}", line, limit),
+            LoxError::Invalid { offset, message } => write!(f, "[offset {}] Invalid bytecode: {}", offset, message),
+        }
+    }
+}
+
+impl std::error::Error for LoxError {}
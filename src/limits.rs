@@ -0,0 +1,47 @@
+use std::time::Duration;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    Fuel,
+    StackDepth,
+    Heap,
+    Time,
+}
+
+impl std::fmt::Display for Limit {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Limit::Fuel => write!(f, "ran out of fuel"),
+            Limit::StackDepth => write!(f, "the stack got too deep"),
+            Limit::Heap => write!(f, "used too much heap"),
+            Limit::Time => write!(f, "ran out of time"),
+        }
+    }
+}
+
+
+// Caps for running untrusted scripts, None means no cap. They're checked before every
+// instruction, so when one is hit the vm stops cleanly between two instructions and
+// VirtualMachine::resume carries on from there once the host has raised the limit (or topped
+// up the fuel, for time slicing). The register backend checks them before each of its own
+// instructions, there are fewer of those than stack ones so the same fuel goes further, and its
+// stack is its registers.
+#[derive(Debug, Clone, Default)]
+pub struct Limits {
+    // instructions left to run, counts down as the script runs
+    pub fuel: Option<u64>,
+    // values on the stack
+    pub max_stack: Option<usize>,
+    // bytes of objects the vm holds, in globals, on the stack and in the chunk's constants,
+    // whether they were made by this chunk, an earlier one or the host
+    pub max_heap_bytes: Option<usize>,
+    // wall clock time per interpret/run_chunk/resume call
+    pub time: Option<Duration>,
+}
+
+impl Limits {
+    pub fn any(&self) -> bool {
+        return self.fuel.is_some() || self.max_stack.is_some() || self.max_heap_bytes.is_some() || self.time.is_some();
+    }
+}
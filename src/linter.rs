@@ -0,0 +1,686 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::LoxError;
+use crate::compiler::{CompileOptions, compile};
+use crate::scanner::{Token, TokenType, scan_with_comments};
+use crate::stdlib::Profile;
+use crate::value::{ObjData, ValueKind};
+use crate::vm::Vm;
+
+
+// Finds the mistakes that compile fine but are still mistakes. The script gets parsed into a
+// small tree of its own, the grammar is the compiler's plus `!`, `and`, `or` and `return`, so a
+// script using them still gets the rest of what there is to say about it. The compiler's own
+// error comes along with that, as a syntax finding, since rlox won't run the script.
+//
+// Every rule can be set to off, warning or error in a config file:
+//     # comments start with a hash
+//     unused-variable = off
+//     self-comparison = error
+//     globals = player, score
+// (globals are the names the host defines, so reading them isn't a mistake)
+// and in comments in the script:
+//     // lint: allow unused-variable     this line, or the next one with code when it's on its own
+//     // lint: off self-comparison       from here to the end, warning and error work the same way
+// A directive without rules is about all of them.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Off,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn from_name(name: &str) -> Option<Severity> {
+        match name {
+            "off" => return Some(Severity::Off),
+            "warning" => return Some(Severity::Warning),
+            "error" => return Some(Severity::Error),
+            _ => return None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Off => return "off",
+            Severity::Warning => return "warning",
+            Severity::Error => return "error",
+        }
+    }
+}
+
+pub struct Rule {
+    pub id: &'static str,
+    pub severity: Severity,
+    pub summary: &'static str,
+}
+
+pub const RULES: &[Rule] = &[
+    Rule { id: "undefined-global", severity: Severity::Error, summary: "reading a global that nothing defines" },
+    Rule { id: "unused-variable", severity: Severity::Warning, summary: "a global that's assigned but never read" },
+    Rule { id: "self-comparison", severity: Severity::Warning, summary: "comparing something with itself" },
+    Rule { id: "constant-condition", severity: Severity::Warning, summary: "an if that goes the same way every time" },
+    Rule { id: "unreachable-code", severity: Severity::Warning, summary: "statements after a return" },
+    Rule { id: "dead-branch", severity: Severity::Warning, summary: "the branch an if with a literal condition never takes" },
+    Rule { id: "mixed-type-comparison", severity: Severity::Warning, summary: "== or != between literals of different types" },
+];
+
+// Not rules, these can't be turned off
+const SYNTAX: &str = "syntax";
+const LINT_COMMENT: &str = "lint-comment";
+
+fn rule(id: &str) -> Option<&'static Rule> {
+    return RULES.iter().find(|rule| rule.id == id);
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LintConfig {
+    severities: HashMap<&'static str, Severity>,
+    // defined by the host before the script runs
+    pub globals: Vec<String>,
+}
+
+impl LintConfig {
+    pub fn parse(text: &str) -> Result<LintConfig, String> {
+        let mut config = LintConfig::default();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => return Err(format!("line {}: expected `rule = off|warning|error`, got `{}`", index + 1, line)),
+            };
+            if key == "globals" {
+                config.globals.extend(value.split(',').map(str::trim).filter(|name| !name.is_empty()).map(String::from));
+                continue;
+            }
+            let severity = match Severity::from_name(value) {
+                Some(severity) => severity,
+                None => return Err(format!("line {}: expected off, warning or error for {}, got `{}`", index + 1, key, value)),
+            };
+            config.set(key, severity).map_err(|message| format!("line {}: {}", index + 1, message))?;
+        }
+        return Ok(config);
+    }
+
+    pub fn set(&mut self, id: &str, severity: Severity) -> Result<(), String> {
+        match rule(id) {
+            Some(rule) => {
+                self.severities.insert(rule.id, severity);
+                return Ok(());
+            },
+            None => return Err(format!("there's no rule called {}", id)),
+        }
+    }
+
+    pub fn severity(&self, id: &str) -> Severity {
+        if let Some(severity) = self.severities.get(id) {
+            return *severity;
+        }
+        return rule(id).map_or(Severity::Error, |rule| rule.severity);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub rule: &'static str,
+    pub severity: Severity,
+    pub line: i64,
+    pub column: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        return write!(f, "{}:{}: {}[{}]: {}", self.line, self.column, self.severity.name(), self.rule, self.message);
+    }
+}
+
+// Everything worth saying about the script, in the order it comes up. A script that doesn't
+// parse only gets the one finding saying where it stopped making sense.
+pub fn lint(source: &str, config: &LintConfig) -> Vec<Finding> {
+    let tokens = scan_with_comments(source);
+    if let Some(error) = tokens.iter().find(|token| token.token_type == TokenType::Error) {
+        return vec![syntax_error(error.line, error.column, &error.data)];
+    }
+    let code: Vec<&Token> = tokens.iter().filter(|token| token.token_type != TokenType::Comment).collect();
+    let statements = match (Parser { tokens: &code, index: 0 }).script() {
+        Ok(statements) => statements,
+        Err((line, column, message)) => return vec![syntax_error(line, column, &message)],
+    };
+
+    let mut findings = vec![];
+    if let Err(LoxError::Compile { line, message }) = compile(source) {
+        let column = tokens.iter().find(|token| token.line == line && token.token_type != TokenType::Comment).map_or(1, |token| token.column);
+        findings.push(syntax_error(line, column, &format!("rlox won't compile this: {}", message)));
+    }
+
+    let vm = Vm::with_profile(CompileOptions::default(), Profile::Full);
+    let mut known: HashSet<String> = vm.globals.iter().filter(|(_, value)| matches!(value.kind(), ValueKind::Obj(ObjData::Native(_)))).map(|(name, _)| name.clone()).collect();
+    known.extend(config.globals.iter().cloned());
+    let mut checker = Checker { reads: vec![], definitions: vec![], found: vec![] };
+    checker.statements(&statements);
+    checker.globals(&known);
+
+    let directives = Directives::new(&tokens);
+    findings.extend(directives.problems.iter().cloned());
+    for (rule, token, message) in checker.found {
+        if directives.allowed(rule, token.line) {
+            continue;
+        }
+        let severity = directives.severity(config, rule, token.line);
+        if severity != Severity::Off {
+            findings.push(Finding { rule: rule, severity: severity, line: token.line, column: token.column, message: message });
+        }
+    }
+    findings.sort_by_key(|finding| (finding.line, finding.column));
+    return findings;
+}
+
+fn syntax_error(line: i64, column: usize, message: &str) -> Finding {
+    return Finding { rule: SYNTAX, severity: Severity::Error, line: line, column: column, message: message.to_string() };
+}
+
+
+enum Expr {
+    // a number, string, true, false or null
+    Literal(Token),
+    Variable(Token),
+    Assign(Token, Box<Expr>),
+    Unary(Token, Box<Expr>),
+    // and, or, and everything else with an operator in the middle
+    Binary(Box<Expr>, Token, Box<Expr>),
+    Grouping(Box<Expr>),
+    Call(Box<Expr>, Vec<Expr>),
+    Method(Box<Expr>, Token, Vec<Expr>),
+}
+
+struct Statement {
+    // the first token of it
+    start: Token,
+    kind: StatementKind,
+}
+
+enum StatementKind {
+    Var(Token, Option<Expr>),
+    Print(Expr),
+    Expression(Expr),
+    If(Expr, Box<Statement>, Option<Box<Statement>>),
+    Block(Vec<Statement>),
+    Return(Option<Expr>),
+}
+
+// line, column, message
+type ParseError = (i64, usize, String);
+
+struct Parser<'a> {
+    tokens: &'a [&'a Token],
+    index: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a Token> {
+        return self.tokens.get(self.index).copied();
+    }
+
+    fn check(&self, token_type: TokenType) -> bool {
+        return self.peek().is_some_and(|token| token.token_type == token_type);
+    }
+
+    fn advance(&mut self) -> Option<&'a Token> {
+        let token = self.peek();
+        if token.is_some() {
+            self.index += 1;
+        }
+        return token;
+    }
+
+    fn advance_if(&mut self, token_types: &[TokenType]) -> Option<&'a Token> {
+        if self.peek().is_some_and(|token| token_types.contains(&token.token_type)) {
+            return self.advance();
+        }
+        return None;
+    }
+
+    // At the token that isn't what was expected, or just past the last one at the end
+    fn error(&self, message: &str) -> ParseError {
+        match (self.peek(), self.tokens.last()) {
+            (Some(token), _) => return (token.line, token.column, format!("{}, not `{}`", message, token.data)),
+            (None, Some(last)) => return (last.line, last.column + last.data.chars().count(), format!("{}, the script ended", message)),
+            (None, None) => return (1, 1, format!("{}, the script ended", message)),
+        }
+    }
+
+    fn consume(&mut self, token_type: TokenType, message: &str) -> Result<&'a Token, ParseError> {
+        if self.check(token_type) {
+            return Ok(self.advance().unwrap());
+        }
+        return Err(self.error(message));
+    }
+
+    fn script(&mut self) -> Result<Vec<Statement>, ParseError> {
+        let mut statements = vec![];
+        while self.peek().is_some() {
+            statements.push(self.declaration()?);
+        }
+        return Ok(statements);
+    }
+
+    fn declaration(&mut self) -> Result<Statement, ParseError> {
+        if let Some(start) = self.advance_if(&[TokenType::Var]) {
+            let name = self.consume(TokenType::Identifier, "Expected a variable name")?;
+            let value = if self.advance_if(&[TokenType::Equal]).is_some() { Some(self.expression()?) } else { None };
+            self.consume(TokenType::Semicolon, "Expected a semicolon")?;
+            return Ok(Statement { start: start.clone(), kind: StatementKind::Var(name.clone(), value) });
+        }
+        return self.statement();
+    }
+
+    fn statement(&mut self) -> Result<Statement, ParseError> {
+        let start = match self.advance_if(&[TokenType::Print, TokenType::If, TokenType::LeftBrace, TokenType::Return]) {
+            Some(start) => start,
+            None => {
+                let start = self.peek().cloned();
+                let expr = self.expression()?;
+                self.consume(TokenType::Semicolon, "Expected a semicolon")?;
+                return Ok(Statement { start: start.unwrap(), kind: StatementKind::Expression(expr) });
+            },
+        };
+        let kind = match start.token_type {
+            TokenType::Print => {
+                let expr = self.expression()?;
+                self.consume(TokenType::Semicolon, "Expected a semicolon")?;
+                StatementKind::Print(expr)
+            },
+            TokenType::If => {
+                let condition = self.expression()?;
+                let then = Box::new(self.statement()?);
+                let otherwise = if self.advance_if(&[TokenType::Else]).is_some() { Some(Box::new(self.statement()?)) } else { None };
+                StatementKind::If(condition, then, otherwise)
+            },
+            TokenType::LeftBrace => {
+                let mut statements = vec![];
+                while self.peek().is_some() && !self.check(TokenType::RightBrace) {
+                    statements.push(self.declaration()?);
+                }
+                self.consume(TokenType::RightBrace, "Expected a right brace to end the block")?;
+                StatementKind::Block(statements)
+            },
+            _ => {
+                let value = if self.check(TokenType::Semicolon) { None } else { Some(self.expression()?) };
+                self.consume(TokenType::Semicolon, "Expected a semicolon")?;
+                StatementKind::Return(value)
+            },
+        };
+        return Ok(Statement { start: start.clone(), kind: kind });
+    }
+
+    fn expression(&mut self) -> Result<Expr, ParseError> {
+        return self.binary(0);
+    }
+
+    // Operators from loosest to tightest, each level's operands are the next level
+    fn binary(&mut self, level: usize) -> Result<Expr, ParseError> {
+        const LEVELS: &[&[TokenType]] = &[
+            &[TokenType::Or],
+            &[TokenType::And],
+            &[TokenType::EqualEqual, TokenType::BangEqual],
+            &[TokenType::Less, TokenType::LessEqual, TokenType::Greater, TokenType::GreaterEqual],
+            &[TokenType::Plus, TokenType::Minus],
+            &[TokenType::Star, TokenType::Slash],
+        ];
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut expr = self.binary(level + 1)?;
+        while let Some(operator) = self.advance_if(LEVELS[level]) {
+            let right = self.binary(level + 1)?;
+            expr = Expr::Binary(Box::new(expr), operator.clone(), Box::new(right));
+        }
+        return Ok(expr);
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        if let Some(operator) = self.advance_if(&[TokenType::Minus, TokenType::Bang]) {
+            return Ok(Expr::Unary(operator.clone(), Box::new(self.unary()?)));
+        }
+        let mut expr = self.primary()?;
+        loop {
+            if self.advance_if(&[TokenType::LeftParen]).is_some() {
+                expr = Expr::Call(Box::new(expr), self.arguments()?);
+            }
+            else if self.advance_if(&[TokenType::Dot]).is_some() {
+                let name = self.consume(TokenType::Identifier, "Expected a method name after the dot")?;
+                self.consume(TokenType::LeftParen, "Expected a left parenthesis to call the method")?;
+                expr = Expr::Method(Box::new(expr), name.clone(), self.arguments()?);
+            }
+            else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn arguments(&mut self) -> Result<Vec<Expr>, ParseError> {
+        let mut arguments = vec![];
+        if self.advance_if(&[TokenType::RightParen]).is_some() {
+            return Ok(arguments);
+        }
+        loop {
+            arguments.push(self.expression()?);
+            if self.advance_if(&[TokenType::Comma]).is_none() {
+                break;
+            }
+        }
+        self.consume(TokenType::RightParen, "Expected a right parenthesis after the arguments")?;
+        return Ok(arguments);
+    }
+
+    fn primary(&mut self) -> Result<Expr, ParseError> {
+        let literals = [TokenType::Number, TokenType::String, TokenType::True, TokenType::False, TokenType::Null];
+        if let Some(literal) = self.advance_if(&literals) {
+            return Ok(Expr::Literal(literal.clone()));
+        }
+        // like the compiler, an assignment can go anywhere a variable can
+        if let Some(name) = self.advance_if(&[TokenType::Identifier]) {
+            if self.advance_if(&[TokenType::Equal]).is_some() {
+                return Ok(Expr::Assign(name.clone(), Box::new(self.expression()?)));
+            }
+            return Ok(Expr::Variable(name.clone()));
+        }
+        if self.advance_if(&[TokenType::LeftParen]).is_some() {
+            let expr = self.expression()?;
+            self.consume(TokenType::RightParen, "Expected a right parenthesis")?;
+            return Ok(Expr::Grouping(Box::new(expr)));
+        }
+        return Err(self.error("Expected an expression"));
+    }
+}
+
+
+// The script written back out, for messages
+fn describe(expr: &Expr) -> String {
+    let list = |arguments: &[Expr]| arguments.iter().map(describe).collect::<Vec<String>>().join(", ");
+    match expr {
+        Expr::Literal(token) | Expr::Variable(token) => return token.data.clone(),
+        Expr::Assign(name, value) => return format!("{} = {}", name.data, describe(value)),
+        Expr::Unary(operator, operand) => return format!("{}{}", operator.data, describe(operand)),
+        Expr::Binary(left, operator, right) => return format!("{} {} {}", describe(left), operator.data, describe(right)),
+        Expr::Grouping(inner) => return format!("({})", describe(inner)),
+        Expr::Call(callee, arguments) => return format!("{}({})", describe(callee), list(arguments)),
+        Expr::Method(object, name, arguments) => return format!("{}.{}({})", describe(object), name.data, list(arguments)),
+    }
+}
+
+// Written the same way and nothing in it can change anything, so it's the same value both times
+fn same_value(a: &Expr, b: &Expr) -> bool {
+    match (a, b) {
+        (Expr::Literal(a), Expr::Literal(b)) | (Expr::Variable(a), Expr::Variable(b)) => return a.token_type == b.token_type && a.data == b.data,
+        (Expr::Unary(a_operator, a), Expr::Unary(b_operator, b)) => return a_operator.token_type == b_operator.token_type && same_value(a, b),
+        (Expr::Binary(a_left, a_operator, a_right), Expr::Binary(b_left, b_operator, b_right)) => {
+            return a_operator.token_type == b_operator.token_type && same_value(a_left, b_left) && same_value(a_right, b_right);
+        },
+        (Expr::Grouping(a), Expr::Grouping(b)) => return same_value(a, b),
+        _ => return false,
+    }
+}
+
+// Made of literals and operators, so it's worked out the same every run
+fn is_constant(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal(_) => return true,
+        Expr::Unary(_, operand) | Expr::Grouping(operand) => return is_constant(operand),
+        Expr::Binary(left, _, right) => return is_constant(left) && is_constant(right),
+        _ => return false,
+    }
+}
+
+// What values_equal() goes by, None when it isn't a literal
+fn literal_type(expr: &Expr) -> Option<&'static str> {
+    match expr {
+        Expr::Literal(token) => match token.token_type {
+            TokenType::Number => return Some("a number"),
+            TokenType::String => return Some("a string"),
+            TokenType::True | TokenType::False => return Some("a bool"),
+            _ => return Some("null"),
+        },
+        Expr::Grouping(inner) => return literal_type(inner),
+        Expr::Unary(operator, operand) if operator.token_type == TokenType::Minus && literal_type(operand) == Some("a number") => return Some("a number"),
+        _ => return None,
+    }
+}
+
+// Whether the statement can't finish without returning, so nothing after it runs
+fn always_returns(statement: &Statement) -> bool {
+    match &statement.kind {
+        StatementKind::Return(_) => return true,
+        StatementKind::Block(statements) => return statements.iter().any(always_returns),
+        StatementKind::If(_, then, Some(otherwise)) => return always_returns(then) && always_returns(otherwise),
+        _ => return false,
+    }
+}
+
+// Which way an if on it goes, when that's plain from the literal itself
+fn literal_truth(expr: &Expr) -> Option<bool> {
+    match expr {
+        Expr::Literal(token) => return Some(!matches!(token.token_type, TokenType::False | TokenType::Null)),
+        Expr::Grouping(inner) => return literal_truth(inner),
+        _ => return None,
+    }
+}
+
+struct Checker {
+    // every variable that's read, and every var or assignment, in the order they're written
+    reads: Vec<Token>,
+    definitions: Vec<Token>,
+    found: Vec<(&'static str, Token, String)>,
+}
+
+impl Checker {
+    fn statements(&mut self, statements: &[Statement]) {
+        // only the first one's reported, what comes after it is part of the same mistake
+        let mut returned = false;
+        let mut reported = false;
+        for statement in statements {
+            if returned && !reported {
+                self.found.push(("unreachable-code", statement.start.clone(), "This never runs, there's a return before it".to_string()));
+                reported = true;
+            }
+            self.statement(statement);
+            returned |= always_returns(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match &statement.kind {
+            StatementKind::Var(name, value) => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+                self.definitions.push(name.clone());
+            },
+            StatementKind::Print(expr) | StatementKind::Expression(expr) | StatementKind::Return(Some(expr)) => self.expr(expr),
+            StatementKind::Return(None) => (),
+            StatementKind::If(condition, then, otherwise) => {
+                self.condition(condition, &statement.start);
+                self.expr(condition);
+                // only literals, `1 < 2` would have to be worked out to know which branch it is
+                let skipped = match literal_truth(condition) {
+                    Some(true) => otherwise.as_ref(),
+                    Some(false) => Some(then),
+                    None => None,
+                };
+                if let Some(skipped) = skipped {
+                    self.found.push(("dead-branch", skipped.start.clone(), format!("This never runs, `{}` always goes the other way", describe(condition))));
+                }
+                self.statements(std::slice::from_ref(then));
+                if let Some(otherwise) = otherwise {
+                    self.statements(std::slice::from_ref(otherwise));
+                }
+            },
+            StatementKind::Block(statements) => self.statements(statements),
+        }
+    }
+
+    fn condition(&mut self, condition: &Expr, start: &Token) {
+        if !is_constant(condition) {
+            return;
+        }
+        let mut inner = condition;
+        while let Expr::Grouping(grouped) = inner {
+            inner = grouped;
+        }
+        let message = match inner {
+            Expr::Literal(token) if matches!(token.token_type, TokenType::False | TokenType::Null) => format!("`{}` is always false, so the if never runs", describe(condition)),
+            Expr::Literal(_) => format!("`{}` is always true, so the if always runs", describe(condition)),
+            _ => format!("`{}` is only literals, so the if goes the same way every time", describe(condition)),
+        };
+        self.found.push(("constant-condition", start.clone(), message));
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Literal(_) => (),
+            Expr::Variable(name) => self.reads.push(name.clone()),
+            Expr::Assign(name, value) => {
+                self.expr(value);
+                self.definitions.push(name.clone());
+            },
+            Expr::Unary(_, operand) | Expr::Grouping(operand) => self.expr(operand),
+            Expr::Binary(left, operator, right) => {
+                self.comparison(left, operator, right);
+                self.expr(left);
+                self.expr(right);
+            },
+            Expr::Call(callee, arguments) => {
+                self.expr(callee);
+                for argument in arguments {
+                    self.expr(argument);
+                }
+            },
+            Expr::Method(object, _, arguments) => {
+                self.expr(object);
+                for argument in arguments {
+                    self.expr(argument);
+                }
+            },
+        }
+    }
+
+    fn comparison(&mut self, left: &Expr, operator: &Token, right: &Expr) {
+        let equality = matches!(operator.token_type, TokenType::EqualEqual | TokenType::BangEqual);
+        let ordering = matches!(operator.token_type, TokenType::Less | TokenType::LessEqual | TokenType::Greater | TokenType::GreaterEqual);
+        if !equality && !ordering {
+            return;
+        }
+        let written = format!("{} {} {}", describe(left), operator.data, describe(right));
+        if same_value(left, right) {
+            // which way it goes isn't said, NaN isn't equal to itself and strings can't be ordered
+            self.found.push(("self-comparison", operator.clone(), format!("`{}` compares `{}` with itself", written, describe(left))));
+            return;
+        }
+        if let (true, Some(left_type), Some(right_type)) = (equality, literal_type(left), literal_type(right)) {
+            if left_type != right_type {
+                let outcome = if operator.token_type == TokenType::EqualEqual { "false" } else { "true" };
+                self.found.push(("mixed-type-comparison", operator.clone(), format!("`{}` compares {} with {}, values of different types are never equal so it's always {}", written, left_type, right_type, outcome)));
+            }
+        }
+    }
+
+    // undefined-global and unused-variable, once everything's been seen
+    fn globals(&mut self, known: &HashSet<String>) {
+        let defined: HashSet<&str> = self.definitions.iter().map(|token| token.data.as_str()).collect();
+        let read: HashSet<&str> = self.reads.iter().map(|token| token.data.as_str()).collect();
+        for token in &self.reads {
+            if !defined.contains(token.data.as_str()) && !known.contains(&token.data) {
+                self.found.push(("undefined-global", token.clone(), format!("`{}` is read but never defined with var or assigned", token.data)));
+            }
+        }
+        let mut reported = HashSet::new();
+        for token in &self.definitions {
+            if !read.contains(token.data.as_str()) && reported.insert(token.data.as_str()) {
+                self.found.push(("unused-variable", token.clone(), format!("`{}` is assigned but never read", token.data)));
+            }
+        }
+    }
+}
+
+
+// The `// lint:` comments in a script
+struct Directives {
+    // (line, rule), no rule is all of them
+    allows: HashSet<(i64, Option<&'static str>)>,
+    // from this line on, in the order they're written
+    severities: Vec<(i64, Option<&'static str>, Severity)>,
+    problems: Vec<Finding>,
+}
+
+impl Directives {
+    fn new(tokens: &[Token]) -> Directives {
+        let mut directives = Directives { allows: HashSet::new(), severities: vec![], problems: vec![] };
+        let mut code_ended_on = 0;
+        for (index, token) in tokens.iter().enumerate() {
+            if token.token_type != TokenType::Comment {
+                code_ended_on = token.line + token.data.matches('\n').count() as i64;
+                continue;
+            }
+            let text = token.data.trim_start_matches('/').trim();
+            let text = match text.strip_prefix("lint:") {
+                Some(text) => text,
+                None => continue,
+            };
+            let mut words = text.split(|c: char| c.is_whitespace() || c == ',').filter(|word| !word.is_empty());
+            let command = words.next().unwrap_or("");
+            if command != "allow" && Severity::from_name(command).is_none() {
+                directives.problem(token, format!("Expected allow, off, warning or error after `lint:`, not `{}`", command));
+                continue;
+            }
+            let mut rules = vec![];
+            for word in words {
+                match rule(word) {
+                    Some(rule) => rules.push(Some(rule.id)),
+                    None => directives.problem(token, format!("There's no rule called {}", word)),
+                }
+            }
+            if rules.is_empty() {
+                rules.push(None);
+            }
+
+            if command == "allow" {
+                // a comment on a line of its own is about the next line with code on it
+                let line = if code_ended_on == token.line {
+                    Some(token.line)
+                } else {
+                    tokens[index + 1..].iter().find(|next| next.token_type != TokenType::Comment).map(|next| next.line)
+                };
+                if let Some(line) = line {
+                    directives.allows.extend(rules.into_iter().map(|rule| (line, rule)));
+                }
+            }
+            else if let Some(severity) = Severity::from_name(command) {
+                directives.severities.extend(rules.into_iter().map(|rule| (token.line, rule, severity)));
+            }
+        }
+        return directives;
+    }
+
+    fn problem(&mut self, token: &Token, message: String) {
+        self.problems.push(Finding { rule: LINT_COMMENT, severity: Severity::Warning, line: token.line, column: token.column, message: message });
+    }
+
+    fn allowed(&self, rule: &'static str, line: i64) -> bool {
+        return self.allows.contains(&(line, None)) || self.allows.contains(&(line, Some(rule)));
+    }
+
+    fn severity(&self, config: &LintConfig, rule: &'static str, line: i64) -> Severity {
+        let mut severity = config.severity(rule);
+        for (from, directive_rule, directive_severity) in &self.severities {
+            if *from <= line && directive_rule.is_none_or(|directive_rule| directive_rule == rule) {
+                severity = *directive_severity;
+            }
+        }
+        return severity;
+    }
+}
@@ -0,0 +1,152 @@
+use crate::chunk::Chunk;
+use crate::value::{ObjData, Value, ValueKind};
+
+
+// The .loxc format, a compiled chunk so a script doesn't have to be scanned and compiled every
+// time it runs. Everything is little endian:
+//     magic       b"LOXC"
+//     version     u16
+//     constants   u32 count, then each one as a tag byte and its data:
+//                     0 null, 1 false, 2 true, 3 number (f64), 4 string (u32 length, utf-8)
+//     code        u32 length, then the bytes
+//     lines       u32 count of runs, then each run as an i64 line and a u32 number of code bytes
+// Bump FORMAT_VERSION whenever any of that changes, old files get refused instead of misread.
+pub const MAGIC: &[u8; 4] = b"LOXC";
+pub const FORMAT_VERSION: u16 = 1;
+
+const TAG_NULL: u8 = 0;
+const TAG_FALSE: u8 = 1;
+const TAG_TRUE: u8 = 2;
+const TAG_NUMBER: u8 = 3;
+const TAG_STRING: u8 = 4;
+
+pub fn is_loxc(bytes: &[u8]) -> bool {
+    return bytes.starts_with(MAGIC);
+}
+
+// Only fails for constants there's no way to write down, natives and userdata. The compiler
+// never makes those, they only get in through chunks put together by hand.
+pub fn chunk_to_bytes(chunk: &Chunk) -> Result<Vec<u8>, String> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend(FORMAT_VERSION.to_le_bytes());
+
+    bytes.extend((chunk.constants.len() as u32).to_le_bytes());
+    for constant in &chunk.constants {
+        match constant.kind() {
+            ValueKind::Null => bytes.push(TAG_NULL),
+            ValueKind::Bool(false) => bytes.push(TAG_FALSE),
+            ValueKind::Bool(true) => bytes.push(TAG_TRUE),
+            ValueKind::Number(number) => {
+                bytes.push(TAG_NUMBER);
+                bytes.extend(number.to_le_bytes());
+            },
+            ValueKind::Obj(ObjData::String(string)) => {
+                let string: String = string.iter().collect();
+                bytes.push(TAG_STRING);
+                bytes.extend((string.len() as u32).to_le_bytes());
+                bytes.extend(string.as_bytes());
+            },
+            ValueKind::Obj(ObjData::Native(native)) => return Err(format!("Can't write the native function {} to a .loxc file", native.name)),
+            ValueKind::Obj(ObjData::UserData(data)) => return Err(format!("Can't write a {} to a .loxc file", data.type_name())),
+        }
+    }
+
+    bytes.extend((chunk.code.len() as u32).to_le_bytes());
+    bytes.extend(&chunk.code);
+
+    let mut runs: Vec<(i64, u32)> = vec![];
+    for line in &chunk.lines {
+        match runs.last_mut() {
+            Some((run_line, count)) if run_line == line => *count += 1,
+            _ => runs.push((*line, 1)),
+        }
+    }
+    bytes.extend((runs.len() as u32).to_le_bytes());
+    for (line, count) in runs {
+        bytes.extend(line.to_le_bytes());
+        bytes.extend(count.to_le_bytes());
+    }
+    return Ok(bytes);
+}
+
+pub fn chunk_from_bytes(bytes: &[u8]) -> Result<Chunk, String> {
+    let mut reader = Reader { bytes: bytes, index: 0 };
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err("Not a .loxc file, it doesn't start with LOXC".to_string());
+    }
+    let version = reader.u16()?;
+    if version != FORMAT_VERSION {
+        return Err(format!("This .loxc file is format version {}, but this rlox reads version {}", version, FORMAT_VERSION));
+    }
+
+    let mut chunk = Chunk::default();
+    let constant_count = reader.u32()?;
+    for _ in 0..constant_count {
+        let tag = reader.u8()?;
+        let constant = match tag {
+            TAG_NULL => Value::null(),
+            TAG_FALSE => Value::from_bool(false),
+            TAG_TRUE => Value::from_bool(true),
+            TAG_NUMBER => Value::from_number(f64::from_le_bytes(reader.array()?)),
+            TAG_STRING => {
+                let length = reader.u32()? as usize;
+                let string = std::str::from_utf8(reader.take(length)?).map_err(|_| "A string constant isn't valid utf-8".to_string())?;
+                Value::from_obj(ObjData::String(string.chars().collect()))
+            },
+            _ => return Err(format!("Unknown constant tag {}", tag)),
+        };
+        chunk.constants.push(constant);
+    }
+
+    let code_length = reader.u32()? as usize;
+    chunk.code = reader.take(code_length)?.to_vec();
+
+    let run_count = reader.u32()?;
+    for _ in 0..run_count {
+        let line = i64::from_le_bytes(reader.array()?);
+        let count = reader.u32()? as usize;
+        if chunk.lines.len() + count > chunk.code.len() {
+            return Err("The line table covers more bytes than there is code".to_string());
+        }
+        chunk.lines.extend(std::iter::repeat_n(line, count));
+    }
+    if chunk.lines.len() != chunk.code.len() {
+        return Err("The line table doesn't cover all of the code".to_string());
+    }
+    if reader.index != bytes.len() {
+        return Err("There's junk after the end of the chunk".to_string());
+    }
+    return Ok(chunk);
+}
+
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    index: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() - self.index < count {
+            return Err("The .loxc file ends partway through".to_string());
+        }
+        self.index += count;
+        return Ok(&self.bytes[self.index - count..self.index]);
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        return Ok(self.take(N)?.try_into().unwrap());
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        return Ok(self.take(1)?[0]);
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        return Ok(u16::from_le_bytes(self.array()?));
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        return Ok(u32::from_le_bytes(self.array()?));
+    }
+}
@@ -0,0 +1,416 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+use crate::compiler::{CompileOptions, compile_tokens};
+use crate::json::Json;
+use crate::protocol::{read_message, write_message};
+use crate::scanner::{Scanner, Token, TokenType};
+use crate::stdlib::Profile;
+use crate::value::{ObjData, ValueKind};
+use crate::vm::Vm;
+
+
+// A Language Server Protocol server. Documents are synced incrementally and only the tokens
+// around an edit get scanned again, the compiler then runs over the tokens for diagnostics.
+// Everything in Lox is a global declared with var, so hover, go to definition, references and
+// symbols all come from the tokens.
+
+const TOKEN_TYPES: &[&str] = &["keyword", "variable", "string", "number", "operator", "comment", "function"];
+const DECLARATION_MODIFIER: i64 = 1;
+
+// An open file, as chars so edits can be applied by index
+pub struct Document {
+    text: Vec<char>,
+    // where each line starts
+    line_starts: Vec<usize>,
+    // comments included, with where each one starts and ends in text
+    tokens: Vec<Token>,
+    spans: Vec<(usize, usize)>,
+}
+
+impl Document {
+    pub fn new(text: &str) -> Document {
+        let mut document = Document { text: vec![], line_starts: vec![0], tokens: vec![], spans: vec![] };
+        document.edit(0, 0, text);
+        return document;
+    }
+
+    pub fn text(&self) -> String {
+        return self.text.iter().collect();
+    }
+
+    pub fn tokens(&self) -> &[Token] {
+        return &self.tokens;
+    }
+
+    // indexes into text
+    pub fn line_starts(&self) -> &[usize] {
+        return &self.line_starts;
+    }
+
+    fn line_of(&self, index: usize) -> usize {
+        return self.line_starts.partition_point(|start| *start <= index) - 1;
+    }
+
+    // Replaces text[start..end] and scans again from the last token before the edit. Once the
+    // scan reaches a token past the edit, on a later line, that the old scan also started a token
+    // at, the rest is the same as before moved down, and gets reused.
+    pub fn edit(&mut self, start: usize, end: usize, new_text: &str) {
+        let inserted: Vec<char> = new_text.chars().collect();
+        let old_end_line = self.line_of(end) as i64 + 1;
+        let old_line_count = self.line_starts.len();
+        self.text.splice(start..end, inserted.iter().copied());
+        let delta = inserted.len() as isize - (end - start) as isize;
+        // lines starting inside the replaced text go, the inserted text's come in, and the ones
+        // after it move along
+        let first_replaced = self.line_starts.partition_point(|line_start| *line_start <= start);
+        let first_after = self.line_starts.partition_point(|line_start| *line_start <= end);
+        let after: Vec<usize> = self.line_starts.split_off(first_after).into_iter().map(|line_start| (line_start as isize + delta) as usize).collect();
+        self.line_starts.truncate(first_replaced);
+        self.line_starts.extend(inserted.iter().enumerate().filter(|(_, c)| **c == '\n').map(|(index, _)| start + index + 1));
+        self.line_starts.extend(after);
+        let line_delta = self.line_starts.len() as i64 - old_line_count as i64;
+
+        // a token that ends right where the edit starts could be part of what's typed
+        let keep = self.spans.partition_point(|(_, token_end)| *token_end < start);
+        let restart = if keep > 0 { self.spans[keep - 1].1 } else { 0 };
+        let old_tokens = self.tokens.split_off(keep);
+        let old_spans = self.spans.split_off(keep);
+        let line = self.line_of(restart);
+        let mut scanner = Scanner::starting_at(&self.text, restart, line as i64 + 1, self.line_starts[line]);
+        let edit_end = start + inserted.len();
+        while let Some(token) = scanner.next_token() {
+            let token_start = self.line_starts[token.line as usize - 1] + token.column - 1;
+            let token_end = scanner.position().0;
+            if token_start >= edit_end {
+                let old_start = (token_start as isize - delta) as usize;
+                if let Ok(same) = old_spans.binary_search_by_key(&old_start, |span| span.0) {
+                    if old_tokens[same].line > old_end_line {
+                        for (mut token, (token_start, token_end)) in old_tokens.into_iter().zip(old_spans).skip(same) {
+                            token.line += line_delta;
+                            self.tokens.push(token);
+                            self.spans.push(((token_start as isize + delta) as usize, (token_end as isize + delta) as usize));
+                        }
+                        return;
+                    }
+                }
+            }
+            self.tokens.push(token);
+            self.spans.push((token_start, token_end));
+        }
+    }
+
+    // LSP positions are a line and UTF-16 code units into it
+    fn index_of(&self, position: &Json) -> usize {
+        let line = position.get("line").as_i64().unwrap_or(0).max(0) as usize;
+        let Some(line_start) = self.line_starts.get(line).copied() else {
+            return self.text.len();
+        };
+        let mut units = position.get("character").as_i64().unwrap_or(0).max(0) as usize;
+        let mut index = line_start;
+        while index < self.text.len() && self.text[index] != '\n' && units >= self.text[index].len_utf16() {
+            units -= self.text[index].len_utf16();
+            index += 1;
+        }
+        return index;
+    }
+
+    fn position_of(&self, index: usize) -> Json {
+        let line = self.line_of(index);
+        let character: usize = self.text[self.line_starts[line]..index].iter().map(|c| c.len_utf16()).sum();
+        return Json::object(vec![("line", Json::from(line)), ("character", Json::from(character))]);
+    }
+
+    fn range(&self, (start, end): (usize, usize)) -> Json {
+        return Json::object(vec![("start", self.position_of(start)), ("end", self.position_of(end))]);
+    }
+
+    fn line_text(&self, line: usize) -> String {
+        let end = self.line_starts.get(line + 1).map(|start| start - 1).unwrap_or(self.text.len());
+        return self.text[self.line_starts[line]..end].iter().collect::<String>().trim().to_string();
+    }
+
+    // The identifier under the cursor, if there is one
+    fn identifier_at(&self, index: usize) -> Option<&Token> {
+        let found = self.spans.iter().position(|(start, end)| *start <= index && index <= *end)?;
+        return Some(&self.tokens[found]).filter(|token| token.token_type == TokenType::Identifier);
+    }
+
+    // Indexes of the tokens naming the global, properties after a . aren't globals
+    fn occurrences(&self, name: &str) -> Vec<usize> {
+        let mut found = vec![];
+        for (index, token) in self.tokens.iter().enumerate() {
+            let after_dot = index > 0 && self.tokens[index - 1].token_type == TokenType::Dot;
+            if token.token_type == TokenType::Identifier && token.data == name && !after_dot {
+                found.push(index);
+            }
+        }
+        return found;
+    }
+
+    fn is_declaration(&self, index: usize) -> bool {
+        return index > 0 && self.tokens[index - 1].token_type == TokenType::Var;
+    }
+}
+
+struct Server {
+    documents: HashMap<String, Document>,
+    options: CompileOptions,
+    // natives every script can see, for hover
+    natives: Vec<String>,
+    outbox: Vec<Json>,
+    shutting_down: bool,
+}
+
+// Serves until the exit notification or the end of input
+pub fn serve(input: &mut impl BufRead, output: &mut impl Write, options: &CompileOptions) -> Result<(), String> {
+    let vm = Vm::with_profile(*options, Profile::Full);
+    let mut natives: Vec<String> = vm.globals.iter().filter(|(_, value)| matches!(value.kind(), ValueKind::Obj(ObjData::Native(_)))).map(|(name, _)| name.clone()).collect();
+    natives.sort();
+    let mut server = Server { documents: HashMap::new(), options: *options, natives: natives, outbox: vec![], shutting_down: false };
+    while let Some(message) = read_message(input)? {
+        let keep_going = server.handle(&message);
+        for message in server.outbox.drain(..) {
+            write_message(output, &message).map_err(|error| format!("Couldn't write a message: {}", error))?;
+        }
+        if !keep_going {
+            break;
+        }
+    }
+    return Ok(());
+}
+
+impl Server {
+    fn notify(&mut self, method: &str, params: Json) {
+        self.outbox.push(Json::object(vec![("jsonrpc", Json::from("2.0")), ("method", Json::from(method)), ("params", params)]));
+    }
+
+    fn handle(&mut self, message: &Json) -> bool {
+        let method = message.get("method").as_str().unwrap_or("");
+        let params = message.get("params");
+        let id = message.get("id");
+        if self.shutting_down && method != "exit" {
+            if !id.is_null() {
+                let error = Json::object(vec![("code", Json::from(-32600_i64)), ("message", Json::from("The server is shutting down"))]);
+                self.outbox.push(Json::object(vec![("jsonrpc", Json::from("2.0")), ("id", id.clone()), ("error", error)]));
+            }
+            return true;
+        }
+        let result = match method {
+            "initialize" => Ok(capabilities()),
+            "shutdown" => {
+                self.shutting_down = true;
+                Ok(Json::Null)
+            },
+            "exit" => return false,
+            "textDocument/didOpen" => {
+                let document = params.get("textDocument");
+                self.documents.insert(uri(params), Document::new(document.get("text").as_str().unwrap_or("")));
+                self.publish_diagnostics(&uri(params));
+                Ok(Json::Null)
+            },
+            "textDocument/didChange" => {
+                if let Some(document) = self.documents.get_mut(&uri(params)) {
+                    for change in params.get("contentChanges").as_array() {
+                        let text = change.get("text").as_str().unwrap_or("");
+                        let range = change.get("range");
+                        if range.is_null() {
+                            document.edit(0, document.text.len(), text);
+                        }
+                        else {
+                            let (start, end) = (document.index_of(range.get("start")), document.index_of(range.get("end")));
+                            document.edit(start, end.max(start), text);
+                        }
+                    }
+                    self.publish_diagnostics(&uri(params));
+                }
+                Ok(Json::Null)
+            },
+            "textDocument/didClose" => {
+                self.documents.remove(&uri(params));
+                self.notify("textDocument/publishDiagnostics", Json::object(vec![("uri", Json::from(uri(params))), ("diagnostics", Json::Array(vec![]))]));
+                Ok(Json::Null)
+            },
+            "textDocument/semanticTokens/full" => self.with_document(params, |_, document| semantic_tokens(document)),
+            "textDocument/hover" => self.with_document(params, |server, document| server.hover(document, params.get("position"))),
+            "textDocument/definition" => self.with_document(params, |_, document| {
+                let locations = find(document, params.get("position"), |index| document.is_declaration(index));
+                Json::from(locations.into_iter().map(|span| location(params, document, span)).collect::<Vec<Json>>())
+            }),
+            "textDocument/references" => self.with_document(params, |_, document| {
+                let include_declaration = params.get("context").get("includeDeclaration").as_bool().unwrap_or(true);
+                let locations = find(document, params.get("position"), |index| include_declaration || !document.is_declaration(index));
+                Json::from(locations.into_iter().map(|span| location(params, document, span)).collect::<Vec<Json>>())
+            }),
+            "textDocument/documentSymbol" => self.with_document(params, |_, document| document_symbols(document)),
+            _ if id.is_null() => Ok(Json::Null),
+            _ => Err((-32601, format!("rlox doesn't do {}", method))),
+        };
+
+        // notifications don't get an answer
+        if id.is_null() {
+            return true;
+        }
+        let mut response = vec![("jsonrpc", Json::from("2.0")), ("id", id.clone())];
+        match result {
+            Ok(result) => response.push(("result", result)),
+            Err((code, message)) => response.push(("error", Json::object(vec![("code", Json::from(code)), ("message", Json::from(message))]))),
+        }
+        self.outbox.push(Json::object(response));
+        return true;
+    }
+
+    fn with_document(&self, params: &Json, answer: impl FnOnce(&Server, &Document) -> Json) -> Result<Json, (i64, String)> {
+        match self.documents.get(&uri(params)) {
+            Some(document) => return Ok(answer(self, document)),
+            None => return Err((-32602, format!("{} isn't open", uri(params)))),
+        }
+    }
+
+    // Every scanner error, or the compiler's first error on its line when the scan was clean
+    fn publish_diagnostics(&mut self, uri: &str) {
+        let document = &self.documents[uri];
+        let diagnostic = |range: Json, message: &str| {
+            return Json::object(vec![
+                ("range", range),
+                ("severity", Json::from(1_i64)),
+                ("source", Json::from("rlox")),
+                ("message", Json::from(message)),
+            ]);
+        };
+        let mut diagnostics = vec![];
+        for (token, span) in document.tokens.iter().zip(&document.spans) {
+            if token.token_type == TokenType::Error {
+                diagnostics.push(diagnostic(document.range(*span), &token.data));
+            }
+        }
+        if diagnostics.is_empty() {
+            if let Err(crate::LoxError::Compile { line, message }) = compile_tokens(&document.tokens, &self.options) {
+                let line = (line.max(1) as usize - 1).min(document.line_starts.len() - 1);
+                let start = document.line_starts[line];
+                let end = document.line_starts.get(line + 1).map(|start| start - 1).unwrap_or(document.text.len());
+                diagnostics.push(diagnostic(document.range((start, end)), &message));
+            }
+        }
+        self.notify("textDocument/publishDiagnostics", Json::object(vec![("uri", Json::from(uri)), ("diagnostics", Json::from(diagnostics))]));
+    }
+
+    // The line a global's declared on, or that it's built in
+    fn hover(&self, document: &Document, position: &Json) -> Json {
+        let Some(token) = document.identifier_at(document.index_of(position)) else {
+            return Json::Null;
+        };
+        let declaration = document.occurrences(&token.data).into_iter().find(|index| document.is_declaration(*index));
+        let contents = match declaration {
+            Some(index) => {
+                let line = document.line_of(document.spans[index].0);
+                format!("```lox\n{}\n```\ndeclared on line {}", document.line_text(line), line + 1)
+            },
+            None if self.natives.contains(&token.data) => format!("```lox\n{}\n```\nbuilt in native function", token.data),
+            None => format!("```lox\n{}\n```\nnever declared with var", token.data),
+        };
+        return Json::object(vec![("contents", Json::object(vec![("kind", Json::from("markdown")), ("value", Json::from(contents))]))]);
+    }
+}
+
+fn capabilities() -> Json {
+    let legend = Json::object(vec![
+        ("tokenTypes", Json::from(TOKEN_TYPES.iter().map(|name| Json::from(*name)).collect::<Vec<Json>>())),
+        ("tokenModifiers", Json::from(vec![Json::from("declaration")])),
+    ]);
+    let capabilities = Json::object(vec![
+        // incremental
+        ("textDocumentSync", Json::object(vec![("openClose", Json::from(true)), ("change", Json::from(2_i64))])),
+        ("hoverProvider", Json::from(true)),
+        ("definitionProvider", Json::from(true)),
+        ("referencesProvider", Json::from(true)),
+        ("documentSymbolProvider", Json::from(true)),
+        ("semanticTokensProvider", Json::object(vec![("legend", legend), ("full", Json::from(true))])),
+    ]);
+    return Json::object(vec![("capabilities", capabilities), ("serverInfo", Json::object(vec![("name", Json::from("rlox"))]))]);
+}
+
+fn uri(params: &Json) -> String {
+    return params.get("textDocument").get("uri").as_str().unwrap_or("").to_string();
+}
+
+fn location(params: &Json, document: &Document, span: (usize, usize)) -> Json {
+    return Json::object(vec![("uri", Json::from(uri(params))), ("range", document.range(span))]);
+}
+
+// Spans of the global under the cursor wherever it's named, for the occurrences that pass keep
+fn find(document: &Document, position: &Json, keep: impl Fn(usize) -> bool) -> Vec<(usize, usize)> {
+    let Some(token) = document.identifier_at(document.index_of(position)) else {
+        return vec![];
+    };
+    return document.occurrences(&token.data).into_iter().filter(|index| keep(*index)).map(|index| document.spans[index]).collect();
+}
+
+fn token_type_index(document: &Document, index: usize) -> Option<usize> {
+    let token = &document.tokens[index];
+    let name = match token.token_type {
+        TokenType::And | TokenType::Class | TokenType::Else | TokenType::False | TokenType::For | TokenType::Fun |
+        TokenType::If | TokenType::Null | TokenType::Or | TokenType::Print | TokenType::Return | TokenType::Super |
+        TokenType::This | TokenType::True | TokenType::Var | TokenType::While => "keyword",
+        // called right away, so a function
+        TokenType::Identifier if document.tokens.get(index + 1).is_some_and(|next| next.token_type == TokenType::LeftParen) => "function",
+        TokenType::Identifier => "variable",
+        TokenType::String => "string",
+        TokenType::Number => "number",
+        TokenType::Comment => "comment",
+        TokenType::Minus | TokenType::Plus | TokenType::Slash | TokenType::Star | TokenType::Bang | TokenType::BangEqual |
+        TokenType::Equal | TokenType::EqualEqual | TokenType::Greater | TokenType::GreaterEqual | TokenType::Less |
+        TokenType::LessEqual => "operator",
+        _ => return None,
+    };
+    return TOKEN_TYPES.iter().position(|known| *known == name);
+}
+
+// Five numbers a token: line and start relative to the token before, length, type, modifiers.
+// Tokens can't go over more than one line, so strings that do are split up.
+fn semantic_tokens(document: &Document) -> Json {
+    let mut data = vec![];
+    let (mut previous_line, mut previous_start) = (0, 0);
+    for index in 0..document.tokens.len() {
+        let Some(type_index) = token_type_index(document, index) else {
+            continue;
+        };
+        let modifiers = if document.is_declaration(index) { DECLARATION_MODIFIER } else { 0 };
+        let (start, end) = document.spans[index];
+        let mut piece_start = start;
+        while piece_start < end {
+            let line = document.line_of(piece_start);
+            let piece_end = document.line_starts.get(line + 1).map(|next| (next - 1).min(end)).unwrap_or(end);
+            let column: usize = document.text[document.line_starts[line]..piece_start].iter().map(|c| c.len_utf16()).sum();
+            let length: usize = document.text[piece_start..piece_end].iter().map(|c| c.len_utf16()).sum();
+            if length > 0 {
+                let relative_start = if line == previous_line { column - previous_start } else { column };
+                data.extend([line - previous_line, relative_start, length, type_index, modifiers as usize].map(Json::from));
+                (previous_line, previous_start) = (line, column);
+            }
+            piece_start = piece_end + 1;
+        }
+    }
+    return Json::object(vec![("data", Json::from(data))]);
+}
+
+// A variable for every var, the range is its line and the selection is the name
+fn document_symbols(document: &Document) -> Json {
+    let mut symbols = vec![];
+    for index in 0..document.tokens.len() {
+        if document.tokens[index].token_type != TokenType::Identifier || !document.is_declaration(index) {
+            continue;
+        }
+        let (start, end) = document.spans[index];
+        let line = document.line_of(start);
+        let line_end = document.line_starts.get(line + 1).map(|next| next - 1).unwrap_or(document.text.len());
+        symbols.push(Json::object(vec![
+            ("name", Json::from(document.tokens[index].data.as_str())),
+            // Variable
+            ("kind", Json::from(13_i64)),
+            ("range", document.range((document.spans[index - 1].0, line_end))),
+            ("selectionRange", document.range((start, end))),
+        ]));
+    }
+    return Json::from(symbols);
+}
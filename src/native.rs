@@ -0,0 +1,100 @@
+use std::rc::Rc;
+
+use crate::convert::{FromLox, IntoLox};
+use crate::value::Value;
+
+
+pub type NativeFn = dyn Fn(&[Value]) -> Result<Value, String>;
+
+// A Rust function scripts can call. The vm checks the argument count before calling, so
+// `function` always gets exactly `arity` arguments.
+#[derive(Clone)]
+pub struct NativeFunction {
+    pub name: String,
+    pub arity: usize,
+    pub function: Rc<NativeFn>,
+    // the vm flushes what's been printed before calling it, for natives that wait on or leave a
+    // mark outside the vm, like input() and exit()
+    pub flush_output: bool,
+}
+
+impl std::fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
+
+// Two natives are only equal if they are the same function
+impl PartialEq for NativeFunction {
+    fn eq(&self, other: &Self) -> bool {
+        return Rc::ptr_eq(&self.function, &other.function);
+    }
+}
+
+
+// What a native can return, anything IntoLox or a Result of one where Err is the message for
+// the runtime error
+pub trait NativeResult {
+    fn into_native_result(self) -> Result<Value, String>;
+}
+
+impl<T: IntoLox> NativeResult for T {
+    fn into_native_result(self) -> Result<Value, String> {
+        return Ok(self.into_lox());
+    }
+}
+
+impl<T: IntoLox> NativeResult for Result<T, String> {
+    fn into_native_result(self) -> Result<Value, String> {
+        return self.map(|x| x.into_lox());
+    }
+}
+
+
+// Anything that can be registered with VirtualMachine::register_native. Implemented for
+// closures taking up to 6 FromLox arguments and returning a NativeResult, Args is only there
+// so the impls don't overlap.
+pub trait IntoNative<Args> {
+    fn into_native(self, name: &str) -> NativeFunction;
+}
+
+macro_rules! count {
+    () => { 0usize };
+    ($head:ident $($tail:ident)*) => { 1usize + count!($($tail)*) };
+}
+
+macro_rules! impl_into_native {
+    ($($arg:ident),*) => {
+        impl<F, R, $($arg),*> IntoNative<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + 'static,
+            R: NativeResult,
+            $($arg: FromLox,)*
+        {
+            #[allow(non_snake_case, unused_variables, unused_mut, unused_assignments)]
+            fn into_native(self, name: &str) -> NativeFunction {
+                let function_name = name.to_string();
+                let function = move |args: &[Value]| -> Result<Value, String> {
+                    let mut index = 0;
+                    $(
+                        let $arg = match <$arg as FromLox>::from_lox(&args[index]) {
+                            Ok(x) => x,
+                            Err(message) => return Err(format!("{}() argument {}: {}", function_name, index + 1, message)),
+                        };
+                        index += 1;
+                    )*
+                    return self($($arg),*).into_native_result();
+                };
+                return NativeFunction { name: name.to_string(), arity: count!($($arg)*), function: Rc::new(function), flush_output: false };
+            }
+        }
+    };
+}
+
+impl_into_native!();
+impl_into_native!(A);
+impl_into_native!(A, B);
+impl_into_native!(A, B, C);
+impl_into_native!(A, B, C, D);
+impl_into_native!(A, B, C, D, E);
+impl_into_native!(A, B, C, D, E, G);
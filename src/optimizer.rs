@@ -0,0 +1,411 @@
+use std::collections::HashSet;
+
+use crate::chunk::{Chunk, OpCode, add_constant_dont_emit, instruction_size};
+use crate::value::{ObjData, Value, ValueKind, values_equal, values_greater, values_less};
+
+
+// What an instruction carries after its opcode. Constants hold the value itself and jumps
+// hold the absolute offset they land on, so passes can move code around and encode()
+// rebuilds the constant pool and the relative jump distances afterwards.
+#[derive(Clone)]
+enum Operand {
+    None,
+    Constant(Value),
+    Jump(usize),
+    // a plain number, like Call's argument count
+    Byte(u8),
+    // Invoke's method name and argument count
+    Invoke(Value, u8),
+}
+
+#[derive(Clone)]
+struct Instruction {
+    // offset in the chunk this instruction was decoded from, new instructions take the
+    // offset of the first instruction they replace
+    offset: usize,
+    opcode: u8,
+    operand: Operand,
+    line: i64,
+}
+
+
+fn decode(chunk: &Chunk) -> Vec<Instruction> {
+    let mut instructions = vec![];
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        let opcode = chunk.code[offset];
+        let size = instruction_size(opcode);
+        let operand = if is_jump(opcode) {
+            let jump = ((chunk.code[offset + 1] as usize) << 8) + chunk.code[offset + 2] as usize;
+            Operand::Jump(offset + size + jump)
+        }
+        else if opcode == OpCode::Call as u8 {
+            Operand::Byte(chunk.code[offset + 1])
+        }
+        else if opcode == OpCode::Invoke as u8 {
+            Operand::Invoke(chunk.constants[chunk.code[offset + 1] as usize].clone(), chunk.code[offset + 2])
+        }
+        else if size == 2 {
+            Operand::Constant(chunk.constants[chunk.code[offset + 1] as usize].clone())
+        }
+        else {
+            Operand::None
+        };
+        instructions.push(Instruction {
+            offset: offset,
+            opcode: opcode,
+            operand: operand,
+            line: chunk.lines.get(offset).copied().unwrap_or(0),
+        });
+        offset += size;
+    }
+    return instructions;
+}
+
+// None when the constants don't fit in the pool any more, the pass then leaves the chunk as it was
+fn encode(instructions: &[Instruction]) -> Option<Chunk> {
+    let mut new_offsets = vec![];
+    let mut end = 0;
+    for instruction in instructions {
+        new_offsets.push(end);
+        end += instruction_size(instruction.opcode);
+    }
+    // Jumps into code that got removed land on whatever instruction came after it
+    let resolve = |target: usize| -> usize {
+        let index = instructions.partition_point(|instruction| instruction.offset < target);
+        return if index < instructions.len() { new_offsets[index] } else { end };
+    };
+
+    let mut chunk: Chunk = Default::default();
+    for (instruction, new_offset) in instructions.iter().zip(&new_offsets) {
+        chunk.code.push(instruction.opcode);
+        match &instruction.operand {
+            Operand::None => (),
+            Operand::Byte(byte) => chunk.code.push(*byte),
+            Operand::Invoke(name, arg_count) => {
+                let index = add_constant_dont_emit(&mut chunk, name.clone()).ok()?;
+                chunk.code.push(index);
+                chunk.code.push(*arg_count);
+            },
+            Operand::Constant(value) => {
+                let index = add_constant_dont_emit(&mut chunk, value.clone()).ok()?;
+                chunk.code.push(index);
+            },
+            Operand::Jump(target) => {
+                let jump = resolve(*target) - (new_offset + instruction_size(instruction.opcode));
+                chunk.code.push(((jump >> 8) & 0xff) as u8);
+                chunk.code.push((jump & 0xff) as u8);
+            },
+        }
+        for _ in 0..instruction_size(instruction.opcode) {
+            chunk.lines.push(instruction.line);
+        }
+    }
+    return Some(chunk);
+}
+
+fn jump_targets(instructions: &[Instruction]) -> HashSet<usize> {
+    let mut targets = HashSet::new();
+    for instruction in instructions {
+        if let Operand::Jump(target) = instruction.operand {
+            targets.insert(target);
+        }
+    }
+    return targets;
+}
+
+
+fn literal_value(instruction: &Instruction) -> Option<Value> {
+    if instruction.opcode == OpCode::True as u8 {
+        return Some(Value::from_bool(true));
+    }
+    if instruction.opcode == OpCode::False as u8 {
+        return Some(Value::from_bool(false));
+    }
+    if instruction.opcode == OpCode::Null as u8 {
+        return Some(Value::null());
+    }
+    if instruction.opcode == OpCode::Constant as u8 {
+        if let Operand::Constant(value) = &instruction.operand {
+            return Some(value.clone());
+        }
+    }
+    return None;
+}
+
+fn literal_instruction(value: Value, offset: usize, line: i64) -> Instruction {
+    let (opcode, operand) = match value.kind() {
+        ValueKind::Bool(true) => (OpCode::True as u8, Operand::None),
+        ValueKind::Bool(false) => (OpCode::False as u8, Operand::None),
+        ValueKind::Null => (OpCode::Null as u8, Operand::None),
+        _ => (OpCode::Constant as u8, Operand::Constant(value)),
+    };
+    return Instruction { offset: offset, opcode: opcode, operand: operand, line: line };
+}
+
+// Mirrors what run() does for these opcodes. Anything that would error or panic at runtime
+// gives None so it stays in the bytecode and still fails when it runs.
+fn fold_unary(opcode: u8, value: Value) -> Option<Value> {
+    if opcode == OpCode::Not as u8 {
+        if let ValueKind::Bool(b) = value.kind() {
+            return Some(Value::from_bool(!b));
+        }
+    }
+    else if opcode == OpCode::Negate as u8 {
+        match value.kind() {
+            ValueKind::Bool(b) => return Some(Value::from_bool(!b)),
+            ValueKind::Number(num) => return Some(Value::from_number(-num)),
+            _ => (),
+        }
+    }
+    return None;
+}
+
+fn fold_binary(opcode: u8, left: Value, right: Value) -> Option<Value> {
+    if opcode == OpCode::Equal as u8 {
+        return Some(Value::from_bool(values_equal(left, right)));
+    }
+    if opcode == OpCode::Greater as u8 || opcode == OpCode::Less as u8 {
+        if let ValueKind::Obj(_) = left.kind() {
+            return None;
+        }
+        if let ValueKind::Obj(_) = right.kind() {
+            return None;
+        }
+        if opcode == OpCode::Greater as u8 {
+            return Some(Value::from_bool(values_greater(left, right)));
+        }
+        return Some(Value::from_bool(values_less(left, right)));
+    }
+
+    if let (ValueKind::Obj(ObjData::String(left_char_vec)), ValueKind::Obj(ObjData::String(right_char_vec))) = (left.kind(), right.kind()) {
+        if opcode == OpCode::Add as u8 {
+            let mut new_char_vec = left_char_vec.clone();
+            new_char_vec.extend(right_char_vec);
+            return Some(Value::from_obj(ObjData::String(new_char_vec)));
+        }
+        return None;
+    }
+    if let (ValueKind::Number(left_val), ValueKind::Number(right_val)) = (left.kind(), right.kind()) {
+        if opcode == OpCode::Add as u8 {
+            return Some(Value::from_number(left_val + right_val));
+        }
+        if opcode == OpCode::Subtract as u8 {
+            return Some(Value::from_number(left_val - right_val));
+        }
+        if opcode == OpCode::Multiply as u8 {
+            return Some(Value::from_number(left_val * right_val));
+        }
+        if opcode == OpCode::Divide as u8 {
+            return Some(Value::from_number(left_val / right_val));
+        }
+    }
+    return None;
+}
+
+fn is_binary_op(opcode: u8) -> bool {
+    return opcode == OpCode::Add as u8
+        || opcode == OpCode::Subtract as u8
+        || opcode == OpCode::Multiply as u8
+        || opcode == OpCode::Divide as u8
+        || opcode == OpCode::Equal as u8
+        || opcode == OpCode::Greater as u8
+        || opcode == OpCode::Less as u8;
+}
+
+// Looks at the instruction just pushed onto `folded` and, if it only operates on literals
+// sitting right before it, replaces the whole run with the result.
+fn try_fold_tail(folded: &mut Vec<Instruction>, targets: &HashSet<usize>) -> bool {
+    let count = folded.len();
+    let op = &folded[count - 1];
+    // something jumps straight to the operator, the operands might not be our literals
+    if targets.contains(&op.offset) {
+        return false;
+    }
+
+    if (op.opcode == OpCode::Not as u8 || op.opcode == OpCode::Negate as u8) && count >= 2 {
+        let operand = &folded[count - 2];
+        if let Some(value) = literal_value(operand) {
+            if let Some(result) = fold_unary(op.opcode, value) {
+                let replacement = literal_instruction(result, operand.offset, op.line);
+                folded.truncate(count - 2);
+                folded.push(replacement);
+                return true;
+            }
+        }
+    }
+    else if is_binary_op(op.opcode) && count >= 3 {
+        let left = &folded[count - 3];
+        let right = &folded[count - 2];
+        if targets.contains(&right.offset) {
+            return false;
+        }
+        if let (Some(left_value), Some(right_value)) = (literal_value(left), literal_value(right)) {
+            if let Some(result) = fold_binary(op.opcode, left_value, right_value) {
+                let replacement = literal_instruction(result, left.offset, op.line);
+                folded.truncate(count - 3);
+                folded.push(replacement);
+                return true;
+            }
+        }
+    }
+    return false;
+}
+
+pub fn fold_constants(chunk: &mut Chunk) {
+    let instructions = decode(chunk);
+    let targets = jump_targets(&instructions);
+
+    let mut folded: Vec<Instruction> = vec![];
+    for instruction in instructions {
+        folded.push(instruction);
+        try_fold_tail(&mut folded, &targets);
+    }
+    if let Some(encoded) = encode(&folded) {
+        *chunk = encoded;
+    }
+}
+
+
+fn is_jump(opcode: u8) -> bool {
+    return opcode == OpCode::JumpIfFalse as u8 || opcode == OpCode::Jump as u8;
+}
+
+// Pushes that have no side effects and can't fail, so pushing and then popping them is a no-op
+fn is_pure_push(opcode: u8) -> bool {
+    return opcode == OpCode::Constant as u8
+        || opcode == OpCode::True as u8
+        || opcode == OpCode::False as u8
+        || opcode == OpCode::Null as u8;
+}
+
+// Comparison followed by a Not, the replacement negates the same comparison so NaN still
+// behaves the way the two instruction version did
+fn fused_comparison(first: u8, second: u8) -> Option<u8> {
+    if second != OpCode::Not as u8 {
+        return None;
+    }
+    if first == OpCode::Equal as u8 {
+        return Some(OpCode::NotEqual as u8);
+    }
+    if first == OpCode::Less as u8 {
+        return Some(OpCode::GreaterEqual as u8);
+    }
+    if first == OpCode::Greater as u8 {
+        return Some(OpCode::LessEqual as u8);
+    }
+    return None;
+}
+
+// Any jump landing on an unconditional Jump can go straight to where that one goes
+fn thread_jumps(instructions: &mut [Instruction]) {
+    let mut jump_at = std::collections::HashMap::new();
+    for instruction in instructions.iter() {
+        if instruction.opcode == OpCode::Jump as u8 {
+            if let Operand::Jump(target) = instruction.operand {
+                jump_at.insert(instruction.offset, target);
+            }
+        }
+    }
+
+    for instruction in instructions.iter_mut() {
+        if let Operand::Jump(target) = &mut instruction.operand {
+            // jumps only go forward so this always ends, the bound is just to be safe
+            let mut hops = 0;
+            while let Some(next_target) = jump_at.get(target) {
+                if hops > jump_at.len() {
+                    break;
+                }
+                *target = *next_target;
+                hops += 1;
+            }
+        }
+    }
+}
+
+pub fn peephole(chunk: &mut Chunk) {
+    let mut instructions = decode(chunk);
+    thread_jumps(&mut instructions);
+    let targets = jump_targets(&instructions);
+
+    let mut optimized: Vec<Instruction> = vec![];
+    for instruction in instructions {
+        if let Some(previous) = optimized.last_mut() {
+            if !targets.contains(&instruction.offset) {
+                if let Some(fused) = fused_comparison(previous.opcode, instruction.opcode) {
+                    previous.opcode = fused;
+                    continue;
+                }
+                if instruction.opcode == OpCode::Pop as u8 && is_pure_push(previous.opcode) {
+                    optimized.pop();
+                    continue;
+                }
+            }
+        }
+        optimized.push(instruction);
+    }
+
+    // With dead code gone some jumps now land right after themselves. An unconditional one
+    // does nothing and a conditional one only has to get rid of its condition.
+    let mut index = 0;
+    while index < optimized.len() {
+        let instruction = &optimized[index];
+        if let Operand::Jump(target) = instruction.operand {
+            let next_offset = optimized.get(index + 1).map(|next| next.offset).unwrap_or(usize::MAX);
+            if is_jump(instruction.opcode) && next_offset >= target {
+                if instruction.opcode == OpCode::Jump as u8 {
+                    optimized.remove(index);
+                    continue;
+                }
+                optimized[index].opcode = OpCode::Pop as u8;
+                optimized[index].operand = Operand::None;
+            }
+        }
+        index += 1;
+    }
+    if let Some(encoded) = encode(&optimized) {
+        *chunk = encoded;
+    }
+}
+
+
+// Picked from `--profile-pairs` over benches/workload.lox, these were the most common pairs
+// that can be merged without changing what ends up on the stack.
+fn superinstruction(first: u8, second: u8) -> Option<u8> {
+    if first == OpCode::Constant as u8 && second == OpCode::Add as u8 {
+        return Some(OpCode::ConstantAdd as u8);
+    }
+    if first == OpCode::Constant as u8 && second == OpCode::Print as u8 {
+        return Some(OpCode::ConstantPrint as u8);
+    }
+    if first == OpCode::GetGlobal as u8 && second == OpCode::Print as u8 {
+        return Some(OpCode::GetGlobalPrint as u8);
+    }
+    if first == OpCode::SetGlobal as u8 && second == OpCode::Pop as u8 {
+        return Some(OpCode::SetGlobalPop as u8);
+    }
+    return None;
+}
+
+// Every superinstruction keeps the operand of its first half, so fusing is just swapping
+// that opcode and dropping the second one
+pub fn fuse_superinstructions(chunk: &mut Chunk) {
+    let instructions = decode(chunk);
+    let targets = jump_targets(&instructions);
+
+    let mut fused: Vec<Instruction> = vec![];
+    for instruction in instructions {
+        if let Some(previous) = fused.last_mut() {
+            if !targets.contains(&instruction.offset) {
+                if let Some(opcode) = superinstruction(previous.opcode, instruction.opcode) {
+                    previous.opcode = opcode;
+                    continue;
+                }
+            }
+        }
+        fused.push(instruction);
+    }
+    if let Some(encoded) = encode(&fused) {
+        *chunk = encoded;
+    }
+}
@@ -0,0 +1,58 @@
+use std::cell::RefCell;
+use std::io::{IsTerminal, Write};
+use std::rc::Rc;
+
+
+// Where a vm writes what scripts print and the errors that stop them. Anything that implements
+// Write works, these are the usual ones.
+pub type Sink = Box<dyn Write>;
+
+// Line buffered on a terminal so people see each print as it happens, otherwise buffered in
+// full and flushed whenever the vm stops or is about to wait on something
+pub fn stdout_sink() -> Sink {
+    if std::io::stdout().is_terminal() {
+        return Box::new(std::io::LineWriter::new(std::io::stdout()));
+    }
+    return Box::new(std::io::BufWriter::new(std::io::stdout()));
+}
+
+pub fn stderr_sink() -> Sink {
+    return Box::new(std::io::stderr());
+}
+
+
+// Keeps everything written to it in memory, clone it before handing it to the vm and read it
+// back through the clone:
+//     let output = MemorySink::new();
+//     vm.set_output(output.clone());
+//     vm.interpret("print 1;");
+//     assert_eq!(output.contents(), "1\n");
+#[derive(Clone, Default)]
+pub struct MemorySink {
+    buffer: Rc<RefCell<Vec<u8>>>,
+}
+
+impl MemorySink {
+    pub fn new() -> MemorySink {
+        return Default::default();
+    }
+
+    pub fn contents(&self) -> String {
+        return String::from_utf8_lossy(&self.buffer.borrow()).to_string();
+    }
+
+    pub fn clear(&self) {
+        self.buffer.borrow_mut().clear();
+    }
+}
+
+impl Write for MemorySink {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buffer.borrow_mut().extend_from_slice(buf);
+        return Ok(buf.len());
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        return Ok(());
+    }
+}
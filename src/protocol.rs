@@ -0,0 +1,45 @@
+use std::io::{BufRead, Write};
+
+use crate::json::{Json, parse_json};
+
+
+// DAP and LSP both send JSON with headers in front, like HTTP:
+//     Content-Length: 52\r\n
+//     \r\n
+//     {"seq":1,"type":"request","command":"initialize"}
+
+// The next message, None when the other end has closed the stream
+pub fn read_message(input: &mut impl BufRead) -> Result<Option<Json>, String> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        let read = input.read_line(&mut header).map_err(|error| format!("Couldn't read a header: {}", error))?;
+        if read == 0 {
+            if length.is_none() {
+                return Ok(None);
+            }
+            return Err("The stream ended in the middle of the headers".to_string());
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        // Content-Type is the only other one, and it's always utf-8 JSON
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("Content-Length") {
+                length = Some(value.trim().parse::<usize>().map_err(|_| format!("Content-Length {} isn't a number", value.trim()))?);
+            }
+        }
+    }
+    let length = length.ok_or("A message didn't have a Content-Length")?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body).map_err(|error| format!("Couldn't read a message: {}", error))?;
+    let body = String::from_utf8(body).map_err(|_| "A message wasn't utf-8".to_string())?;
+    return parse_json(&body).map(Some);
+}
+
+pub fn write_message(output: &mut impl Write, message: &Json) -> std::io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    return output.flush();
+}
@@ -0,0 +1,481 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+use crate::LoxError;
+use crate::limits::{Limit, Limits};
+use crate::chunk::{Chunk, OpCode, instruction_size, jump_target, opcode_name, same_constant};
+use crate::value::{ObjData, Value, ValueKind, get_value_str, get_value_str_with_quotes};
+use crate::output::{Sink, stdout_sink};
+use crate::vm::{CLOCK_INTERVAL, InterpretResult, binary_op, call_value, distinct_heap_bytes, heap_size, invoke_value, is_falsey, print_value, unary_op};
+
+
+// Experimental register machine. The parser still produces a stack Chunk, compile_chunk() then
+// walks it keeping track of what is in every stack slot and turns it into register
+// instructions. Stack slot n always lives in register n, and literals don't get loaded at all,
+// the instructions that use them read straight from the constant pool instead. Registers and
+// constants aren't bytes here, so nothing that fits on the stack is too big for them.
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operand {
+    Register(usize),
+    Constant(usize),
+}
+
+#[derive(Clone, Debug)]
+pub enum RegisterOp {
+    Load { dest: usize, source: Operand },
+    GetGlobal { dest: usize, name: u8 },
+    SetGlobal { name: u8, source: Operand },
+    DefineGlobal { name: u8, source: Operand },
+    // `op` is the stack OpCode the instruction came from, it picks what unary_op/binary_op do
+    Unary { op: u8, dest: usize, source: Operand },
+    Binary { op: u8, dest: usize, left: Operand, right: Operand },
+    Print { source: Operand },
+    Return { source: Operand },
+    // the callee is in `dest` and the arguments in the registers right after it
+    Call { dest: usize, arg_count: u8 },
+    // same layout as Call with the receiver in `dest`
+    Invoke { dest: usize, name: u8, arg_count: u8 },
+    Jump { target: usize },
+    JumpIfFalse { condition: Operand, target: usize },
+}
+
+#[derive(Default)]
+pub struct RegisterChunk {
+    pub code: Vec<RegisterOp>,
+    pub lines: Vec<i64>,
+    pub constants: Vec<Value>,
+    pub register_count: usize,
+}
+
+
+struct Translator {
+    chunk: RegisterChunk,
+    // what every stack slot currently holds, slot n is register n once it's in a register
+    slots: Vec<Operand>,
+    literal_constants: HashMap<u8, usize>,
+    // register instruction index of each stack instruction, to resolve jumps afterwards
+    offsets: HashMap<usize, usize>,
+    line: i64,
+}
+
+impl Translator {
+    fn emit(&mut self, op: RegisterOp) {
+        self.chunk.code.push(op);
+        self.chunk.lines.push(self.line);
+    }
+
+    fn push(&mut self, operand: Operand) {
+        self.slots.push(operand);
+        self.chunk.register_count = self.chunk.register_count.max(self.slots.len());
+    }
+
+    fn pop(&mut self) -> Operand {
+        return self.slots.pop().expect("Register translation popped an empty stack");
+    }
+
+    fn next_register(&self) -> usize {
+        return self.slots.len();
+    }
+
+    // True/False/Null become constants too, so every literal can be an operand
+    fn literal(&mut self, opcode: u8) -> Operand {
+        if let Some(constant) = self.literal_constants.get(&opcode) {
+            return Operand::Constant(*constant);
+        }
+        let value = if opcode == OpCode::True as u8 {
+            Value::from_bool(true)
+        }
+        else if opcode == OpCode::False as u8 {
+            Value::from_bool(false)
+        }
+        else {
+            Value::null()
+        };
+        // the pool can already have one, the optimizer folds conditions into constants
+        let constant = match self.chunk.constants.iter().position(|existing| same_constant(existing, &value)) {
+            Some(constant) => constant,
+            None => {
+                self.chunk.constants.push(value);
+                self.chunk.constants.len() - 1
+            },
+        };
+        self.literal_constants.insert(opcode, constant);
+        return Operand::Constant(constant);
+    }
+
+    // Puts every slot in its own register. Done at every jump and jump target so both ways of
+    // getting to an instruction agree on where everything is.
+    fn spill(&mut self) {
+        for slot in 0..self.slots.len() {
+            if let Operand::Constant(_) = self.slots[slot] {
+                let source = self.slots[slot];
+                self.emit(RegisterOp::Load { dest: slot, source: source });
+                self.slots[slot] = Operand::Register(slot);
+            }
+        }
+    }
+}
+
+
+pub fn compile_chunk(chunk: &Chunk) -> RegisterChunk {
+    let mut translator = Translator {
+        chunk: RegisterChunk { constants: chunk.constants.clone(), ..Default::default() },
+        slots: vec![],
+        literal_constants: HashMap::new(),
+        offsets: HashMap::new(),
+        line: 0,
+    };
+
+    let mut jump_targets = std::collections::HashSet::new();
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        let instruction = chunk.code[offset];
+        if instruction == OpCode::Jump as u8 || instruction == OpCode::JumpIfFalse as u8 {
+            jump_targets.insert(jump_target(chunk, offset));
+        }
+        offset += instruction_size(instruction);
+    }
+
+    // jumps are patched once everything has an index, they hold stack offsets until then
+    let mut jumps = vec![];
+    offset = 0;
+    while offset < chunk.code.len() {
+        if jump_targets.contains(&offset) {
+            translator.spill();
+        }
+        translator.offsets.insert(offset, translator.chunk.code.len());
+        translator.line = chunk.lines.get(offset).copied().unwrap_or(0);
+
+        let instruction = chunk.code[offset];
+        let operand = chunk.code.get(offset + 1).copied().unwrap_or(0);
+        if instruction == OpCode::Constant as u8 {
+            translator.push(Operand::Constant(operand as usize));
+        }
+        else if instruction == OpCode::True as u8 || instruction == OpCode::False as u8 || instruction == OpCode::Null as u8 {
+            let literal = translator.literal(instruction);
+            translator.push(literal);
+        }
+        else if instruction == OpCode::Pop as u8 {
+            translator.pop();
+        }
+        else if instruction == OpCode::GetGlobal as u8 {
+            let dest = translator.next_register();
+            translator.emit(RegisterOp::GetGlobal { dest: dest, name: operand });
+            translator.push(Operand::Register(dest));
+        }
+        else if instruction == OpCode::SetGlobal as u8 {
+            // assignment is an expression, the value stays where it was
+            let source = translator.pop();
+            translator.emit(RegisterOp::SetGlobal { name: operand, source: source });
+            translator.push(source);
+        }
+        else if instruction == OpCode::DefineGlobal as u8 {
+            let source = translator.pop();
+            translator.emit(RegisterOp::DefineGlobal { name: operand, source: source });
+        }
+        else if instruction == OpCode::Print as u8 {
+            let source = translator.pop();
+            translator.emit(RegisterOp::Print { source: source });
+        }
+        else if instruction == OpCode::Return as u8 {
+            let source = translator.pop();
+            translator.emit(RegisterOp::Return { source: source });
+        }
+        else if instruction == OpCode::Not as u8 || instruction == OpCode::Negate as u8 {
+            let source = translator.pop();
+            let dest = translator.next_register();
+            translator.emit(RegisterOp::Unary { op: instruction, dest: dest, source: source });
+            translator.push(Operand::Register(dest));
+        }
+        // superinstructions just get split back up
+        else if instruction == OpCode::ConstantAdd as u8 {
+            let left = translator.pop();
+            let dest = translator.next_register();
+            translator.emit(RegisterOp::Binary { op: OpCode::Add as u8, dest: dest, left: left, right: Operand::Constant(operand as usize) });
+            translator.push(Operand::Register(dest));
+        }
+        else if instruction == OpCode::ConstantPrint as u8 {
+            translator.emit(RegisterOp::Print { source: Operand::Constant(operand as usize) });
+        }
+        else if instruction == OpCode::GetGlobalPrint as u8 {
+            let dest = translator.next_register();
+            translator.chunk.register_count = translator.chunk.register_count.max(dest + 1);
+            translator.emit(RegisterOp::GetGlobal { dest: dest, name: operand });
+            translator.emit(RegisterOp::Print { source: Operand::Register(dest) });
+        }
+        else if instruction == OpCode::SetGlobalPop as u8 {
+            let source = translator.pop();
+            translator.emit(RegisterOp::SetGlobal { name: operand, source: source });
+        }
+        else if instruction == OpCode::Call as u8 {
+            // every slot in its own register puts the callee and arguments next to each other
+            translator.spill();
+            for _ in 0..operand {
+                translator.pop();
+            }
+            translator.pop();
+            let dest = translator.next_register();
+            translator.emit(RegisterOp::Call { dest: dest, arg_count: operand });
+            translator.push(Operand::Register(dest));
+        }
+        else if instruction == OpCode::Invoke as u8 {
+            let arg_count = chunk.code[offset + 2];
+            translator.spill();
+            for _ in 0..arg_count {
+                translator.pop();
+            }
+            translator.pop();
+            let dest = translator.next_register();
+            translator.emit(RegisterOp::Invoke { dest: dest, name: operand, arg_count: arg_count });
+            translator.push(Operand::Register(dest));
+        }
+        else if instruction == OpCode::Jump as u8 {
+            translator.spill();
+            jumps.push((translator.chunk.code.len(), jump_target(chunk, offset)));
+            translator.emit(RegisterOp::Jump { target: 0 });
+        }
+        else if instruction == OpCode::JumpIfFalse as u8 {
+            let condition = translator.pop();
+            translator.spill();
+            jumps.push((translator.chunk.code.len(), jump_target(chunk, offset)));
+            translator.emit(RegisterOp::JumpIfFalse { condition: condition, target: 0 });
+        }
+        else {
+            // Assuming its a binary operation
+            let right = translator.pop();
+            let left = translator.pop();
+            let dest = translator.next_register();
+            translator.emit(RegisterOp::Binary { op: instruction, dest: dest, left: left, right: right });
+            translator.push(Operand::Register(dest));
+        }
+        offset += instruction_size(instruction);
+    }
+    translator.offsets.insert(offset, translator.chunk.code.len());
+
+    for (index, stack_target) in jumps {
+        let new_target = translator.offsets[&stack_target];
+        match &mut translator.chunk.code[index] {
+            RegisterOp::Jump { target } => *target = new_target,
+            RegisterOp::JumpIfFalse { target, .. } => *target = new_target,
+            _ => (),
+        }
+    }
+    return translator.chunk;
+}
+
+
+fn operand_str(chunk: &RegisterChunk, operand: Operand) -> String {
+    match operand {
+        Operand::Register(register) => format!("r{}", register),
+        Operand::Constant(constant) => format!("k{}({})", constant, get_value_str_with_quotes(&chunk.constants[constant])),
+    }
+}
+
+fn name_str(chunk: &RegisterChunk, name: u8) -> String {
+    return get_value_str(&chunk.constants[name as usize]);
+}
+
+pub fn disassemble_register_chunk(chunk: &RegisterChunk, name: &str) {
+    println!("=== {} ({} registers) ===", name, chunk.register_count);
+    for (index, op) in chunk.code.iter().enumerate() {
+        let text = match op {
+            RegisterOp::Load { dest, source } => format!("Load r{} <- {}", dest, operand_str(chunk, *source)),
+            RegisterOp::GetGlobal { dest, name } => format!("GetGlobal r{} <- {}", dest, name_str(chunk, *name)),
+            RegisterOp::SetGlobal { name, source } => format!("SetGlobal {} <- {}", name_str(chunk, *name), operand_str(chunk, *source)),
+            RegisterOp::DefineGlobal { name, source } => format!("DefineGlobal {} <- {}", name_str(chunk, *name), operand_str(chunk, *source)),
+            RegisterOp::Unary { op, dest, source } => format!("{} r{} <- {}", opcode_name(*op), dest, operand_str(chunk, *source)),
+            RegisterOp::Binary { op, dest, left, right } => format!("{} r{} <- {}, {}", opcode_name(*op), dest, operand_str(chunk, *left), operand_str(chunk, *right)),
+            RegisterOp::Print { source } => format!("Print {}", operand_str(chunk, *source)),
+            RegisterOp::Return { source } => format!("Return {}", operand_str(chunk, *source)),
+            RegisterOp::Call { dest, arg_count } => format!("Call r{} <- r{}(r{}..r{})", dest, dest, dest + 1, dest + *arg_count as usize),
+            RegisterOp::Invoke { dest, name, arg_count } => format!("Invoke r{} <- r{}.{}(r{}..r{})", dest, dest, name_str(chunk, *name), dest + 1, dest + *arg_count as usize),
+            RegisterOp::Jump { target } => format!("Jump -> {}", target),
+            RegisterOp::JumpIfFalse { condition, target } => format!("JumpIfFalse {} -> {}", operand_str(chunk, *condition), target),
+        };
+        println!("{: >4} {: >4}: {}", index, chunk.lines[index], text);
+    }
+}
+
+
+pub struct RegisterMachine {
+    pub chunk: RegisterChunk,
+    pub ip: usize,
+    pub registers: Vec<Value>,
+    pub globals: HashMap<String, Value>,
+    pub error: Option<LoxError>,
+    pub output: Sink,
+    // what a Return ended the chunk with
+    pub result: Option<Value>,
+    // the vm's, for as long as the machine runs, see Limits for how they count here
+    pub limits: Limits,
+    pub deadline: Option<Instant>,
+    pub heap_bytes: usize,
+}
+
+impl RegisterMachine {
+    pub fn new(chunk: RegisterChunk) -> RegisterMachine {
+        let registers = vec![Value::null(); chunk.register_count];
+        return RegisterMachine {
+            chunk: chunk,
+            ip: 0,
+            registers: registers,
+            globals: HashMap::new(),
+            error: None,
+            output: stdout_sink(),
+            result: None,
+            limits: Default::default(),
+            deadline: None,
+            heap_bytes: 0,
+        };
+    }
+
+    fn read(&self, operand: Operand) -> &Value {
+        match operand {
+            Operand::Register(register) => &self.registers[register],
+            Operand::Constant(constant) => &self.chunk.constants[constant],
+        }
+    }
+
+    fn global_name(&self, name: u8) -> String {
+        if let ValueKind::Obj(ObjData::String(string)) = self.chunk.constants[name as usize].kind() {
+            return string.iter().collect();
+        }
+        panic!("Globals must be named by a string constant");
+    }
+}
+
+// How many registers the instruction needs, the register backend's stack depth
+fn registers_used(op: &RegisterOp) -> usize {
+    match op {
+        RegisterOp::Load { dest, .. } | RegisterOp::GetGlobal { dest, .. } | RegisterOp::Unary { dest, .. } | RegisterOp::Binary { dest, .. } => return dest + 1,
+        RegisterOp::Call { dest, arg_count } | RegisterOp::Invoke { dest, arg_count, .. } => return dest + 1 + *arg_count as usize,
+        _ => return 0,
+    }
+}
+
+// The same checks as the stack backend's, before every register instruction
+fn check_limits(vm: &mut RegisterMachine, until_clock: &mut u32) -> Option<Limit> {
+    if let Some(max_stack) = vm.limits.max_stack {
+        if registers_used(&vm.chunk.code[vm.ip]) > max_stack {
+            return Some(Limit::StackDepth);
+        }
+    }
+    if let Some(max_heap_bytes) = vm.limits.max_heap_bytes {
+        if vm.heap_bytes > max_heap_bytes {
+            vm.heap_bytes = distinct_heap_bytes(vm.globals.values().chain(&vm.registers).chain(&vm.chunk.constants));
+            if vm.heap_bytes > max_heap_bytes {
+                return Some(Limit::Heap);
+            }
+        }
+    }
+    if let Some(deadline) = vm.deadline {
+        if *until_clock == 0 {
+            *until_clock = CLOCK_INTERVAL;
+            if Instant::now() >= deadline {
+                return Some(Limit::Time);
+            }
+        }
+        *until_clock -= 1;
+    }
+    if let Some(fuel) = vm.limits.fuel {
+        if fuel == 0 {
+            return Some(Limit::Fuel);
+        }
+        vm.limits.fuel = Some(fuel - 1);
+    }
+    return None;
+}
+
+// For results that might be a new object
+fn set_result(vm: &mut RegisterMachine, dest: usize, result: Value) {
+    vm.heap_bytes += heap_size(&result);
+    vm.registers[dest] = result;
+}
+
+fn runtime_error(vm: &mut RegisterMachine, message: String) -> InterpretResult {
+    let line = vm.chunk.lines.get(vm.ip - 1).copied().unwrap_or(0);
+    vm.error = Some(LoxError::Runtime { line: line, message: message });
+    return InterpretResult::RuntimeError;
+}
+
+pub fn run_register(vm: &mut RegisterMachine) -> InterpretResult {
+    trace!("=== NOW RUNNING (register backend) ===");
+
+    let mut until_clock = 0;
+    while vm.ip < vm.chunk.code.len() {
+        if let Some(limit) = check_limits(vm, &mut until_clock) {
+            vm.error = Some(LoxError::Limit { line: vm.chunk.lines[vm.ip], limit: limit });
+            return InterpretResult::LimitReached(limit);
+        }
+        trace!("Execution: {}, Current state of registers: {:?}", vm.ip, vm.registers);
+        let op = vm.chunk.code[vm.ip].clone();
+        vm.ip += 1;
+
+        match op {
+            RegisterOp::Load { dest, source } => {
+                vm.registers[dest] = vm.read(source).clone();
+            },
+            RegisterOp::GetGlobal { dest, name } => {
+                let name = vm.global_name(name);
+                match vm.globals.get(&name) {
+                    Some(x) => vm.registers[dest] = x.clone(),
+                    None => return runtime_error(vm, format!("Tried to access a variable that doesn't exist: {}", name)),
+                }
+            },
+            RegisterOp::SetGlobal { name, source } | RegisterOp::DefineGlobal { name, source } => {
+                let value = vm.read(source).clone();
+                vm.globals.insert(vm.global_name(name), value);
+            },
+            RegisterOp::Unary { op, dest, source } => {
+                match unary_op(op, vm.read(source).clone()) {
+                    Ok(result) => set_result(vm, dest, result),
+                    Err(message) => return runtime_error(vm, message),
+                }
+            },
+            RegisterOp::Binary { op, dest, left, right } => {
+                match binary_op(op, vm.read(left).clone(), vm.read(right).clone()) {
+                    Ok(result) => set_result(vm, dest, result),
+                    Err(message) => return runtime_error(vm, message),
+                }
+            },
+            RegisterOp::Print { source } => {
+                let value = vm.read(source).clone();
+                if let Err(message) = print_value(&mut vm.output, &value) {
+                    return runtime_error(vm, message);
+                }
+            },
+            RegisterOp::Return { source } => {
+                trace!("Return found: {:?}", vm.read(source));
+                vm.result = Some(vm.read(source).clone());
+                return InterpretResult::Ok;
+            },
+            RegisterOp::Call { dest, arg_count } => {
+                let first_arg = dest + 1;
+                let args = vm.registers[first_arg..first_arg + arg_count as usize].to_vec();
+                match call_value(&vm.registers[dest], &args, &mut vm.output) {
+                    Ok(result) => set_result(vm, dest, result),
+                    Err(message) => return runtime_error(vm, message),
+                }
+            },
+            RegisterOp::Invoke { dest, name, arg_count } => {
+                let first_arg = dest + 1;
+                let args = vm.registers[first_arg..first_arg + arg_count as usize].to_vec();
+                match invoke_value(&vm.registers[dest], &vm.global_name(name), &args) {
+                    Ok(result) => set_result(vm, dest, result),
+                    Err(message) => return runtime_error(vm, message),
+                }
+            },
+            RegisterOp::Jump { target } => {
+                vm.ip = target;
+            },
+            RegisterOp::JumpIfFalse { condition, target } => {
+                if is_falsey(vm.read(condition)) {
+                    vm.ip = target;
+                }
+            },
+        }
+    }
+    return InterpretResult::Ok;
+}
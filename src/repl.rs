@@ -0,0 +1,113 @@
+use colored::Colorize;
+
+use rlox::{CompileOptions, Profile, Vm, compile_with_options, disassemble_chunk, get_value_str_with_quotes};
+
+use crate::editor::{LineEditor, ReadResult, needs_more_input};
+use crate::interpret;
+
+
+const HELP: &str = "\
+:dis <code>     show the bytecode <code> compiles to, without running it
+:tokens <code>  show the tokens <code> scans to
+:globals        list the globals and their values
+:load <file>    run a file in this session
+:reset          start over with a fresh vm
+:time <code>    run <code> and say how long it took
+:help           show this";
+
+// One vm for the whole session, so globals from earlier entries are still there. Every entry
+// is its own chunk, and a compile or runtime error only loses that entry. An entry keeps going
+// onto more lines while it has unclosed parens or braces.
+pub fn repl(options: &CompileOptions, profile: Profile) {
+    let mut vm = Vm::with_profile(*options, profile);
+    let mut editor = LineEditor::new(history_path());
+    'entries: loop {
+        let mut source = String::new();
+        let mut prompt = "> ";
+        loop {
+            match editor.read_line(prompt) {
+                ReadResult::Line(line) => {
+                    editor.add_history(&line);
+                    source += &line;
+                    source += "\n";
+                },
+                ReadResult::Interrupted => continue 'entries,
+                ReadResult::Eof => return,
+            }
+            // commands are always one line
+            if source.trim_start().starts_with(':') || !needs_more_input(&source) {
+                break;
+            }
+            prompt = "... ";
+        }
+        let entry = source.trim();
+        if entry.is_empty() {
+            continue;
+        }
+        if let Some(command) = entry.strip_prefix(':') {
+            run_command(&mut vm, command, options, profile);
+            continue;
+        }
+        evaluate(&mut vm, &source);
+    }
+}
+
+// Runs an entry and prints what it evaluated to, if it ended with an expression
+fn evaluate(vm: &mut Vm, source: &str) {
+    let result = vm.eval(source);
+    if let Some(pair_counts) = &vm.pair_counts {
+        rlox::vm::print_pair_profile(pair_counts);
+    }
+    if let Ok(Some(value)) = result {
+        println!("{}", get_value_str_with_quotes(&value));
+    }
+}
+
+fn run_command(vm: &mut Vm, command: &str, options: &CompileOptions, profile: Profile) {
+    let (name, argument) = match command.split_once(char::is_whitespace) {
+        Some((name, argument)) => (name, argument.trim()),
+        None => (command, ""),
+    };
+    match name {
+        "dis" => {
+            match compile_with_options(argument, &CompileOptions { repl: true, ..vm.options }) {
+                Ok(chunk) => disassemble_chunk(&chunk, argument),
+                Err(error) => println!("{}", error.to_string().red()),
+            }
+        },
+        "tokens" => {
+            let (_, tokens) = rlox::scanner::scan(&argument.to_string());
+            for token in tokens {
+                println!("[line {}] {:?} {}", token.line, token.token_type, token.data);
+            }
+        },
+        "globals" => {
+            let mut names: Vec<&String> = vm.globals.keys().collect();
+            names.sort();
+            for name in names {
+                println!("{} = {}", name, get_value_str_with_quotes(&vm.globals[name]));
+            }
+        },
+        "load" => {
+            match std::fs::read_to_string(argument) {
+                Ok(source) => {
+                    let _result = interpret(vm, &source);
+                },
+                Err(error) => println!("{}", format!("Couldn't read {}: {}", argument, error).red()),
+            }
+        },
+        "reset" => *vm = Vm::with_profile(*options, profile),
+        "time" => {
+            let start = std::time::Instant::now();
+            evaluate(vm, argument);
+            println!("took {:?}", start.elapsed());
+        },
+        "help" => println!("{}", HELP),
+        _ => println!("{}", format!("Unknown command :{}, :help lists them", name).red()),
+    }
+}
+
+fn history_path() -> Option<std::path::PathBuf> {
+    let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?;
+    return Some(std::path::PathBuf::from(home).join(".rlox_history"));
+}
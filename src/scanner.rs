@@ -0,0 +1,236 @@
+use enum_map::Enum;
+
+
+#[derive(PartialEq, Clone, Debug, Enum, Copy)]
+pub enum TokenType {
+    // Single-character tokens.
+    LeftParen, RightParen, 
+    LeftBrace, RightBrace, 
+    Comma, Dot, Minus, Plus, 
+    Semicolon, Slash, Star, 
+    // One or two character tokens.
+    Bang, BangEqual, 
+    Equal, EqualEqual, 
+    Greater, GreaterEqual, 
+    Less, LessEqual, 
+    // Literals.
+    Identifier, String, Number, 
+    // Keywords.
+    And, Class, Else, False, 
+    For, Fun, If, Null, Or, 
+    Print, Return, Super, This, 
+    True, Var, While, 
+    Error, 
+    Comment, 
+    // Eof,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct Token {
+    pub token_type: TokenType,
+    pub data: String,
+    pub line: i64,
+    // in characters from the start of the line, starting at 1
+    pub column: usize,
+}
+
+// Works on chars so anything utf-8 can be scanned, and can start partway through the source
+// for rescanning only what an edit touched
+pub struct Scanner<'a> {
+    chars: &'a [char],
+    current: usize,
+    line: i64,
+    // where the line being scanned starts, for columns
+    line_start: usize,
+}
+
+impl<'a> Scanner<'a> {
+    pub fn new(chars: &'a [char]) -> Scanner<'a> {
+        return Scanner::starting_at(chars, 0, 1, 0);
+    }
+
+    // current has to be between tokens, line and line_start are for where it is
+    pub fn starting_at(chars: &'a [char], current: usize, line: i64, line_start: usize) -> Scanner<'a> {
+        return Scanner { chars: chars, current: current, line: line, line_start: line_start };
+    }
+
+    fn peek(&self, ahead: usize) -> Option<char> {
+        return self.chars.get(self.current + ahead).copied();
+    }
+
+    fn make_token(&mut self, token_type: TokenType, size: usize) -> Token {
+        let token = Token {
+            token_type: token_type,
+            data: self.chars[self.current..self.current + size].iter().collect(),
+            line: self.line,
+            column: self.current - self.line_start + 1,
+        };
+        self.current += size;
+        return token;
+    }
+
+    fn error_token(&mut self, message: &str, start: usize, line: i64, line_start: usize) -> Token {
+        return Token { token_type: TokenType::Error, data: message.to_string(), line: line, column: start - line_start + 1 };
+    }
+
+    // One or two characters, depending on whether the next one is second
+    fn one_or_two(&mut self, second: char, one: TokenType, two: TokenType) -> Token {
+        if self.peek(1) == Some(second) {
+            return self.make_token(two, 2);
+        }
+        return self.make_token(one, 1);
+    }
+
+    fn skip_whitespace(&mut self) -> bool {
+        while let Some(c) = self.peek(0) {
+            if c == '\n' {
+                self.current += 1;
+                self.line += 1;
+                self.line_start = self.current;
+            }
+            else if c == ' ' || c == '\t' || c == '\r' {
+                self.current += 1;
+            }
+            else {
+                break;
+            }
+        }
+        return self.current < self.chars.len();
+    }
+
+    // Comments included, None at the end
+    pub fn next_token(&mut self) -> Option<Token> {
+        if !self.skip_whitespace() {
+            return None;
+        }
+        let the_char = self.peek(0).unwrap();
+        let token = match the_char {
+            '(' => self.make_token(TokenType::LeftParen, 1),
+            ')' => self.make_token(TokenType::RightParen, 1),
+            '{' => self.make_token(TokenType::LeftBrace, 1),
+            '}' => self.make_token(TokenType::RightBrace, 1),
+            ';' => self.make_token(TokenType::Semicolon, 1),
+            ',' => self.make_token(TokenType::Comma, 1),
+            '.' => self.make_token(TokenType::Dot, 1),
+            '-' => self.make_token(TokenType::Minus, 1),
+            '+' => self.make_token(TokenType::Plus, 1),
+            '*' => self.make_token(TokenType::Star, 1),
+            '!' => self.one_or_two('=', TokenType::Bang, TokenType::BangEqual),
+            '=' => self.one_or_two('=', TokenType::Equal, TokenType::EqualEqual),
+            '<' => self.one_or_two('=', TokenType::Less, TokenType::LessEqual),
+            '>' => self.one_or_two('=', TokenType::Greater, TokenType::GreaterEqual),
+            '/' => {
+                if self.peek(1) != Some('/') {
+                    return Some(self.make_token(TokenType::Slash, 1));
+                }
+                let mut size = 2;
+                while self.peek(size).is_some_and(|c| c != '\n') {
+                    size += 1;
+                }
+                trace!("Comment starts at: {} and ends at: {}", self.current, self.current + size);
+                self.make_token(TokenType::Comment, size)
+            },
+            '"' => {
+                // the token is on the line it starts on, but the lines inside it still count
+                let (start, line, line_start) = (self.current, self.line, self.line_start);
+                let mut size = 1;
+                while self.peek(size).is_some_and(|c| c != '"') {
+                    if self.peek(size) == Some('\n') {
+                        self.line += 1;
+                        self.line_start = self.current + size + 1;
+                    }
+                    size += 1;
+                }
+                if self.peek(size).is_none() {
+                    self.current += size;
+                    return Some(self.error_token("TOKEN ERROR: No end quote", start, line, line_start));
+                }
+                let token = Token {
+                    token_type: TokenType::String,
+                    data: self.chars[start..start + size + 1].iter().collect(),
+                    line: line,
+                    column: start - line_start + 1,
+                };
+                self.current += size + 1;
+                token
+            },
+            '0'..='9' => {
+                let mut size = 1;
+                while self.peek(size).is_some_and(|c| c.is_ascii_digit() || c == '.') {
+                    size += 1;
+                }
+                self.make_token(TokenType::Number, size)
+            },
+            'a'..='z' | 'A'..='Z' | '_' => {
+                let mut size = 1;
+                while self.peek(size).is_some_and(|c| c.is_alphanumeric() || c == '_') {
+                    size += 1;
+                }
+                let word: String = self.chars[self.current..self.current + size].iter().collect();
+                self.make_token(keyword(&word), size)
+            },
+            _ => {
+                let (start, line, line_start) = (self.current, self.line, self.line_start);
+                self.current += 1;
+                self.error_token("TOKEN ERROR: Unexpected character", start, line, line_start)
+            },
+        };
+        return Some(token);
+    }
+
+    // Where it's got to, as (current, line, line_start) for starting_at
+    pub fn position(&self) -> (usize, i64, usize) {
+        return (self.current, self.line, self.line_start);
+    }
+}
+
+fn keyword(word: &str) -> TokenType {
+    match word {
+        "and" => TokenType::And,
+        "class" => TokenType::Class,
+        "else" => TokenType::Else,
+        "if" => TokenType::If,
+        "null" => TokenType::Null,
+        "or" => TokenType::Or,
+        "print" => TokenType::Print,
+        "return" => TokenType::Return,
+        "super" => TokenType::Super,
+        "var" => TokenType::Var,
+        "while" => TokenType::While,
+        "true" => TokenType::True,
+        "false" => TokenType::False,
+        "for" => TokenType::For,
+        "fun" => TokenType::Fun,
+        "this" => TokenType::This,
+        _ => TokenType::Identifier,
+    }
+}
+
+// Every token, comments included, for tools that care about the whole file
+pub fn scan_with_comments(source: &str) -> Vec<Token> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut scanner = Scanner::new(&chars);
+    let mut tokens = vec![];
+    while let Some(token) = scanner.next_token() {
+        tokens.push(token);
+    }
+    return tokens;
+}
+
+// What the compiler wants, comments dropped. The bool is false when there's an Error token.
+pub fn scan(source: &String) -> (bool, Vec<Token>) {
+    trace!("=== Starting scanning of source code ===\n{}", source);
+    let mut succeeded = true;
+    let mut all_tokens = vec!();
+    for token in scan_with_comments(source) {
+        trace!("line: {}, column: {}, data: \"{}\", tokentype: {:?}", token.line, token.column, token.data, token.token_type);
+        if token.token_type == TokenType::Error {
+            succeeded = false;
+        }
+        if token.token_type != TokenType::Comment {
+            all_tokens.push(token);
+        }
+    }
+    trace!("Finished scanning of source code");
+    return (succeeded, all_tokens);
+}
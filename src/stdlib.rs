@@ -0,0 +1,161 @@
+use std::io::BufRead;
+use std::rc::Rc;
+
+use crate::native::{IntoNative, NativeFunction};
+use crate::value::{ObjData, Value, ValueKind, get_value_str};
+use crate::vm::VirtualMachine;
+
+
+// Natives that can reach outside the vm are grouped in modules, each needing one capability.
+// Profiles are the sets of capabilities embedders pick from when making a vm.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Capability {
+    Stdio,
+    Time,
+    Fs,
+    Process,
+}
+
+impl std::fmt::Display for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Capability::Stdio => write!(f, "stdio"),
+            Capability::Time => write!(f, "time"),
+            Capability::Fs => write!(f, "fs"),
+            Capability::Process => write!(f, "process"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Profile {
+    // only the core module, a script can't see or change anything outside the vm
+    Pure,
+    // reading stdin and the clock on top of that
+    Stdio,
+    // everything, files and the process included
+    Full,
+}
+
+impl Profile {
+    pub fn from_name(name: &str) -> Option<Profile> {
+        match name {
+            "pure" => Some(Profile::Pure),
+            "stdio" => Some(Profile::Stdio),
+            "full" => Some(Profile::Full),
+            _ => None,
+        }
+    }
+
+    pub fn capabilities(&self) -> &'static [Capability] {
+        match self {
+            Profile::Pure => &[],
+            Profile::Stdio => &[Capability::Stdio, Capability::Time],
+            Profile::Full => &[Capability::Stdio, Capability::Time, Capability::Fs, Capability::Process],
+        }
+    }
+
+    pub fn allows(&self, capability: Capability) -> bool {
+        return self.capabilities().contains(&capability);
+    }
+}
+
+impl std::fmt::Display for Profile {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Profile::Pure => write!(f, "pure"),
+            Profile::Stdio => write!(f, "stdio"),
+            Profile::Full => write!(f, "full"),
+        }
+    }
+}
+
+
+struct Module {
+    // None for modules every profile gets
+    capability: Option<Capability>,
+    natives: Vec<NativeFunction>,
+}
+
+fn type_name(value: Value) -> String {
+    match value.kind() {
+        ValueKind::Bool(_) => "bool".to_string(),
+        ValueKind::Null => "null".to_string(),
+        ValueKind::Number(_) => "number".to_string(),
+        ValueKind::Obj(ObjData::String(_)) => "string".to_string(),
+        ValueKind::Obj(ObjData::Native(_)) => "native".to_string(),
+        ValueKind::Obj(ObjData::UserData(data)) => data.type_name().to_string(),
+    }
+}
+
+fn modules() -> Vec<Module> {
+    return vec![
+        Module {
+            capability: None,
+            natives: vec![
+                (|value: Value| get_value_str(&value)).into_native("str"),
+                (|string: String| string.trim().parse::<f64>().ok()).into_native("num"),
+                (|string: String| string.chars().count()).into_native("len"),
+                type_name.into_native("type"),
+                f64::floor.into_native("floor"),
+                f64::sqrt.into_native("sqrt"),
+                f64::abs.into_native("abs"),
+            ],
+        },
+        Module {
+            capability: Some(Capability::Stdio),
+            natives: vec![
+                // null once stdin is closed
+                (|| -> Result<Option<String>, String> {
+                    let mut line = String::new();
+                    match std::io::stdin().lock().read_line(&mut line) {
+                        Ok(0) => Ok(None),
+                        Ok(_) => Ok(Some(line.trim_end_matches(['\n', '\r']).to_string())),
+                        Err(error) => Err(format!("input() couldn't read stdin: {}", error)),
+                    }
+                }).into_native("input"),
+            ],
+        },
+        Module {
+            capability: Some(Capability::Time),
+            natives: vec![
+                // seconds since the unix epoch
+                (|| std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|time| time.as_secs_f64()).unwrap_or(0.0)).into_native("clock"),
+            ],
+        },
+        Module {
+            capability: Some(Capability::Fs),
+            natives: vec![
+                (|path: String| std::fs::read_to_string(&path).map_err(|error| format!("read_file() couldn't read {}: {}", path, error))).into_native("read_file"),
+                (|path: String, contents: String| std::fs::write(&path, contents).map_err(|error| format!("write_file() couldn't write {}: {}", path, error))).into_native("write_file"),
+                (|path: String| std::path::Path::new(&path).exists()).into_native("file_exists"),
+            ],
+        },
+        Module {
+            capability: Some(Capability::Process),
+            natives: vec![
+                (|name: String| std::env::var(name).ok()).into_native("env"),
+                (|code: i32| -> () { std::process::exit(code) }).into_native("exit"),
+            ],
+        },
+    ];
+}
+
+// Natives the profile doesn't allow are still defined, as stubs that only ever error, so
+// scripts get told why instead of finding an undefined variable. The real function is dropped
+// right here and can't be reached from the vm. The ones that are allowed reach outside the vm,
+// so anything printed before has to be out first.
+pub fn register_stdlib(vm: &mut VirtualMachine, profile: Profile) {
+    for module in modules() {
+        for mut native in module.natives {
+            if let Some(capability) = module.capability {
+                native.flush_output = profile.allows(capability);
+                if !profile.allows(capability) {
+                    let message = format!("{}() needs the {} capability, which the {} profile doesn't grant", native.name, capability, profile);
+                    native.function = Rc::new(move |_args: &[Value]| Err(message.clone()));
+                }
+            }
+            vm.globals.insert(native.name.clone(), Value::from_obj(ObjData::Native(Rc::new(native))));
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use std::any::Any;
+use std::rc::Rc;
+
+use crate::convert::{FromLox, IntoLox};
+use crate::value::{ObjData, Value, ValueKind, get_value_str_with_quotes};
+
+
+// A host object handed to scripts as is, like a database handle. Scripts can print it, compare
+// it and call its methods (`db.query("...")`), everything else about it stays on the Rust side.
+// Methods only get &self, so anything a method changes has to sit behind a Cell/RefCell.
+pub trait UserData: Any {
+    fn type_name(&self) -> &str;
+
+    // what `print` shows
+    fn display(&self) -> String {
+        return format!("<{}>", self.type_name());
+    }
+
+    // Only asked when the two aren't the same object, the same object is always equal to
+    // itself. `other` can be any userdata, downcast it to check it's the same type.
+    fn equals(&self, _other: &dyn UserData) -> bool {
+        return false;
+    }
+
+    // Err is the message for the runtime error
+    fn call_method(&self, name: &str, _args: &[Value]) -> Result<Value, String> {
+        return Err(format!("{} has no method {}", self.type_name(), name));
+    }
+}
+
+impl std::fmt::Debug for dyn UserData {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.display())
+    }
+}
+
+impl PartialEq for dyn UserData {
+    fn eq(&self, other: &Self) -> bool {
+        return std::ptr::addr_eq(self, other) || self.equals(other);
+    }
+}
+
+
+impl Value {
+    pub fn from_userdata(data: impl UserData) -> Value {
+        return Value::from_obj(ObjData::UserData(Rc::new(data)));
+    }
+}
+
+impl<T: UserData> IntoLox for Rc<T> {
+    fn into_lox(self) -> Value {
+        return Value::from_obj(ObjData::UserData(self));
+    }
+}
+
+impl IntoLox for Rc<dyn UserData> {
+    fn into_lox(self) -> Value {
+        return Value::from_obj(ObjData::UserData(self));
+    }
+}
+
+// Gets the host object back out, only if it's the type asked for
+impl<T: UserData> FromLox for Rc<T> {
+    fn from_lox(value: &Value) -> Result<Self, String> {
+        if let ValueKind::Obj(ObjData::UserData(data)) = value.kind() {
+            let any: Rc<dyn Any> = data.clone();
+            if let Ok(data) = any.downcast::<T>() {
+                return Ok(data);
+            }
+        }
+        return Err(format!("expected a {} but got {}", std::any::type_name::<T>(), get_value_str_with_quotes(value)));
+    }
+}
+
+impl FromLox for Rc<dyn UserData> {
+    fn from_lox(value: &Value) -> Result<Self, String> {
+        if let ValueKind::Obj(ObjData::UserData(data)) = value.kind() {
+            return Ok(data.clone());
+        }
+        return Err(format!("expected userdata but got {}", get_value_str_with_quotes(value)));
+    }
+}
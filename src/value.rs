@@ -0,0 +1,324 @@
+use std::iter::zip;
+use std::rc::Rc;
+
+use crate::native::NativeFunction;
+use crate::userdata::UserData;
+
+// Value comes in two layouts. By default it's a plain enum, with the `nan-boxing` feature it's
+// packed into a single u64. Both can be built with the enum's constructors (Value::Number(1.0),
+// Value::Null, ...) or the from_* ones, and both are read through kind(), so the compiler and vm
+// don't care which one is in use. Only matching on Value itself is limited to the enum.
+
+
+#[repr(u8)]
+#[derive(Debug, Clone, PartialEq)]
+#[allow(dead_code)]
+pub enum ObjData {
+    String(Vec<char>),
+    Native(Rc<NativeFunction>),
+    UserData(Rc<dyn UserData>),
+}
+
+// Borrowed view of a Value, this is what gets matched on instead of the Value itself
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValueKind<'a> {
+    Bool(bool),
+    Null,
+    Number(f64),
+    Obj(&'a ObjData),
+}
+
+
+#[cfg(not(feature = "nan-boxing"))]
+#[repr(u8)]
+#[derive(Clone, PartialEq)]
+#[allow(dead_code)]
+pub enum Value {
+    Bool(bool),
+    Null,
+    Number(f64),
+    Obj(ObjData),
+}
+
+#[cfg(not(feature = "nan-boxing"))]
+impl Value {
+    pub fn from_bool(b: bool) -> Value {
+        return Value::Bool(b);
+    }
+
+    pub fn null() -> Value {
+        return Value::Null;
+    }
+
+    pub fn from_number(num: f64) -> Value {
+        return Value::Number(num);
+    }
+
+    pub fn from_obj(obj: ObjData) -> Value {
+        return Value::Obj(obj);
+    }
+
+    pub fn kind(&self) -> ValueKind<'_> {
+        match self {
+            Value::Bool(b) => ValueKind::Bool(*b),
+            Value::Null => ValueKind::Null,
+            Value::Number(num) => ValueKind::Number(*num),
+            Value::Obj(obj) => ValueKind::Obj(obj),
+        }
+    }
+}
+
+
+// Same scheme as clox: anything that isn't a quiet NaN with our bits set is a number, null and
+// the bools are small tags inside the quiet NaN, and objects set the sign bit and keep an
+// Rc<ObjData> pointer in the low 48 bits.
+#[cfg(feature = "nan-boxing")]
+const SIGN_BIT: u64 = 0x8000000000000000;
+#[cfg(feature = "nan-boxing")]
+const QNAN: u64 = 0x7ffc000000000000;
+#[cfg(feature = "nan-boxing")]
+const TAG_NULL: u64 = 1;
+#[cfg(feature = "nan-boxing")]
+const TAG_FALSE: u64 = 2;
+#[cfg(feature = "nan-boxing")]
+const TAG_TRUE: u64 = 3;
+// Real NaNs from arithmetic get swapped for this one so they can't look like a tagged value
+#[cfg(feature = "nan-boxing")]
+const CANONICAL_NAN: u64 = 0x7ff8000000000000;
+
+#[cfg(feature = "nan-boxing")]
+pub struct Value {
+    bits: u64,
+    // the object pointer is an Rc, so Value can't be shared across threads either
+    _rc: std::marker::PhantomData<std::rc::Rc<ObjData>>,
+}
+
+#[cfg(feature = "nan-boxing")]
+impl Value {
+    fn from_bits(bits: u64) -> Value {
+        return Value { bits: bits, _rc: std::marker::PhantomData };
+    }
+
+    pub fn from_bool(b: bool) -> Value {
+        return Value::from_bits(QNAN | if b { TAG_TRUE } else { TAG_FALSE });
+    }
+
+    pub fn null() -> Value {
+        return Value::from_bits(QNAN | TAG_NULL);
+    }
+
+    pub fn from_number(num: f64) -> Value {
+        if num.is_nan() {
+            return Value::from_bits(CANONICAL_NAN);
+        }
+        return Value::from_bits(num.to_bits());
+    }
+
+    pub fn from_obj(obj: ObjData) -> Value {
+        let pointer = std::rc::Rc::into_raw(std::rc::Rc::new(obj)) as u64;
+        assert!(pointer & (SIGN_BIT | QNAN) == 0, "Object pointer doesn't fit in 48 bits");
+        return Value::from_bits(SIGN_BIT | QNAN | pointer);
+    }
+
+    fn is_obj(&self) -> bool {
+        return self.bits & (SIGN_BIT | QNAN) == (SIGN_BIT | QNAN);
+    }
+
+    fn obj_pointer(&self) -> *const ObjData {
+        return (self.bits & !(SIGN_BIT | QNAN)) as *const ObjData;
+    }
+
+    pub fn kind(&self) -> ValueKind<'_> {
+        if self.bits & QNAN != QNAN {
+            return ValueKind::Number(f64::from_bits(self.bits));
+        }
+        if self.is_obj() {
+            // self holds a strong count on the object for as long as the borrow lives
+            return ValueKind::Obj(unsafe { &*self.obj_pointer() });
+        }
+        match self.bits & !QNAN {
+            TAG_NULL => ValueKind::Null,
+            TAG_FALSE => ValueKind::Bool(false),
+            TAG_TRUE => ValueKind::Bool(true),
+            _ => ValueKind::Number(f64::from_bits(self.bits)),
+        }
+    }
+}
+
+// The enum's variants as constructors, so code building values the old way (the vm does) still
+// compiles with this layout. There's nothing to match on in a u64 though, that goes through kind().
+#[cfg(feature = "nan-boxing")]
+#[allow(non_snake_case, non_upper_case_globals)]
+impl Value {
+    pub const Null: Value = Value { bits: QNAN | TAG_NULL, _rc: std::marker::PhantomData };
+
+    pub fn Bool(b: bool) -> Value {
+        return Value::from_bool(b);
+    }
+
+    pub fn Number(num: f64) -> Value {
+        return Value::from_number(num);
+    }
+
+    pub fn Obj(obj: ObjData) -> Value {
+        return Value::from_obj(obj);
+    }
+}
+
+#[cfg(feature = "nan-boxing")]
+impl Clone for Value {
+    fn clone(&self) -> Self {
+        if self.is_obj() {
+            unsafe { std::rc::Rc::increment_strong_count(self.obj_pointer()) };
+        }
+        return Value::from_bits(self.bits);
+    }
+}
+
+#[cfg(feature = "nan-boxing")]
+impl Drop for Value {
+    fn drop(&mut self) {
+        if self.is_obj() {
+            unsafe { std::rc::Rc::decrement_strong_count(self.obj_pointer()) };
+        }
+    }
+}
+
+#[cfg(feature = "nan-boxing")]
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        return self.kind() == other.kind();
+    }
+}
+
+
+impl std::fmt::Debug for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", get_value_str_with_quotes(self))
+    }
+}
+
+
+pub fn get_value_str(value: &Value) -> String {
+    match value.kind() {
+        ValueKind::Bool(b) => b.to_string(),
+        ValueKind::Null => "null".to_string(),
+        ValueKind::Number(num) => num.to_string(),
+        ValueKind::Obj(obj1) => {
+            let mut the_string = "".to_string();
+            match obj1 {
+                ObjData::String(char_vec) => {
+                    for i in char_vec {
+                        the_string += &i.to_string();
+                    }
+                    return the_string;
+                },
+                ObjData::Native(native) => return format!("{:?}", native),
+                ObjData::UserData(data) => return data.display(),
+            }
+        },
+    }
+}
+
+pub fn get_value_str_with_quotes(value: &Value) -> String {
+    match value.kind() {
+        ValueKind::Bool(b) => b.to_string(),
+        ValueKind::Null => "null".to_string(),
+        ValueKind::Number(num) => num.to_string(),
+        ValueKind::Obj(obj1) => {
+            let mut the_string = "\"".to_string();
+            match obj1 {
+                ObjData::String(char_vec) => {
+                    for i in char_vec {
+                        the_string += &i.to_string();
+                    }
+                    the_string += "\"";
+                    return the_string;
+                },
+                ObjData::Native(native) => return format!("{:?}", native),
+                ObjData::UserData(data) => return data.display(),
+            }
+        },
+    }
+}
+
+
+pub fn values_equal(val1: Value, val2: Value) -> bool {
+    if let ValueKind::Null = val1.kind() {
+        if let ValueKind::Null = val2.kind() {
+            return true;
+        }
+    }
+    if let ValueKind::Number(num1) = val1.kind() {
+        if let ValueKind::Number(num2) = val2.kind() {
+            return num1 == num2;
+        }
+    }
+    if let ValueKind::Bool(b1) = val1.kind() {
+        if let ValueKind::Bool(b2) = val2.kind() {
+            return b1 == b2;
+        }
+    }
+
+    // Can do string interning here (page 370) for perf increase, map strings to value to compare
+    if let ValueKind::Obj(ObjData::String(char_vec1)) = val1.kind() {
+        if let ValueKind::Obj(ObjData::String(char_vec2)) = val2.kind() {
+            if char_vec1.len() == char_vec2.len() {
+                for (a, b) in zip(char_vec1, char_vec2) {
+                    if a != b {
+                        return false;
+                    }
+                }
+                return true;
+            }
+        }
+    }
+    if let (ValueKind::Obj(ObjData::Native(native1)), ValueKind::Obj(ObjData::Native(native2))) = (val1.kind(), val2.kind()) {
+        return native1 == native2;
+    }
+    if let (ValueKind::Obj(ObjData::UserData(data1)), ValueKind::Obj(ObjData::UserData(data2))) = (val1.kind(), val2.kind()) {
+        return data1 == data2;
+    }
+    return false;
+}
+
+pub fn values_greater(val1: Value, val2: Value) -> bool {
+    if let ValueKind::Number(num1) = val1.kind() {
+        if let ValueKind::Number(num2) = val2.kind() {
+            return num1 > num2;
+        }
+    }
+    if let ValueKind::Bool(b1) = val1.kind() {
+        if let ValueKind::Bool(b2) = val2.kind() {
+            return b1 & !b2;
+        }
+    }
+    if let ValueKind::Obj(_) = val1.kind() {
+        panic!("Cant value greater an object, sorry bud")
+    }
+    if let ValueKind::Obj(_) = val2.kind() {
+        panic!("Cant value greater an object, sorry bud")
+    }
+    return false;
+}
+
+
+pub fn values_less(val1: Value, val2: Value) -> bool {
+    if let ValueKind::Number(num1) = val1.kind() {
+        if let ValueKind::Number(num2) = val2.kind() {
+            return num1 < num2;
+        }
+    }
+    if let ValueKind::Bool(b1) = val1.kind() {
+        if let ValueKind::Bool(b2) = val2.kind() {
+            return !b1 & b2;
+        }
+    }
+    if let ValueKind::Obj(_) = val1.kind() {
+        panic!("Cant value less an object, sorry bud")
+    }
+    if let ValueKind::Obj(_) = val2.kind() {
+        panic!("Cant value less an object, sorry bud")
+    }
+    return false;
+}
@@ -0,0 +1,148 @@
+use enum_map::Enum;
+
+use crate::LoxError;
+use crate::chunk::{Chunk, OpCode, OperandLayout, instruction_size, jump_target, opcode_name, operand_layout};
+use crate::value::{ObjData, ValueKind};
+
+
+// run() trusts the chunk it's given: operands are read without bounds checks, constants are
+// indexed straight into the pool and a global's name has to be a string. The compiler always
+// makes chunks like that, but a .loxc file or a tool might not, so those get checked here
+// first. Checks that every byte is part of a real instruction, every operand is in range, jumps
+// go forward to the start of an instruction, and the stack is never popped past empty and has
+// the same depth however an instruction is reached.
+pub fn verify(chunk: &Chunk) -> Result<(), LoxError> {
+    if chunk.lines.len() != chunk.code.len() {
+        return Err(invalid(0, format!("there are {} bytes of code but {} lines", chunk.code.len(), chunk.lines.len())));
+    }
+
+    // where each instruction starts, so jumps can be checked to land on one
+    let mut starts = vec![false; chunk.code.len() + 1];
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        let instruction = chunk.code[offset];
+        if instruction as usize >= OpCode::LENGTH {
+            return Err(invalid(offset, format!("{} isn't an opcode", instruction)));
+        }
+        if offset + instruction_size(instruction) > chunk.code.len() {
+            return Err(invalid(offset, format!("{} is cut off by the end of the code", opcode_name(instruction))));
+        }
+        starts[offset] = true;
+        offset += instruction_size(instruction);
+    }
+    // jumping to the very end is how an if at the end of a script finishes
+    starts[chunk.code.len()] = true;
+
+    // stack depth each jump target gets reached with, jumps only go forward so every jump to an
+    // offset has been seen by the time it's reached
+    let mut jumped_in: Vec<Option<usize>> = vec![None; chunk.code.len() + 1];
+    // None when the last instruction doesn't fall through, like a Return
+    let mut depth = Some(0);
+    offset = 0;
+    while offset < chunk.code.len() {
+        let instruction = chunk.code[offset];
+        depth = merge(offset, depth, jumped_in[offset])?;
+        check_operands(chunk, offset)?;
+
+        let (pops, pushes) = stack_effect(chunk, offset);
+        if let Some(before) = depth {
+            if before < pops {
+                return Err(invalid(offset, format!("{} needs {} values on the stack but there are only {}", opcode_name(instruction), pops, before)));
+            }
+            depth = Some(before - pops + pushes);
+        }
+        if instruction == OpCode::Jump as u8 || instruction == OpCode::JumpIfFalse as u8 {
+            let target = jump_target(chunk, offset);
+            if target > chunk.code.len() || !starts[target] {
+                return Err(invalid(offset, format!("{} goes to {}, which isn't the start of an instruction", opcode_name(instruction), target)));
+            }
+            jumped_in[target] = merge(target, depth, jumped_in[target])?;
+        }
+        if instruction == OpCode::Jump as u8 || instruction == OpCode::Return as u8 {
+            depth = None;
+        }
+        offset += instruction_size(instruction);
+    }
+    merge(chunk.code.len(), depth, jumped_in[chunk.code.len()])?;
+    return Ok(());
+}
+
+fn invalid(offset: usize, message: String) -> LoxError {
+    return LoxError::Invalid { offset: offset, message: message };
+}
+
+// Every way into an instruction, falling through or jumping, has to agree on the depth
+fn merge(offset: usize, falling_through: Option<usize>, jumped_in: Option<usize>) -> Result<Option<usize>, LoxError> {
+    match (falling_through, jumped_in) {
+        (Some(depth), Some(jumped)) if depth != jumped => {
+            return Err(invalid(offset, format!("the stack is {} deep one way in here but {} deep another", depth, jumped)));
+        },
+        _ => return Ok(falling_through.or(jumped_in)),
+    }
+}
+
+fn check_operands(chunk: &Chunk, offset: usize) -> Result<(), LoxError> {
+    let instruction = chunk.code[offset];
+    let layout = operand_layout(instruction);
+    let names_a_global = layout == OperandLayout::Global || layout == OperandLayout::Method;
+    if !names_a_global && layout != OperandLayout::Constant {
+        return Ok(());
+    }
+
+    let index = chunk.code[offset + 1] as usize;
+    let constant = match chunk.constants.get(index) {
+        Some(constant) => constant,
+        None => return Err(invalid(offset, format!("{} uses constant {} but there are only {}", opcode_name(instruction), index, chunk.constants.len()))),
+    };
+    if names_a_global && !matches!(constant.kind(), ValueKind::Obj(ObjData::String(_))) {
+        return Err(invalid(offset, format!("{} needs a string constant for the name, constant {} isn't one", opcode_name(instruction), index)));
+    }
+    return Ok(());
+}
+
+// How many values the instruction pops and then how many it pushes
+fn stack_effect(chunk: &Chunk, offset: usize) -> (usize, usize) {
+    let instruction = chunk.code[offset];
+    if instruction == OpCode::Constant as u8
+        || instruction == OpCode::Null as u8
+        || instruction == OpCode::True as u8
+        || instruction == OpCode::False as u8
+        || instruction == OpCode::GetGlobal as u8 {
+        return (0, 1);
+    }
+    else if instruction == OpCode::Add as u8
+        || instruction == OpCode::Subtract as u8
+        || instruction == OpCode::Multiply as u8
+        || instruction == OpCode::Divide as u8
+        || instruction == OpCode::Equal as u8
+        || instruction == OpCode::Greater as u8
+        || instruction == OpCode::Less as u8
+        || instruction == OpCode::NotEqual as u8
+        || instruction == OpCode::GreaterEqual as u8
+        || instruction == OpCode::LessEqual as u8 {
+        return (2, 1);
+    }
+    else if instruction == OpCode::Negate as u8
+        || instruction == OpCode::Not as u8
+        || instruction == OpCode::SetGlobal as u8
+        || instruction == OpCode::ConstantAdd as u8 {
+        return (1, 1);
+    }
+    else if instruction == OpCode::Return as u8
+        || instruction == OpCode::Print as u8
+        || instruction == OpCode::Pop as u8
+        || instruction == OpCode::DefineGlobal as u8
+        || instruction == OpCode::JumpIfFalse as u8
+        || instruction == OpCode::SetGlobalPop as u8 {
+        // Return leaves its value where it is, but nothing runs after it
+        return (1, 0);
+    }
+    else if instruction == OpCode::Call as u8 {
+        return (chunk.code[offset + 1] as usize + 1, 1);
+    }
+    else if instruction == OpCode::Invoke as u8 {
+        return (chunk.code[offset + 2] as usize + 1, 1);
+    }
+    // Jump, ConstantPrint, GetGlobalPrint
+    return (0, 0);
+}
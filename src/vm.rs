@@ -0,0 +1,790 @@
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::time::Instant;
+
+use crate::LoxError;
+use crate::chunk::{Chunk, OpCode, instruction_size, opcode_name};
+use crate::compiler::{Backend, CompileOptions, compile_with_options};
+use crate::convert::IntoLox;
+use crate::disassembler::disassemble_and_print_instruction;
+use crate::limits::{Limit, Limits};
+use crate::native::IntoNative;
+use crate::output::{Sink, stderr_sink, stdout_sink};
+use crate::stdlib::{Profile, register_stdlib};
+use crate::register;
+use crate::value::{ObjData, Value, ValueKind, get_value_str, get_value_str_with_quotes, values_equal, values_greater, values_less};
+use crate::verify::verify;
+
+
+// vm stuff
+#[derive(PartialEq, Debug)]
+pub enum InterpretResult {
+    Ok,
+    CompileError,
+    RuntimeError,
+    // one of vm.limits was hit, the vm can be resumed from where it stopped
+    LimitReached(Limit),
+    // a DebugHook asked to stop before the instruction at vm.ip
+    Paused,
+}
+
+// What a debugger plugs into the dispatch loop. run() is the loop with a hook that never pauses,
+// which compiles away, so there's no cost to having this when nothing's attached.
+pub trait DebugHook {
+    // true pauses the vm before the instruction at vm.ip runs
+    fn before_instruction(&mut self, vm: &VirtualMachine) -> bool;
+}
+
+struct NoHook;
+
+impl DebugHook for NoHook {
+    #[inline(always)]
+    fn before_instruction(&mut self, _vm: &VirtualMachine) -> bool {
+        return false;
+    }
+}
+
+// The operations themselves live outside run() so the register backend does exactly the same thing
+
+pub fn is_falsey(value: &Value) -> bool {
+    match value.kind() {
+        ValueKind::Null => true,
+        ValueKind::Bool(b) => !b,
+        ValueKind::Number(_) => false,
+        ValueKind::Obj(_) => false,
+    }
+}
+
+// Err is the message for the runtime error
+pub fn unary_op(instruction: u8, value: Value) -> Result<Value, String> {
+    if instruction == OpCode::Not as u8 {
+        match value.kind() {
+            ValueKind::Null => return Err("You can't not a null!".to_string()),
+            ValueKind::Bool(b) => return Ok(Value::Bool(!b)),
+            ValueKind::Number(_) => return Err("You can't not a number!".to_string()),
+            ValueKind::Obj(_) => return Err("You can't not an obj!".to_string()),
+        }
+    }
+    match value.kind() {
+        ValueKind::Null => Err("You can't negate a null!".to_string()),
+        ValueKind::Bool(b) => Ok(Value::Bool(!b)),
+        ValueKind::Number(num) => Ok(Value::Number(-num)),
+        ValueKind::Obj(_) => Err("You can't negate an object!".to_string()),
+    }
+}
+
+// Err is the message for the runtime error, usually the operands were the wrong types
+pub fn binary_op(instruction: u8, left: Value, right: Value) -> Result<Value, String> {
+    if instruction == OpCode::Equal as u8 {
+        return Ok(Value::Bool(values_equal(left, right)));
+    }
+    if instruction == OpCode::NotEqual as u8 {
+        return Ok(Value::Bool(!values_equal(left, right)));
+    }
+    // values_less and values_greater panic on objects
+    let is_comparison = instruction == OpCode::Less as u8
+        || instruction == OpCode::Greater as u8
+        || instruction == OpCode::GreaterEqual as u8
+        || instruction == OpCode::LessEqual as u8;
+    if is_comparison && (matches!(left.kind(), ValueKind::Obj(_)) || matches!(right.kind(), ValueKind::Obj(_))) {
+        return Err("Cant compare an object, sorry bud".to_string());
+    }
+    if instruction == OpCode::Less as u8 {
+        return Ok(Value::Bool(values_less(left, right)));
+    }
+    if instruction == OpCode::Greater as u8 {
+        return Ok(Value::Bool(values_greater(left, right)));
+    }
+    // These two and NotEqual stand in for Equal/Less/Greater followed by a Not, see optimizer::peephole
+    if instruction == OpCode::GreaterEqual as u8 {
+        return Ok(Value::Bool(!values_less(left, right)));
+    }
+    if instruction == OpCode::LessEqual as u8 {
+        return Ok(Value::Bool(!values_greater(left, right)));
+    }
+
+    if let ValueKind::Obj(ObjData::String(right_char_vec)) = right.kind() {
+        if let ValueKind::Obj(ObjData::String(left_char_vec)) = left.kind() {
+            // Does this leak memory? The new dynamically allocatd string that ets pushed on the stack
+            if instruction == OpCode::Add as u8 {
+                let mut new_char_vec = left_char_vec.clone();
+                for i in right_char_vec {
+                    new_char_vec.push(*i);
+                }
+                return Ok(Value::Obj(ObjData::String(new_char_vec)));
+            }
+            return Err(format!("Can't {} strings", opcode_name(instruction)));
+        }
+    }
+    else if let ValueKind::Number(right_val) = right.kind() {
+        if let ValueKind::Number(left_val) = left.kind() {
+            if instruction == OpCode::Add as u8 {
+                return Ok(Value::Number(left_val + right_val));
+            }
+            else if instruction == OpCode::Multiply as u8 {
+                return Ok(Value::Number(left_val * right_val));
+            }
+            else if instruction == OpCode::Subtract as u8 {
+                return Ok(Value::Number(left_val - right_val));
+            }
+            else if instruction == OpCode::Divide as u8 {
+                return Ok(Value::Number(left_val / right_val));
+            }
+            return Err(format!("This binary op was straight up illegal: {}", opcode_name(instruction)));
+        }
+    }
+    return Err(format!("Uh oh, stinky! Can't {} {} and {}", opcode_name(instruction), get_value_str_with_quotes(&left), get_value_str_with_quotes(&right)));
+}
+
+
+pub fn call_value(callee: &Value, args: &[Value], output: &mut Sink) -> Result<Value, String> {
+    if let ValueKind::Obj(ObjData::Native(native)) = callee.kind() {
+        if args.len() != native.arity {
+            return Err(format!("{}() expected {} arguments but got {}", native.name, native.arity, args.len()));
+        }
+        if native.flush_output {
+            let _ = output.flush();
+        }
+        return (native.function)(args);
+    }
+    return Err(format!("Can only call functions, {} isn't one", get_value_str_with_quotes(callee)));
+}
+
+
+pub fn invoke_value(receiver: &Value, name: &str, args: &[Value]) -> Result<Value, String> {
+    if let ValueKind::Obj(ObjData::UserData(data)) = receiver.kind() {
+        return data.call_method(name, args);
+    }
+    return Err(format!("Only userdata has methods, {} doesn't", get_value_str_with_quotes(receiver)));
+}
+
+
+// What print does. The "Printing: " prefix is only there to pick prints out of the trace.
+pub fn print_value(output: &mut Sink, value: &Value) -> Result<(), String> {
+    let prefix = if crate::tracing() { "Printing: " } else { "" };
+    return match writeln!(output, "{}{}", prefix, get_value_str(value)) {
+        Ok(()) => Ok(()),
+        Err(error) => Err(format!("Couldn't write the output: {}", error)),
+    };
+}
+
+
+fn constant_string(chunk: &Chunk, constant_index: u8) -> String {
+    if let ValueKind::Obj(ObjData::String(string)) = chunk.constants[constant_index as usize].kind() {
+        return string.iter().collect();
+    }
+    panic!("Expected a string constant for the variable name");
+}
+
+// Records the error for VirtualMachine::run_chunk to hand back
+fn runtime_error(vm: &mut VirtualMachine, line: i64, message: String) -> InterpretResult {
+    vm.error = Some(LoxError::Runtime { line: line, message: message });
+    return InterpretResult::RuntimeError;
+}
+
+// Rough size of what an object takes up on the heap, for Limits::max_heap_bytes. Natives are
+// the host's and there from the start, so they don't count against the script.
+pub fn heap_size(value: &Value) -> usize {
+    match value.kind() {
+        ValueKind::Obj(ObjData::String(string)) => std::mem::size_of::<ObjData>() + string.len() * std::mem::size_of::<char>(),
+        ValueKind::Obj(ObjData::Native(_)) => 0,
+        ValueKind::Obj(_) => std::mem::size_of::<ObjData>(),
+        _ => 0,
+    }
+}
+
+// Every object the vm can still get to, through its globals, its stack or the chunk's
+// constants. An object shared between them only counts once.
+fn live_heap_bytes(vm: &VirtualMachine) -> usize {
+    return distinct_heap_bytes(vm.globals.values().chain(&vm.stack).chain(&vm.chunk.constants));
+}
+
+// The register backend holds its values somewhere else, so it counts them with this
+pub fn distinct_heap_bytes<'a>(values: impl Iterator<Item = &'a Value>) -> usize {
+    let mut seen = HashSet::new();
+    let mut bytes = 0;
+    for value in values {
+        if let ValueKind::Obj(obj) = value.kind() {
+            if seen.insert(obj as *const ObjData) {
+                bytes += heap_size(value);
+            }
+        }
+    }
+    return bytes;
+}
+
+// For results that might be a new object
+fn push_result(vm: &mut VirtualMachine, result: Value) {
+    vm.heap_bytes += heap_size(&result);
+    vm.stack.push(result);
+}
+
+// How many instructions go by between looking at the clock
+pub const CLOCK_INTERVAL: u32 = 1024;
+
+// Called before every instruction. Fuel is only used up once the instruction is actually going
+// to run, so resuming doesn't charge for the same instruction twice.
+fn check_limits(vm: &mut VirtualMachine, until_clock: &mut u32) -> Option<Limit> {
+    if let Some(max_stack) = vm.limits.max_stack {
+        if vm.stack.len() > max_stack {
+            return Some(Limit::StackDepth);
+        }
+    }
+    if let Some(max_heap_bytes) = vm.limits.max_heap_bytes {
+        // heap_bytes never goes down as values are dropped, so it's only a limit once a recount
+        // agrees
+        if vm.heap_bytes > max_heap_bytes {
+            vm.heap_bytes = live_heap_bytes(vm);
+            if vm.heap_bytes > max_heap_bytes {
+                return Some(Limit::Heap);
+            }
+        }
+    }
+    if let Some(deadline) = vm.deadline {
+        if *until_clock == 0 {
+            *until_clock = CLOCK_INTERVAL;
+            if Instant::now() >= deadline {
+                return Some(Limit::Time);
+            }
+        }
+        *until_clock -= 1;
+    }
+    if let Some(fuel) = vm.limits.fuel {
+        if fuel == 0 {
+            return Some(Limit::Fuel);
+        }
+        vm.limits.fuel = Some(fuel - 1);
+    }
+    return None;
+}
+
+pub fn run(vm: &mut VirtualMachine) -> InterpretResult {
+    return dispatch(vm, &mut NoHook);
+}
+
+pub fn run_with_hook(vm: &mut VirtualMachine, hook: &mut impl DebugHook) -> InterpretResult {
+    return dispatch(vm, hook);
+}
+
+fn dispatch<H: DebugHook>(vm: &mut VirtualMachine, hook: &mut H) -> InterpretResult {
+    trace!("=== NOW RUNNING ===");
+
+    trace!("{:?}", vm.chunk.code);
+    // the last instruction and where it ended, pairs only count when nothing jumped in between
+    let mut previous: Option<(u8, usize)> = None;
+    let mut until_clock = 0;
+    while vm.ip < vm.chunk.code.len() {
+        trace!("Execution: {}, Current state of stack: {:?}", vm.ip, vm.stack);
+
+        let instruction = vm.chunk.code[vm.ip];
+        let line = vm.chunk.lines.get(vm.ip).copied().unwrap_or(0);
+        if hook.before_instruction(vm) {
+            return InterpretResult::Paused;
+        }
+        if let Some(limit) = check_limits(vm, &mut until_clock) {
+            vm.error = Some(LoxError::Limit { line: line, limit: limit });
+            return InterpretResult::LimitReached(limit);
+        }
+        if crate::tracing() {
+            disassemble_and_print_instruction(&vm.chunk, vm.ip);
+        }
+
+        if let Some(pair_counts) = &mut vm.pair_counts {
+            if let Some((previous_instruction, previous_end)) = previous {
+                if previous_end == vm.ip {
+                    *pair_counts.entry((previous_instruction, instruction)).or_insert(0) += 1;
+                }
+            }
+            previous = Some((instruction, vm.ip + instruction_size(instruction)));
+        }
+
+        vm.ip += 1;
+        if instruction == OpCode::Return as u8 {
+            // ends the chunk, the value on top is what it evaluated to
+            trace!("Return found: {:?}", vm.stack.last());
+            return InterpretResult::Ok;
+        }
+        else if instruction == OpCode::Pop as u8 {
+            let popped = vm.stack.pop().unwrap();
+            trace!("Pop found: {:?}", popped);
+            continue;
+        }
+        else if instruction == OpCode::DefineGlobal as u8 {
+            trace!("DefineGlobal found");
+            let variable_equal_to = vm.stack.pop().unwrap();
+
+            let constant_index = vm.chunk.code[vm.ip];
+            trace!("the byte is: {}", constant_index);
+            // disassemble_and_print_instruction(&vm.chunk, vm.ip);
+
+            let constant = &vm.chunk.constants[constant_index as usize];
+
+
+            if let ValueKind::Obj(ObjData::String(string)) = constant.kind() {
+                let mut new_string = "".to_string();
+                for i in string {
+                    new_string += &i.to_string();
+                }
+                vm.globals.insert(new_string, variable_equal_to);
+                vm.ip += 1;
+                continue;
+            }
+            panic!("DefiningGlobal must have a string constant after it");
+        }
+        else if instruction == OpCode::GetGlobal as u8 {
+            trace!("GetGlobal found");
+            let constant_index = vm.chunk.code[vm.ip];
+            let constant = &vm.chunk.constants[constant_index as usize];                
+            vm.ip += 1;
+
+            if let ValueKind::Obj(ObjData::String(string)) = constant.kind() {
+                let mut new_string = "".to_string();
+                for i in string {
+                    new_string += &i.to_string();
+                }
+                let result = vm.globals.get(&new_string);
+                match result {
+                    Some(x) => vm.stack.push(x.clone()),
+                    None => return runtime_error(vm, line, format!("Tried to access a variable that doesn't exist: {}", new_string)),
+                }
+                continue;
+            }
+            panic!("DefiningGlobal must have a string constant after it");
+        }
+        else if instruction == OpCode::SetGlobal as u8 {
+            trace!("SetGlobal found");
+            let variable_equal_to = vm.stack.pop().unwrap();
+
+            let constant_index = vm.chunk.code[vm.ip];
+            trace!("the byte is: {}", constant_index);
+            // disassemble_and_print_instruction(&vm.chunk, vm.ip);
+
+            let constant = &vm.chunk.constants[constant_index as usize];
+
+
+            if let ValueKind::Obj(ObjData::String(string)) = constant.kind() {
+                let mut new_string = "".to_string();
+                for i in string {
+                    new_string += &i.to_string();
+                }
+                vm.globals.insert(new_string, variable_equal_to.clone());
+                vm.ip += 1;
+                vm.stack.push(variable_equal_to);
+                continue;
+            }
+            panic!("SetGlobal must have a string constant after it");
+        }
+        else if instruction == OpCode::Constant as u8 {
+            let constant_index = vm.chunk.code[vm.ip];
+            let constant = &vm.chunk.constants[constant_index as usize];
+            vm.stack.push(constant.clone());
+            vm.ip += 1;
+            continue;
+        }
+        else if instruction == OpCode::True as u8 {
+            vm.stack.push(Value::Bool(true));
+            continue;
+        }
+        else if instruction == OpCode::False as u8 {
+            vm.stack.push(Value::Bool(false));
+            continue;
+        }
+        else if instruction == OpCode::Null as u8 {
+            vm.stack.push(Value::Null);
+            continue;
+        }
+        else if instruction == OpCode::Print as u8 {
+            let value = vm.stack.pop().unwrap();
+            if let Err(message) = print_value(&mut vm.output, &value) {
+                return runtime_error(vm, line, message);
+            }
+            continue;
+        }
+        else if instruction == OpCode::JumpIfFalse as u8 {
+            trace!("if statement");
+            let short_part_1 = (vm.chunk.code[vm.ip] as usize) << 8;
+            let short_part_2 = vm.chunk.code[vm.ip + 1] as usize;
+            let jump_forward = short_part_1 + short_part_2;
+            vm.ip += 2;
+
+            if is_falsey(&vm.stack.pop().unwrap()) {
+                trace!("False branch, jumping!");
+                vm.ip += jump_forward;
+            }
+            continue;
+        }
+        else if instruction == OpCode::Jump as u8 {
+            let short_part_1 = (vm.chunk.code[vm.ip] as usize) << 8;
+            let short_part_2 = vm.chunk.code[vm.ip + 1] as usize;
+            vm.ip += 2 + short_part_1 + short_part_2;
+            continue;
+        }
+        else if instruction == OpCode::Call as u8 {
+            let arg_count = vm.chunk.code[vm.ip] as usize;
+            vm.ip += 1;
+            let args = vm.stack.split_off(vm.stack.len() - arg_count);
+            let callee = vm.stack.pop().unwrap();
+            match call_value(&callee, &args, &mut vm.output) {
+                Ok(result) => push_result(vm, result),
+                Err(message) => return runtime_error(vm, line, message),
+            }
+            continue;
+        }
+        else if instruction == OpCode::Invoke as u8 {
+            let name = constant_string(&vm.chunk, vm.chunk.code[vm.ip]);
+            let arg_count = vm.chunk.code[vm.ip + 1] as usize;
+            vm.ip += 2;
+            let args = vm.stack.split_off(vm.stack.len() - arg_count);
+            let receiver = vm.stack.pop().unwrap();
+            match invoke_value(&receiver, &name, &args) {
+                Ok(result) => push_result(vm, result),
+                Err(message) => return runtime_error(vm, line, message),
+            }
+            continue;
+        }
+        else if instruction == OpCode::Not as u8 || instruction == OpCode::Negate as u8 {
+            match unary_op(instruction, vm.stack.pop().unwrap()) {
+                Ok(value) => vm.stack.push(value),
+                Err(message) => return runtime_error(vm, line, message),
+            }
+            continue;
+        }
+        else if instruction == OpCode::ConstantAdd as u8 {
+            let right = vm.chunk.constants[vm.chunk.code[vm.ip] as usize].clone();
+            vm.ip += 1;
+            let left = vm.stack.pop().unwrap();
+            match binary_op(OpCode::Add as u8, left, right) {
+                Ok(result) => push_result(vm, result),
+                Err(message) => return runtime_error(vm, line, message),
+            }
+            continue;
+        }
+        else if instruction == OpCode::ConstantPrint as u8 {
+            let constant = vm.chunk.constants[vm.chunk.code[vm.ip] as usize].clone();
+            vm.ip += 1;
+            if let Err(message) = print_value(&mut vm.output, &constant) {
+                return runtime_error(vm, line, message);
+            }
+            continue;
+        }
+        else if instruction == OpCode::GetGlobalPrint as u8 {
+            let name = constant_string(&vm.chunk, vm.chunk.code[vm.ip]);
+            vm.ip += 1;
+            let value = match vm.globals.get(&name) {
+                Some(x) => x.clone(),
+                None => return runtime_error(vm, line, format!("Tried to access a variable that doesn't exist: {}", name)),
+            };
+            if let Err(message) = print_value(&mut vm.output, &value) {
+                return runtime_error(vm, line, message);
+            }
+            continue;
+        }
+        else if instruction == OpCode::SetGlobalPop as u8 {
+            let name = constant_string(&vm.chunk, vm.chunk.code[vm.ip]);
+            vm.ip += 1;
+            let variable_equal_to = vm.stack.pop().unwrap();
+            vm.globals.insert(name, variable_equal_to);
+            continue;
+        }
+        // Assuming its a binary operation
+
+        let stack_val1 = vm.stack.pop().unwrap();
+        let stack_val2 = vm.stack.pop().unwrap();
+
+        match binary_op(instruction, stack_val2, stack_val1) {
+            Ok(result) => push_result(vm, result),
+            Err(message) => return runtime_error(vm, line, message),
+        }
+    }
+    return InterpretResult::Ok;
+}
+
+
+
+pub struct VirtualMachine {
+    pub chunk: Chunk,
+    pub ip: usize,
+    pub stack: Vec<Value>,
+    pub globals: HashMap<String, Value>,
+    // counts of opcodes that ran back to back, only kept with --profile-pairs
+    pub pair_counts: Option<HashMap<(u8, u8), u64>>,
+    pub options: CompileOptions,
+    // set when run() returns RuntimeError
+    pub error: Option<LoxError>,
+    // what scripts print goes to output, errors that stop a script go to errors
+    pub output: Sink,
+    pub errors: Sink,
+    pub limits: Limits,
+    // bytes of objects the vm holds on to, counted up from the last time they were all added
+    // up, see Limits::max_heap_bytes
+    pub heap_bytes: usize,
+    deadline: Option<Instant>,
+    // stopped by a limit with the rest of the chunk still to run
+    suspended: bool,
+    // what resume() carries on with when it was the register backend that got stopped
+    register_machine: Option<register::RegisterMachine>,
+    // which natives scripts can use, fixed when the vm is made
+    profile: Profile,
+}
+
+pub type Vm = VirtualMachine;
+
+impl Default for VirtualMachine {
+    fn default() -> Self {
+        return VirtualMachine::new();
+    }
+}
+
+// The embedding API. Globals stick around between calls, so a host can set_global, interpret
+// a script that uses it, then get_global whatever the script left behind.
+impl VirtualMachine {
+    pub fn new() -> VirtualMachine {
+        return VirtualMachine::with_options(Default::default());
+    }
+
+    // Embedders get the pure profile unless they ask for more
+    pub fn with_options(options: CompileOptions) -> VirtualMachine {
+        return VirtualMachine::with_profile(options, Profile::Pure);
+    }
+
+    pub fn with_profile(options: CompileOptions, profile: Profile) -> VirtualMachine {
+        let mut vm = VirtualMachine {
+            chunk: Default::default(),
+            ip: 0,
+            stack: vec!(),
+            globals: HashMap::new(),
+            pair_counts: if options.profile_pairs { Some(HashMap::new()) } else { None },
+            options: options,
+            error: None,
+            output: stdout_sink(),
+            errors: stderr_sink(),
+            limits: Default::default(),
+            heap_bytes: 0,
+            deadline: None,
+            suspended: false,
+            register_machine: None,
+            profile: profile,
+        };
+        register_stdlib(&mut vm, profile);
+        return vm;
+    }
+
+    pub fn profile(&self) -> Profile {
+        return self.profile;
+    }
+
+    pub fn set_output(&mut self, output: impl Write + 'static) {
+        self.output = Box::new(output);
+    }
+
+    pub fn set_errors(&mut self, errors: impl Write + 'static) {
+        self.errors = Box::new(errors);
+    }
+
+    // Ok is whatever the script left on the stack, which is null for anything made of statements.
+    // An Err has also been written to the errors sink.
+    pub fn interpret(&mut self, source: &str) -> Result<Value, LoxError> {
+        let options = self.options;
+        let chunk = self.compile(source, &options)?;
+        return self.execute(&chunk).map(|value| value.unwrap_or(Value::Null));
+    }
+
+    // What the repl runs entries with. An entry ending in an expression gives back its value,
+    // and that last expression doesn't need a semicolon. None when it ends with anything else.
+    pub fn eval(&mut self, source: &str) -> Result<Option<Value>, LoxError> {
+        let chunk = self.compile(source, &CompileOptions { repl: true, ..self.options })?;
+        return self.execute(&chunk);
+    }
+
+    fn compile(&mut self, source: &str, options: &CompileOptions) -> Result<Chunk, LoxError> {
+        let result = compile_with_options(source, options);
+        if let Err(error) = &result {
+            self.report(error);
+        }
+        return result;
+    }
+
+    fn report(&mut self, error: &LoxError) {
+        let _ = self.output.flush();
+        let _ = writeln!(self.errors, "{}", error);
+        let _ = self.errors.flush();
+    }
+
+    // The chunk could have come from anywhere, so it's verified before it runs. Chunks compiled
+    // by interpret and eval skip that.
+    pub fn run_chunk(&mut self, chunk: &Chunk) -> Result<Value, LoxError> {
+        if let Err(error) = verify(chunk) {
+            self.report(&error);
+            return Err(error);
+        }
+        return self.execute(chunk).map(|value| value.unwrap_or(Value::Null));
+    }
+
+    fn load(&mut self, chunk: &Chunk) {
+        self.chunk = chunk.clone();
+        self.ip = 0;
+        self.stack.clear();
+        self.error = None;
+        self.heap_bytes = live_heap_bytes(self);
+        self.suspended = false;
+        self.register_machine = None;
+    }
+
+    fn execute(&mut self, chunk: &Chunk) -> Result<Option<Value>, LoxError> {
+        self.load(chunk);
+        self.deadline = self.limits.time.map(|time| Instant::now() + time);
+
+        let result = if self.options.backend == Backend::Register {
+            let register_chunk = register::compile_chunk(&self.chunk);
+            if self.options.print_code {
+                register::disassemble_register_chunk(&register_chunk, "register code");
+            }
+            self.run_register(register::RegisterMachine::new(register_chunk))
+        }
+        else {
+            run(self)
+        };
+        return self.finish(result);
+    }
+
+    // The machine gets the vm's globals, output and limits while it runs. One that a limit
+    // stopped is kept for resume().
+    fn run_register(&mut self, mut machine: register::RegisterMachine) -> InterpretResult {
+        machine.deadline = self.deadline;
+        machine.heap_bytes = self.heap_bytes;
+        std::mem::swap(&mut machine.globals, &mut self.globals);
+        std::mem::swap(&mut machine.output, &mut self.output);
+        std::mem::swap(&mut machine.limits, &mut self.limits);
+        let result = register::run_register(&mut machine);
+        std::mem::swap(&mut machine.globals, &mut self.globals);
+        std::mem::swap(&mut machine.output, &mut self.output);
+        std::mem::swap(&mut machine.limits, &mut self.limits);
+        self.heap_bytes = machine.heap_bytes;
+        self.error = machine.error.take();
+        self.stack.extend(machine.result.take());
+        if let InterpretResult::LimitReached(_) = result {
+            self.register_machine = Some(machine);
+        }
+        return result;
+    }
+
+    // Carries on after a LimitReached, from the instruction that didn't get to run. Raise
+    // whatever limit was hit first or it'll just be hit again.
+    pub fn resume(&mut self) -> Result<Value, LoxError> {
+        if !self.suspended {
+            let error = LoxError::Runtime { line: 0, message: "There's nothing to resume".to_string() };
+            self.report(&error);
+            return Err(error);
+        }
+        self.suspended = false;
+        self.deadline = self.limits.time.map(|time| Instant::now() + time);
+        let result = match self.register_machine.take() {
+            Some(machine) => self.run_register(machine),
+            None => run(self),
+        };
+        return self.finish(result).map(|value| value.unwrap_or(Value::Null));
+    }
+
+    // For debuggers, verifies the chunk and leaves it suspended before its first instruction,
+    // ready for resume_with_hook. It always runs on the stack backend.
+    pub fn start_chunk(&mut self, chunk: &Chunk) -> Result<(), LoxError> {
+        if let Err(error) = verify(chunk) {
+            self.report(&error);
+            return Err(error);
+        }
+        self.load(chunk);
+        self.suspended = true;
+        return Ok(());
+    }
+
+    // resume() with the hook asked before every instruction. Ok(true) when the hook paused it,
+    // and it's suspended again right where it stopped, Ok(false) when the chunk ran to the end.
+    pub fn resume_with_hook(&mut self, hook: &mut impl DebugHook) -> Result<bool, LoxError> {
+        if !self.suspended {
+            let error = LoxError::Runtime { line: 0, message: "There's nothing to resume".to_string() };
+            self.report(&error);
+            return Err(error);
+        }
+        self.suspended = false;
+        self.deadline = self.limits.time.map(|time| Instant::now() + time);
+        let result = run_with_hook(self, hook);
+        if result == InterpretResult::Paused {
+            let _ = self.output.flush();
+            self.suspended = true;
+            return Ok(true);
+        }
+        return self.finish(result).map(|_| false);
+    }
+
+    // Evaluates source the way eval does while a chunk is suspended, with the same globals, and
+    // puts the suspended chunk back the way it was afterwards
+    pub fn eval_while_suspended(&mut self, source: &str) -> Result<Option<Value>, LoxError> {
+        let chunk = std::mem::take(&mut self.chunk);
+        let stack = std::mem::take(&mut self.stack);
+        let (ip, heap_bytes, suspended, deadline) = (self.ip, self.heap_bytes, self.suspended, self.deadline);
+        let result = self.eval(source);
+        self.chunk = chunk;
+        self.stack = stack;
+        self.ip = ip;
+        self.heap_bytes = heap_bytes;
+        self.suspended = suspended;
+        self.deadline = deadline;
+        return result;
+    }
+
+    pub fn is_suspended(&self) -> bool {
+        return self.suspended;
+    }
+
+    pub fn add_fuel(&mut self, fuel: u64) {
+        self.limits.fuel = Some(self.limits.fuel.unwrap_or(0) + fuel);
+    }
+
+    fn finish(&mut self, result: InterpretResult) -> Result<Option<Value>, LoxError> {
+        match result {
+            InterpretResult::LimitReached(_) => {
+                // not reported, the host asked for the limit and is expected to handle it
+                let _ = self.output.flush();
+                self.suspended = true;
+                return Err(self.error.take().expect("Hitting a limit always records it"));
+            },
+            InterpretResult::Ok => {
+                let _ = self.output.flush();
+                return Ok(self.stack.pop());
+            },
+            _ => {
+                let error = self.error.take().expect("A failed run always records its error");
+                self.report(&error);
+                return Err(error);
+            },
+        }
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        return self.globals.get(name).cloned();
+    }
+
+    pub fn set_global(&mut self, name: &str, value: impl IntoLox) {
+        self.globals.insert(name.to_string(), value.into_lox());
+    }
+
+    // Makes a Rust closure callable from scripts as a global, arguments get converted with
+    // FromLox and a mismatch is a runtime error in the script:
+    //     vm.register_native("add", |a: f64, b: f64| a + b);
+    pub fn register_native<Args>(&mut self, name: &str, function: impl IntoNative<Args>) {
+        let native = function.into_native(name);
+        self.globals.insert(name.to_string(), Value::Obj(ObjData::Native(std::rc::Rc::new(native))));
+    }
+}
+
+pub fn print_pair_profile(pair_counts: &HashMap<(u8, u8), u64>) {
+    let mut pairs: Vec<(&(u8, u8), &u64)> = pair_counts.iter().collect();
+    pairs.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    let total: u64 = pair_counts.values().sum();
+
+    println!("=== opcode pairs ({} total) ===", total);
+    for ((first, second), count) in pairs {
+        let percent = *count as f64 * 100.0 / total as f64;
+        println!("{: >10} {: >6.2}%  {} -> {}", count, percent, opcode_name(*first), opcode_name(*second));
+    }
+}
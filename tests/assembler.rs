@@ -0,0 +1,113 @@
+use rlox::{Chunk, CompileOptions, LoxError, MemorySink, ObjData, OpCode, Value, Vm, assemble, compile_with_options, disassemble, values_equal};
+
+
+const SCRIPTS: &[&str] = &[
+    "print 1 + 2 * 3; print -4.25 / 2;",
+    "var a = 1; var b = 2;\nif (a < b) {\n  if (b > a) { print \"inner\"; } else { print \"inner else\"; }\n} else {\n  print \"outer else\";\n}\na = 5;\nprint a;",
+    "var name = \"world\"; print \"hello \" + name; print len(name) == 5; print str(len(name));",
+    "if (false) print 1;",
+];
+
+fn assert_same_chunk(assembled: &Chunk, chunk: &Chunk) {
+    assert_eq!(assembled.code, chunk.code);
+    assert_eq!(assembled.lines, chunk.lines);
+    assert_eq!(assembled.constants.len(), chunk.constants.len());
+    for (assembled_constant, constant) in assembled.constants.iter().zip(&chunk.constants) {
+        assert!(values_equal(assembled_constant.clone(), constant.clone()), "{:?} came back as {:?}", constant, assembled_constant);
+    }
+}
+
+fn output_of(chunk: &Chunk) -> String {
+    let mut vm = Vm::new();
+    let output = MemorySink::new();
+    vm.set_output(output.clone());
+    vm.run_chunk(chunk).unwrap();
+    return output.contents();
+}
+
+fn error_line_and_message(source: &str) -> (i64, String) {
+    match assemble(source) {
+        Err(LoxError::Compile { line, message }) => return (line, message),
+        other => panic!("Expected {:?} not to assemble, got {:?}", source, other),
+    }
+}
+
+
+#[test]
+fn disassembling_then_assembling_gives_the_same_bytes() {
+    for level in 0..=2 {
+        let mut options = CompileOptions::default();
+        options.set_optimization_level(level);
+        for script in SCRIPTS {
+            let chunk = compile_with_options(script, &options).unwrap();
+            let assembled = assemble(&disassemble(&chunk, "script")).unwrap();
+            assert_same_chunk(&assembled, &chunk);
+        }
+    }
+}
+
+#[test]
+fn awkward_constants_round_trip() {
+    let constants = vec![
+        Value::from_obj(ObjData::String("quote \" backslash \\ semicolon ; tab\tnewline\n".chars().collect())),
+        Value::from_number(0.1),
+        Value::from_number(-0.0),
+        Value::from_number(1e300),
+        Value::from_bool(true),
+        Value::null(),
+    ];
+    let mut code = vec![];
+    for index in 0..constants.len() as u8 {
+        code.extend([OpCode::Constant as u8, index, OpCode::Print as u8]);
+    }
+    let lines = vec![7; code.len()];
+    let chunk = Chunk { code: code, lines: lines, constants: constants };
+    let assembled = assemble(&disassemble(&chunk, "constants")).unwrap();
+    assert_same_chunk(&assembled, &chunk);
+    assert_eq!(output_of(&assembled), output_of(&chunk));
+}
+
+#[test]
+fn bytes_that_arent_instructions_round_trip() {
+    let chunk = Chunk { code: vec![OpCode::Null as u8, 250, OpCode::Jump as u8, 0], lines: vec![1, 1, 2, 2], constants: vec![] };
+    assert_same_chunk(&assemble(&disassemble(&chunk, "junk")).unwrap(), &chunk);
+}
+
+#[test]
+fn hand_written_assembly_runs() {
+    let source = "
+        ; values right in the operands, names for globals
+        CONSTANT \"hi\"
+        PRINT
+        CONSTANT 2
+        DEFINE_GLOBAL two
+        GET_GLOBAL two
+        CONSTANT 0.5
+        Add
+        PRINT
+        FALSE
+        JUMP_IF_FALSE skip
+        CONSTANT \"skipped\"
+        PRINT
+        skip:
+        GET_GLOBAL len
+        CONSTANT \"abc\"
+        CALL 1
+        PRINT
+    ";
+    let chunk = assemble(source).unwrap();
+    assert_eq!(output_of(&chunk), "hi\n2.5\n3\n");
+    // lines come from the file
+    assert_eq!(chunk.lines[0], 3);
+}
+
+#[test]
+fn mistakes_point_at_their_line() {
+    assert_eq!(error_line_and_message("PRINT\nFROB"), (2, "FROB isn't an opcode".to_string()));
+    assert_eq!(error_line_and_message("CONSTANT"), (1, "Constant takes 1 operands but got 0".to_string()));
+    assert_eq!(error_line_and_message("TRUE\nJUMP_IF_FALSE nowhere"), (2, "There's no label called nowhere".to_string()));
+    assert_eq!(error_line_and_message("back:\nTRUE\nJUMP_IF_FALSE back"), (3, "Jumps can only go forward, back is behind".to_string()));
+    assert_eq!(error_line_and_message("a:\na:"), (2, "The label a is already somewhere else".to_string()));
+    assert_eq!(error_line_and_message("constants:\n    0  1\ncode:\nCONSTANT 3"), (4, "There's no constant 3".to_string()));
+    assert_eq!(error_line_and_message("CONSTANT \"open"), (1, "A string is missing its end quote".to_string()));
+}
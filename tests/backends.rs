@@ -0,0 +1,74 @@
+use rlox::{Backend, CompileOptions, MemorySink, Vm};
+
+
+// Every script in the repo, and snippets that between them use every opcode
+const SCRIPT_FILES: &[&str] = &["src/test.lox", "benches/workload.lox"];
+
+const SNIPPETS: &[&str] = &[
+    "print 1 + 2 * 3; print -4.25 / 2; print (1 + 2) * (3 - 4); print 10 / 4 - 1;",
+    "print \"a\" + \"b\"; print 1 < 2; print 2 <= 1; print 3 > 3; print 3 >= 3; print 1 != 2; print \"a\" == \"a\"; print null == false;",
+    "var a = 1; var b = 2;\nif (a < b) {\n  if (b > a) { print \"inner\"; } else { print \"inner else\"; }\n} else {\n  print \"outer else\";\n}\na = 5;\nprint a;",
+    "var name = \"world\"; print \"hello \" + name; print len(name) == 5; print str(len(name) + 1); print type(name); print floor(2.7);",
+    "if (false) print 1; if (null) print 2; else print 3; if (0) print \"zero is true\";",
+    "var x = 1; x = x + 1; x = x + 1; print x; print x + 10; 1 + 2; x; var y = x = 7; print y;",
+    "var s = \"caf\" + \"é\"; print s; print len(s); var t; print t;",
+    // runtime errors have to come out the same too, after the same output
+    "print 1; print -\"a\";",
+    "print 2; print nope;",
+    "print 3; print \"a\" - 1;",
+    "print len(1, 2);",
+];
+
+// What a script printed, then its error if it had one
+fn run(source: &str, backend: Backend, level: u8) -> String {
+    let mut options = CompileOptions::default();
+    options.set_optimization_level(level);
+    options.backend = backend;
+    let mut vm = Vm::with_options(options);
+    let output = MemorySink::new();
+    vm.set_output(output.clone());
+    vm.set_errors(MemorySink::new());
+    match vm.interpret(source) {
+        Ok(_) => return output.contents(),
+        Err(error) => return format!("{}error: {}\n", output.contents(), error),
+    }
+}
+
+
+#[test]
+fn scripts_print_the_same_on_both_backends() {
+    let mut sources: Vec<String> = SCRIPT_FILES.iter().map(|path| {
+        std::fs::read_to_string(format!("{}/{}", env!("CARGO_MANIFEST_DIR"), path)).unwrap()
+    }).collect();
+    sources.extend(SNIPPETS.iter().map(|snippet| snippet.to_string()));
+
+    let mut errors = 0;
+    for source in &sources {
+        errors += run(source, Backend::Stack, 0).contains("Runtime error") as usize;
+        for level in 0..=2 {
+            let stack = run(source, Backend::Stack, level);
+            let register = run(source, Backend::Register, level);
+            assert!(!stack.is_empty(), "{}", source);
+            assert_eq!(register, stack, "at -O{} for {}", level, source);
+        }
+    }
+    assert_eq!(errors, 4);
+}
+
+#[test]
+fn the_register_backend_takes_whatever_the_stack_backend_does() {
+    // a full constant pool, then literals that the register backend keeps in the pool too
+    let mut full_pool: String = (0..127).map(|i| format!("var v{} = {};\n", i, i)).collect();
+    full_pool.push_str("print 1000;\nprint true;\nprint v126 == null;\n");
+    // deeper than a byte can number registers
+    let deep = format!("var a = 1;\nprint {}a{};", "(a + ".repeat(300), ")".repeat(300));
+    for source in [&full_pool, &deep] {
+        for level in 0..=2 {
+            let stack = run(source, Backend::Stack, level);
+            assert!(!stack.contains("error"), "{}", stack);
+            assert_eq!(run(source, Backend::Register, level), stack, "at -O{}", level);
+        }
+    }
+    assert_eq!(run(&full_pool, Backend::Register, 0), "1000\ntrue\nfalse\n");
+    assert_eq!(run(&deep, Backend::Register, 0), "301\n");
+}
@@ -0,0 +1,82 @@
+use rlox::{CompileOptions, LoxError, MemorySink, Profile, Vm};
+
+
+fn vm_with(profile: Profile) -> (Vm, MemorySink) {
+    let mut vm = Vm::with_profile(CompileOptions::default(), profile);
+    let output = MemorySink::new();
+    vm.set_output(output.clone());
+    vm.set_errors(MemorySink::new());
+    return (vm, output);
+}
+
+fn runtime_error(vm: &mut Vm, source: &str) -> String {
+    match vm.interpret(source) {
+        Err(LoxError::Runtime { message, .. }) => return message,
+        other => panic!("Expected a runtime error from {:?}, got {:?}", source, other),
+    }
+}
+
+// somewhere no test would have made on purpose
+fn scratch_path(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("rlox-capabilities-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_file(&path);
+    return path.to_string_lossy().to_string();
+}
+
+
+#[test]
+fn core_natives_work_in_every_profile() {
+    for profile in [Profile::Pure, Profile::Stdio, Profile::Full] {
+        let (mut vm, output) = vm_with(profile);
+        vm.interpret("print len(\"hello\"); print str(1) + \"!\"; print num(\"2.5\"); print type(true); print floor(2.7);").unwrap();
+        assert_eq!(output.contents(), "5\n1!\n2.5\nbool\n2\n");
+    }
+}
+
+#[test]
+fn pure_denies_everything_outside_the_vm() {
+    let (mut vm, _) = vm_with(Profile::Pure);
+    assert_eq!(runtime_error(&mut vm, "input();"), "input() needs the stdio capability, which the pure profile doesn't grant");
+    assert_eq!(runtime_error(&mut vm, "clock();"), "clock() needs the time capability, which the pure profile doesn't grant");
+    assert_eq!(runtime_error(&mut vm, "read_file(\"Cargo.toml\");"), "read_file() needs the fs capability, which the pure profile doesn't grant");
+    assert_eq!(runtime_error(&mut vm, "env(\"PATH\");"), "env() needs the process capability, which the pure profile doesn't grant");
+    assert_eq!(runtime_error(&mut vm, "exit(3);"), "exit() needs the process capability, which the pure profile doesn't grant");
+}
+
+#[test]
+fn stdio_gets_the_clock_but_not_files() {
+    let (mut vm, output) = vm_with(Profile::Stdio);
+    vm.interpret("print clock() > 0;").unwrap();
+    assert_eq!(output.contents(), "true\n");
+
+    let path = scratch_path("stdio");
+    let message = runtime_error(&mut vm, &format!("write_file(\"{}\", \"nope\");", path));
+    assert_eq!(message, "write_file() needs the fs capability, which the stdio profile doesn't grant");
+    assert!(!std::path::Path::new(&path).exists());
+}
+
+#[test]
+fn denied_natives_stay_denied_when_passed_around() {
+    let (mut vm, _) = vm_with(Profile::Pure);
+    let path = scratch_path("aliased");
+    let message = runtime_error(&mut vm, &format!("var sneaky = write_file; sneaky(\"{}\", \"nope\");", path));
+    assert_eq!(message, "write_file() needs the fs capability, which the pure profile doesn't grant");
+    assert!(!std::path::Path::new(&path).exists());
+}
+
+#[test]
+fn full_can_use_files() {
+    let (mut vm, output) = vm_with(Profile::Full);
+    let path = scratch_path("full");
+    vm.interpret(&format!("write_file(\"{}\", \"hi there\"); print file_exists(\"{}\"); print read_file(\"{}\");", path, path, path)).unwrap();
+    assert_eq!(output.contents(), "true\nhi there\n");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn embedders_get_pure_by_default() {
+    let mut vm = Vm::new();
+    vm.set_errors(MemorySink::new());
+    assert_eq!(vm.profile(), Profile::Pure);
+    assert_eq!(runtime_error(&mut vm, "read_file(\"Cargo.toml\");"), "read_file() needs the fs capability, which the pure profile doesn't grant");
+}
@@ -0,0 +1,52 @@
+use rlox::cfg::{BasicBlock, Edge, EdgeKind};
+use rlox::{CompileOptions, assemble, basic_blocks, cfg_dot, compile_with_options};
+
+
+fn edge(to: Option<usize>, kind: EdgeKind) -> Edge {
+    return Edge { to: to, kind: kind };
+}
+
+
+#[test]
+fn if_else_splits_into_a_diamond() {
+    let mut options = CompileOptions::default();
+    options.set_optimization_level(0);
+    let chunk = compile_with_options("var a = true; if (a) { print 1; } else { print 2; } print 3;", &options).unwrap();
+    let blocks = basic_blocks(&chunk);
+    let successors: Vec<Vec<Edge>> = blocks.iter().map(|block| block.successors.clone()).collect();
+    assert_eq!(successors, vec![
+        // the condition
+        vec![edge(Some(2), EdgeKind::Taken), edge(Some(1), EdgeKind::Fallthrough)],
+        // then, jumping over else
+        vec![edge(Some(3), EdgeKind::Taken)],
+        // else
+        vec![edge(Some(3), EdgeKind::Fallthrough)],
+        // after, off the end
+        vec![edge(None, EdgeKind::Fallthrough)],
+    ]);
+    assert_eq!(blocks[0].start, 0);
+    assert_eq!(blocks.last().unwrap().end, chunk.code.len());
+}
+
+#[test]
+fn return_leaves_the_chunk() {
+    let chunk = assemble("TRUE\nJUMP_IF_FALSE end\nNULL\nRETURN\nend:\nNULL\nPOP").unwrap();
+    assert_eq!(basic_blocks(&chunk), vec![
+        BasicBlock { start: 0, end: 4, successors: vec![edge(Some(2), EdgeKind::Taken), edge(Some(1), EdgeKind::Fallthrough)] },
+        BasicBlock { start: 4, end: 6, successors: vec![edge(None, EdgeKind::Return)] },
+        BasicBlock { start: 6, end: 8, successors: vec![edge(None, EdgeKind::Fallthrough)] },
+    ]);
+}
+
+#[test]
+fn dot_output_has_every_block_and_edge() {
+    let chunk = assemble("CONSTANT \"say \\\"hi\\\"\"\nFALSE\nJUMP_IF_FALSE done\nPRINT\ndone:").unwrap();
+    let dot = cfg_dot(&chunk, "quotes");
+    assert!(dot.starts_with("digraph \"quotes\" {\n"), "{}", dot);
+    assert!(dot.contains("block0 [label=\"0000  Constant       0        ; \\\"say \\\\\\\"hi\\\\\\\"\\\"\\l"), "{}", dot);
+    assert!(dot.contains("    entry -> block0;\n"), "{}", dot);
+    assert!(dot.contains("    block0 -> exit [label=\"taken\"];\n"), "{}", dot);
+    assert!(dot.contains("    block0 -> block1 [label=\"fallthrough\", style=dashed];\n"), "{}", dot);
+    assert!(dot.contains("    block1 -> exit [label=\"fallthrough\", style=dashed];\n"), "{}", dot);
+    assert!(dot.ends_with("}\n"));
+}
@@ -0,0 +1,67 @@
+use rlox::{FromLox, IntoLox, Value, ValueKind, get_value_str};
+
+
+fn number(num: f64) -> Value {
+    return Value::from_number(num);
+}
+
+
+#[test]
+fn rust_values_go_in_and_come_back_out() {
+    assert_eq!(f64::from_lox(&2.5f64.into_lox()), Ok(2.5));
+    assert_eq!(f32::from_lox(&0.5f32.into_lox()), Ok(0.5));
+    assert_eq!(i32::from_lox(&(-7i32).into_lox()), Ok(-7));
+    assert_eq!(u8::from_lox(&255u8.into_lox()), Ok(255));
+    assert_eq!(bool::from_lox(&true.into_lox()), Ok(true));
+    assert_eq!(String::from_lox(&"héllo".into_lox()), Ok("héllo".to_string()));
+    assert_eq!(String::from_lox(&"s".to_string().into_lox()), Ok("s".to_string()));
+    assert_eq!(<()>::from_lox(&().into_lox()), Ok(()));
+    assert_eq!(Option::<i64>::from_lox(&Some(3i64).into_lox()), Ok(Some(3)));
+    assert_eq!(Option::<i64>::from_lox(&None::<i64>.into_lox()), Ok(None));
+    assert_eq!(None::<String>.into_lox().kind(), ValueKind::Null);
+    assert_eq!(get_value_str(&Value::from_lox(&"v".into_lox()).unwrap()), "v");
+}
+
+#[test]
+fn the_wrong_kind_of_value_says_what_it_got() {
+    assert_eq!(bool::from_lox(&number(1.0)), Err("expected a bool but got 1".to_string()));
+    assert_eq!(f64::from_lox(&"1".into_lox()), Err("expected a number but got \"1\"".to_string()));
+    assert_eq!(String::from_lox(&Value::null()), Err("expected a string but got null".to_string()));
+    assert_eq!(<()>::from_lox(&false.into_lox()), Err("expected null but got false".to_string()));
+    assert!(Option::<bool>::from_lox(&number(0.0)).is_err());
+}
+
+#[test]
+fn integers_have_to_be_whole_and_fit() {
+    assert_eq!(i8::from_lox(&number(-128.0)), Ok(-128));
+    assert_eq!(i8::from_lox(&number(127.0)), Ok(127));
+    assert!(i8::from_lox(&number(128.0)).is_err());
+    assert!(i8::from_lox(&number(-129.0)).is_err());
+    assert!(u8::from_lox(&number(256.0)).is_err());
+    assert!(u32::from_lox(&number(-1.0)).is_err());
+    assert!(i32::from_lox(&number(1.5)).is_err());
+    assert!(i32::from_lox(&number(f64::NAN)).is_err());
+    assert!(i64::from_lox(&number(f64::INFINITY)).is_err());
+    assert!(u64::from_lox(&number(f64::NEG_INFINITY)).is_err());
+    assert_eq!(
+        i32::from_lox(&number(2.5)),
+        Err("expected a whole number that fits in i32 but got 2.5".to_string())
+    );
+}
+
+#[test]
+fn integers_at_the_edge_of_what_an_f64_holds() {
+    // MAX as f64 rounds up to these, they're one past the end and used to saturate
+    assert!(u64::from_lox(&number(2f64.powi(64))).is_err());
+    assert!(i64::from_lox(&number(2f64.powi(63))).is_err());
+    assert!(usize::from_lox(&number(2f64.powi(usize::BITS as i32))).is_err());
+    assert!(isize::from_lox(&number(2f64.powi(isize::BITS as i32 - 1))).is_err());
+    // the biggest f64s under them still fit
+    let below_u64 = f64::from_bits(2f64.powi(64).to_bits() - 1);
+    assert_eq!(u64::from_lox(&number(below_u64)), Ok(below_u64 as u64));
+    let below_i64 = f64::from_bits(2f64.powi(63).to_bits() - 1);
+    assert_eq!(i64::from_lox(&number(below_i64)), Ok(below_i64 as i64));
+    assert_eq!(i64::from_lox(&number(-(2f64.powi(63)))), Ok(i64::MIN));
+    assert_eq!(u32::from_lox(&number(u32::MAX as f64)), Ok(u32::MAX));
+    assert!(u32::from_lox(&number(u32::MAX as f64 + 1.0)).is_err());
+}
@@ -0,0 +1,165 @@
+use rlox::json::{Json, parse_json};
+use rlox::protocol::{read_message, write_message};
+use rlox::{CompileOptions, Profile};
+
+
+// Sends the requests to a session and gives back everything that came out of it
+fn session(requests: &[&str]) -> Vec<Json> {
+    return session_with(requests, Profile::Pure);
+}
+
+fn session_with(requests: &[&str], profile: Profile) -> Vec<Json> {
+    let mut input = vec![];
+    for (index, request) in requests.iter().enumerate() {
+        let mut request = parse_json(request).unwrap();
+        if let Json::Object(fields) = &mut request {
+            fields.insert(0, ("seq".to_string(), Json::from(index + 1)));
+            fields.insert(1, ("type".to_string(), Json::from("request")));
+        }
+        write_message(&mut input, &request).unwrap();
+    }
+    let mut output = vec![];
+    rlox::dap::serve(&mut &input[..], &mut output, &CompileOptions::default(), profile).unwrap();
+
+    let mut messages = vec![];
+    let mut output = &output[..];
+    while let Some(message) = read_message(&mut output).unwrap() {
+        messages.push(message);
+    }
+    return messages;
+}
+
+fn script(name: &str, source: &str) -> String {
+    let path = std::env::temp_dir().join(format!("rlox-dap-{}-{}.lox", std::process::id(), name));
+    std::fs::write(&path, source).unwrap();
+    return path.to_string_lossy().to_string();
+}
+
+fn launch(program: &str, stop_on_entry: bool) -> String {
+    return Json::object(vec![
+        ("command", Json::from("launch")),
+        ("arguments", Json::object(vec![("program", Json::from(program)), ("stopOnEntry", Json::from(stop_on_entry))])),
+    ]).to_string();
+}
+
+fn response<'a>(messages: &'a [Json], command: &str) -> &'a Json {
+    return messages.iter().find(|message| message.get("type").as_str() == Some("response") && message.get("command").as_str() == Some(command)).unwrap();
+}
+
+// The events in order, as "stopped breakpoint" or "output 3\n"
+fn events(messages: &[Json]) -> Vec<String> {
+    let mut events = vec![];
+    for message in messages.iter().filter(|message| message.get("type").as_str() == Some("event")) {
+        let body = message.get("body");
+        let detail = body.get("reason").as_str().or(body.get("output").as_str()).map(String::from).or(body.get("exitCode").as_i64().map(|code| code.to_string()));
+        let event = message.get("event").as_str().unwrap();
+        match detail {
+            Some(detail) => events.push(format!("{} {}", event, detail)),
+            None => events.push(event.to_string()),
+        }
+    }
+    return events;
+}
+
+
+#[test]
+fn stops_at_breakpoints_and_sends_output() {
+    let program = script("breakpoints", "var a = 1;\n\nprint a;\nprint a + 1;\nprint a + 2;");
+    let messages = session(&[
+        "{\"command\": \"initialize\", \"arguments\": {}}",
+        &launch(&program, false),
+        "{\"command\": \"setBreakpoints\", \"arguments\": {\"breakpoints\": [{\"line\": 2}, {\"line\": 5}, {\"line\": 99}]}}",
+        "{\"command\": \"configurationDone\"}",
+        "{\"command\": \"stackTrace\", \"arguments\": {\"threadId\": 1}}",
+        "{\"command\": \"next\", \"arguments\": {\"threadId\": 1}}",
+        "{\"command\": \"continue\", \"arguments\": {\"threadId\": 1}}",
+        "{\"command\": \"continue\", \"arguments\": {\"threadId\": 1}}",
+        "{\"command\": \"disconnect\"}",
+    ]);
+
+    let breakpoints = response(&messages, "setBreakpoints").get("body").get("breakpoints").as_array();
+    // line 2 is blank so it moves to 3, and there's nothing at 99
+    assert_eq!(breakpoints[0].get("line").as_i64(), Some(3));
+    assert_eq!(breakpoints[1].get("line").as_i64(), Some(5));
+    assert_eq!(breakpoints[2].get("verified").as_bool(), Some(false));
+
+    let frame = &response(&messages, "stackTrace").get("body").get("stackFrames").as_array()[0];
+    assert_eq!(frame.get("line").as_i64(), Some(3));
+    assert_eq!(frame.get("source").get("path").as_str(), Some(program.as_str()));
+
+    assert_eq!(events(&messages), vec![
+        "initialized", "stopped breakpoint", "output 1\n", "stopped step", "output 2\n", "stopped breakpoint",
+        "output 3\n", "exited 0", "terminated",
+    ]);
+    assert_eq!(response(&messages, "disconnect").get("success").as_bool(), Some(true));
+}
+
+#[test]
+fn variables_and_evaluate_see_the_paused_script() {
+    let program = script("variables", "var a = \"hi\";\nvar b = 2;\nprint 10 + b * 3;");
+    let messages = session(&[
+        "{\"command\": \"initialize\", \"arguments\": {}}",
+        &launch(&program, true),
+        "{\"command\": \"configurationDone\"}",
+        "{\"command\": \"next\", \"arguments\": {\"threadId\": 1}}",
+        "{\"command\": \"next\", \"arguments\": {\"threadId\": 1}}",
+        "{\"command\": \"variables\", \"arguments\": {\"variablesReference\": 1}}",
+        "{\"command\": \"evaluate\", \"arguments\": {\"expression\": \"a + str(b)\"}}",
+        "{\"command\": \"evaluate\", \"arguments\": {\"expression\": \"a - 1\"}}",
+        "{\"command\": \"disconnect\"}",
+    ]);
+    assert_eq!(events(&messages), vec!["initialized", "stopped entry", "stopped step", "stopped step"]);
+
+    let variables = response(&messages, "variables").get("body").get("variables").as_array();
+    let a = variables.iter().find(|variable| variable.get("name").as_str() == Some("a")).unwrap();
+    assert_eq!(a.get("value").as_str(), Some("\"hi\""));
+
+    let evaluations: Vec<&Json> = messages.iter().filter(|message| message.get("command").as_str() == Some("evaluate")).collect();
+    assert_eq!(evaluations[0].get("body").get("result").as_str(), Some("\"hi2\""));
+    assert_eq!(evaluations[1].get("success").as_bool(), Some(false));
+}
+
+#[test]
+fn scopes_are_the_globals_and_the_stack() {
+    let program = script("stack", "print 1 + 2;");
+    let messages = session(&[
+        "{\"command\": \"initialize\", \"arguments\": {}}",
+        &launch(&program, true),
+        "{\"command\": \"configurationDone\"}",
+        "{\"command\": \"scopes\", \"arguments\": {\"frameId\": 1}}",
+        "{\"command\": \"variables\", \"arguments\": {\"variablesReference\": 2}}",
+        "{\"command\": \"stepIn\", \"arguments\": {\"threadId\": 1}}",
+    ]);
+    let scopes = response(&messages, "scopes").get("body").get("scopes").as_array();
+    assert_eq!(scopes[1].get("name").as_str(), Some("Stack"));
+    // paused before anything ran, and it all ran on one line so stepping finishes it
+    assert_eq!(response(&messages, "variables").get("body").get("variables").as_array().len(), 0);
+    assert_eq!(events(&messages).last().unwrap(), "terminated");
+}
+
+#[test]
+fn launch_failures_are_reported() {
+    let program = script("broken", "print (1;");
+    let messages = session(&[&launch(&program, false), "{\"command\": \"continue\", \"arguments\": {\"threadId\": 1}}"]);
+    let launched = response(&messages, "launch");
+    assert_eq!(launched.get("success").as_bool(), Some(false));
+    assert!(launched.get("message").as_str().unwrap().contains("Compile error"));
+    assert_eq!(response(&messages, "continue").get("success").as_bool(), Some(false));
+    assert!(events(&messages).is_empty());
+}
+
+#[test]
+fn exit_ends_the_session_not_the_adapter() {
+    let program = script("exit", "print \"before\";\nexit(3);\nprint \"after\";");
+    let messages = session_with(&[
+        "{\"command\": \"initialize\", \"arguments\": {}}",
+        &launch(&program, false),
+        "{\"command\": \"configurationDone\"}",
+        "{\"command\": \"threads\"}",
+        "{\"command\": \"disconnect\"}",
+    ], Profile::Full);
+    assert_eq!(events(&messages), vec!["initialized", "output before\n", "exited 3", "terminated"]);
+    // and it kept answering afterwards
+    assert_eq!(response(&messages, "threads").get("success").as_bool(), Some(true));
+    assert_eq!(response(&messages, "disconnect").get("success").as_bool(), Some(true));
+}
@@ -0,0 +1,101 @@
+use rlox::debugger::{breakpoint_line, current_line};
+use rlox::{Chunk, CompileOptions, Debugger, MemorySink, Step, Stopped, Value, Vm, compile_with_options, values_equal};
+
+
+const SCRIPT: &str = "var a = 1;\nvar b = 2;\n\nprint a + b;\nif (a < b) {\n  print \"less\";\n}\nprint b;";
+
+fn chunk_of(source: &str) -> Chunk {
+    let mut options = CompileOptions::default();
+    options.set_optimization_level(0);
+    return compile_with_options(source, &options).unwrap();
+}
+
+fn quiet_vm() -> (Vm, MemorySink) {
+    let mut vm = Vm::new();
+    let output = MemorySink::new();
+    vm.set_output(output.clone());
+    vm.set_errors(MemorySink::new());
+    return (vm, output);
+}
+
+
+#[test]
+fn breakpoints_pause_before_their_line() {
+    let chunk = chunk_of(SCRIPT);
+    let (mut vm, output) = quiet_vm();
+    let mut debugger = Debugger::new();
+    debugger.breakpoints.insert(4);
+    debugger.breakpoints.insert(8);
+
+    assert_eq!(debugger.start(&mut vm, &chunk, Step::Continue), Ok(Stopped::Breakpoint(4)));
+    assert_eq!(output.contents(), "");
+    assert!(values_equal(vm.get_global("b").unwrap(), Value::from_number(2.0)));
+    assert_eq!(debugger.resume(&mut vm, Step::Continue), Ok(Stopped::Breakpoint(8)));
+    assert_eq!(output.contents(), "3\nless\n");
+    assert_eq!(debugger.resume(&mut vm, Step::Continue), Ok(Stopped::Finished));
+    assert_eq!(output.contents(), "3\nless\n2\n");
+}
+
+#[test]
+fn stepping_goes_line_by_line_or_instruction_by_instruction() {
+    let chunk = chunk_of(SCRIPT);
+    let (mut vm, _) = quiet_vm();
+    let mut debugger = Debugger::new();
+    assert_eq!(debugger.start(&mut vm, &chunk, Step::Instruction), Ok(Stopped::Step));
+    assert_eq!(vm.ip, 0);
+
+    let mut lines = vec![current_line(&vm)];
+    while debugger.resume(&mut vm, Step::Line) == Ok(Stopped::Step) {
+        lines.push(current_line(&vm));
+    }
+    // the blank line and the closing brace don't have any code
+    assert_eq!(lines, vec![1, 2, 4, 5, 6, 8]);
+
+    let (mut vm, _) = quiet_vm();
+    debugger.start(&mut vm, &chunk, Step::Instruction).unwrap();
+    let mut offsets = vec![vm.ip];
+    while debugger.resume(&mut vm, Step::Instruction) == Ok(Stopped::Step) {
+        offsets.push(vm.ip);
+    }
+    // jumps only go forward, so every instruction that runs is further on than the last
+    assert!(offsets.len() > lines.len());
+    assert!(offsets.windows(2).all(|pair| pair[0] < pair[1]));
+}
+
+#[test]
+fn the_stack_can_be_seen_mid_expression() {
+    let chunk = chunk_of("print 1 + 2;");
+    let (mut vm, _) = quiet_vm();
+    let mut debugger = Debugger::new();
+    debugger.start(&mut vm, &chunk, Step::Instruction).unwrap();
+    debugger.resume(&mut vm, Step::Instruction).unwrap();
+    debugger.resume(&mut vm, Step::Instruction).unwrap();
+    // both constants are pushed and Add is next
+    assert_eq!(vm.stack.len(), 2);
+    assert!(values_equal(vm.stack[1].clone(), Value::from_number(2.0)));
+}
+
+#[test]
+fn expressions_evaluate_in_the_paused_script() {
+    let chunk = chunk_of(SCRIPT);
+    let (mut vm, output) = quiet_vm();
+    let mut debugger = Debugger::new();
+    debugger.breakpoints.insert(8);
+    debugger.start(&mut vm, &chunk, Step::Continue).unwrap();
+
+    let value = vm.eval_while_suspended("a * 10 + b").unwrap().unwrap();
+    assert!(values_equal(value, Value::from_number(12.0)));
+    // assignments stick, and the script carries on from where it was
+    vm.eval_while_suspended("b = 7;").unwrap();
+    assert!(vm.eval_while_suspended("nope").is_err());
+    assert_eq!(debugger.resume(&mut vm, Step::Continue), Ok(Stopped::Finished));
+    assert_eq!(output.contents(), "3\nless\n7\n");
+}
+
+#[test]
+fn breakpoints_on_lines_without_code_move_down() {
+    let chunk = chunk_of(SCRIPT);
+    assert_eq!(breakpoint_line(&chunk, 3), Some(4));
+    assert_eq!(breakpoint_line(&chunk, 7), Some(8));
+    assert_eq!(breakpoint_line(&chunk, 100), None);
+}
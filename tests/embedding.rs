@@ -0,0 +1,36 @@
+use rlox::{MemorySink, ValueKind, Vm};
+
+
+fn capturing_vm() -> (Vm, MemorySink) {
+    let mut vm = Vm::new();
+    let output = MemorySink::new();
+    vm.set_output(output.clone());
+    vm.set_errors(MemorySink::new());
+    return (vm, output);
+}
+
+
+#[test]
+fn expression_statements_leave_nothing_behind() {
+    let (mut vm, output) = capturing_vm();
+    let result = vm.interpret("var a = 1; a + a; a; \"s\" + \"t\"; print a;").unwrap();
+    assert_eq!(result.kind(), ValueKind::Null);
+    assert!(vm.stack.is_empty(), "{:?}", vm.stack);
+    assert_eq!(output.contents(), "1\n");
+}
+
+#[test]
+fn globals_go_both_ways() {
+    let (mut vm, output) = capturing_vm();
+    vm.set_global("x", 20.0);
+    vm.interpret("var y = x + 1; print y;").unwrap();
+    assert_eq!(output.contents(), "21\n");
+    assert_eq!(vm.get_global("y").map(|y| y.kind() == ValueKind::Number(21.0)), Some(true));
+    assert!(vm.get_global("z").is_none());
+
+    // a repl entry ending in an expression gives it back
+    let value = vm.eval("y * 2").unwrap().unwrap();
+    assert_eq!(value.kind(), ValueKind::Number(42.0));
+    assert!(vm.eval("var z = 1;").unwrap().is_none());
+    assert!(vm.stack.is_empty(), "{:?}", vm.stack);
+}
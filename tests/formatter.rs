@@ -0,0 +1,67 @@
+use rlox::format_source;
+
+
+const MESSY: &str = "
+
+// header
+var a=1;var b   =  -2 ;
+if(a<b){print \"less\";}else{
+print   \"more\"; // trailing
+
+
+
+print a*(b+ -3);}
+var c = len ( \"abc\" ) ;  print c;
+if a == 1 print \"one\";
+var d = a
+  // explain
+  + 2;
+";
+
+const TIDY: &str = "\
+// header
+var a = 1;
+var b = -2;
+if (a < b) {
+    print \"less\";
+} else {
+    print \"more\"; // trailing
+
+    print a * (b + -3);
+}
+var c = len(\"abc\");
+print c;
+if a == 1 print \"one\";
+var d = a
+    // explain
+    + 2;
+";
+
+
+#[test]
+fn lays_scripts_out_one_way() {
+    assert_eq!(format_source(MESSY).unwrap(), TIDY);
+}
+
+#[test]
+fn formatting_twice_changes_nothing() {
+    let workload = std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/benches/workload.lox")).unwrap();
+    for source in [MESSY, TIDY, &workload, "", "// only a comment", "{\n\n{ print \"a\nb\"; }\n\n}"] {
+        let once = format_source(source).unwrap();
+        assert_eq!(format_source(&once).unwrap(), once, "from {:?}", source);
+    }
+    // the benchmark script is already formatted
+    assert_eq!(format_source(&workload).unwrap(), workload);
+}
+
+#[test]
+fn nested_blocks_indent_and_keep_their_comments() {
+    let source = "if true { // why\nif false {\n// inside\n\n\nprint 1;\n}\n}\n";
+    assert_eq!(format_source(source).unwrap(), "if true { // why\n    if false {\n        // inside\n\n        print 1;\n    }\n}\n");
+}
+
+#[test]
+fn scripts_that_dont_compile_are_left_alone() {
+    assert!(format_source("print (1;").unwrap_err().contains("has to compile"));
+    assert!(format_source("print \"open;").is_err());
+}
@@ -0,0 +1,47 @@
+use rlox::json::{Json, parse_json};
+use rlox::protocol::{read_message, write_message};
+
+
+#[test]
+fn parses_everything_json_has() {
+    let json = parse_json(" {\"a\": [1, -2.5e3, true, false, null], \"b\": {\"c\": \"d\\n\\u00e9\\ud83d\\ude00\\/\"}, \"e\": {}} ").unwrap();
+    assert_eq!(json.get("a").as_array().len(), 5);
+    assert_eq!(json.get("a").as_array()[1].as_f64(), Some(-2500.0));
+    assert_eq!(json.get("a").as_array()[2].as_bool(), Some(true));
+    assert!(json.get("a").as_array()[4].is_null());
+    assert_eq!(json.get("b").get("c").as_str(), Some("d\n\u{e9}\u{1f600}/"));
+    assert_eq!(json.get("e"), &Json::Object(vec![]));
+    // missing keys are null all the way down
+    assert!(json.get("nope").get("deeper").is_null());
+}
+
+#[test]
+fn writing_and_parsing_round_trip() {
+    let json = Json::object(vec![
+        ("name", Json::from("quote \" backslash \\ tab\t")),
+        ("count", Json::from(3_i64)),
+        ("items", Json::from(vec![Json::from(0.5), Json::Null, Json::from(false)])),
+    ]);
+    assert_eq!(json.to_string(), "{\"name\":\"quote \\\" backslash \\\\ tab\\t\",\"count\":3,\"items\":[0.5,null,false]}");
+    assert_eq!(parse_json(&json.to_string()), Ok(json));
+}
+
+#[test]
+fn mistakes_are_errors() {
+    for text in ["", "{", "[1,]", "{\"a\" 1}", "\"open", "tru", "1 2", "{1: 2}"] {
+        assert!(parse_json(text).is_err(), "{:?} parsed", text);
+    }
+}
+
+#[test]
+fn messages_are_framed_with_content_length() {
+    let mut bytes = vec![];
+    write_message(&mut bytes, &Json::object(vec![("seq", Json::from(1_i64))])).unwrap();
+    assert_eq!(bytes, b"Content-Length: 9\r\n\r\n{\"seq\":1}");
+
+    bytes.extend(b"Content-Type: application/vscode-jsonrpc; charset=utf-8\r\ncontent-length: 2\r\n\r\n[]");
+    let mut input = &bytes[..];
+    assert_eq!(read_message(&mut input).unwrap().unwrap().get("seq").as_i64(), Some(1));
+    assert_eq!(read_message(&mut input).unwrap(), Some(Json::Array(vec![])));
+    assert_eq!(read_message(&mut input).unwrap(), None);
+}
@@ -0,0 +1,174 @@
+use std::time::Duration;
+
+use rlox::{Backend, Limit, LoxError, MemorySink, Vm};
+
+
+fn capturing_vm() -> (Vm, MemorySink) {
+    let mut vm = Vm::new();
+    let output = MemorySink::new();
+    vm.set_output(output.clone());
+    vm.set_errors(MemorySink::new());
+    return (vm, output);
+}
+
+fn limit_of(result: Result<impl std::fmt::Debug, LoxError>) -> Limit {
+    match result {
+        Err(LoxError::Limit { limit, .. }) => return limit,
+        other => panic!("Expected a limit to be hit, got {:?}", other),
+    }
+}
+
+fn long_string(length: usize) -> String {
+    return "x".repeat(length);
+}
+
+
+#[test]
+fn running_out_of_fuel_stops_between_instructions() {
+    let (mut vm, output) = capturing_vm();
+    vm.limits.fuel = Some(5);
+    // each print is a Constant then a Print
+    assert_eq!(limit_of(vm.interpret("print 1; print 2; print 3; print 4;")), Limit::Fuel);
+    assert_eq!(output.contents(), "1\n2\n");
+    assert!(vm.is_suspended());
+
+    // topping it up carries on from the instruction that didn't run, nothing runs twice
+    vm.add_fuel(1);
+    assert_eq!(limit_of(vm.resume()), Limit::Fuel);
+    assert_eq!(output.contents(), "1\n2\n3\n");
+    vm.add_fuel(100);
+    vm.resume().unwrap();
+    assert_eq!(output.contents(), "1\n2\n3\n4\n");
+    assert!(!vm.is_suspended());
+    assert_eq!(vm.limits.fuel, Some(98));
+}
+
+#[test]
+fn running_out_of_time_can_be_resumed_with_more() {
+    let (mut vm, output) = capturing_vm();
+    vm.limits.time = Some(Duration::ZERO);
+    assert_eq!(limit_of(vm.interpret("var a = 1; print a + 1;")), Limit::Time);
+    assert_eq!(output.contents(), "");
+
+    vm.limits.time = Some(Duration::from_secs(60));
+    vm.resume().unwrap();
+    assert_eq!(output.contents(), "2\n");
+}
+
+#[test]
+fn the_stack_getting_too_deep_can_be_resumed_with_more_room() {
+    let (mut vm, output) = capturing_vm();
+    vm.options.set_optimization_level(0);
+    vm.limits.max_stack = Some(2);
+    vm.interpret("print 1 + 2;").unwrap();
+    assert_eq!(limit_of(vm.interpret("print 1 + (2 + (3 + 4));")), Limit::StackDepth);
+    assert_eq!(output.contents(), "3\n");
+
+    vm.limits.max_stack = Some(4);
+    vm.resume().unwrap();
+    assert_eq!(output.contents(), "3\n10\n");
+}
+
+#[test]
+fn the_heap_limit_counts_what_the_vm_already_holds() {
+    // the script itself doesn't make anything, it's the global the host left there
+    let (mut vm, output) = capturing_vm();
+    vm.set_global("big", long_string(10_000));
+    vm.limits.max_heap_bytes = Some(1000);
+    assert_eq!(limit_of(vm.interpret("print 1;")), Limit::Heap);
+    assert_eq!(output.contents(), "");
+
+    // and the constants of the chunk
+    let (mut fresh, _) = capturing_vm();
+    fresh.limits.max_heap_bytes = Some(1000);
+    assert_eq!(limit_of(fresh.interpret(&format!("print \"{}\";", long_string(1000)))), Limit::Heap);
+}
+
+#[test]
+fn the_heap_limit_holds_across_repl_entries() {
+    let (mut vm, _) = capturing_vm();
+    vm.set_global("a", long_string(100));
+    vm.limits.max_heap_bytes = Some(2000);
+    let mut hit = None;
+    for i in 0..20 {
+        match vm.eval(&format!("var s{} = a + \"b\";", i)) {
+            Ok(_) => continue,
+            Err(LoxError::Limit { limit: Limit::Heap, .. }) => {
+                hit = Some(i);
+                break;
+            },
+            Err(error) => panic!("{}", error),
+        }
+    }
+    // every entry is small on its own, it's what they keep between them
+    let hit = hit.expect("Globals kept from earlier entries never hit the limit");
+    assert!(hit >= 2, "hit the limit at entry {}", hit);
+}
+
+#[test]
+fn garbage_doesnt_count_against_the_heap_limit() {
+    // more than 2000 bytes get made all told, but each one is dropped straight after
+    let (mut vm, output) = capturing_vm();
+    vm.limits.max_heap_bytes = Some(2000);
+    let source = format!("var a = \"{}\"; a + a; a + a; a + a; a + a; a + a; print len(a + a);", long_string(100));
+    vm.interpret(&source).unwrap();
+    assert_eq!(output.contents(), "200\n");
+}
+
+#[test]
+fn hitting_the_heap_limit_can_be_resumed_with_a_bigger_one() {
+    let (mut vm, output) = capturing_vm();
+    vm.set_global("a", long_string(100));
+    vm.limits.max_heap_bytes = Some(1000);
+    assert_eq!(limit_of(vm.interpret("print 1; var b = a + a; print len(b);")), Limit::Heap);
+    assert_eq!(output.contents(), "1\n");
+    assert_eq!(vm.get_global("b"), None);
+
+    vm.limits.max_heap_bytes = Some(100_000);
+    vm.resume().unwrap();
+    assert_eq!(output.contents(), "1\n200\n");
+}
+
+#[test]
+fn resuming_needs_something_stopped_by_a_limit() {
+    let (mut vm, _) = capturing_vm();
+    vm.interpret("print 1;").unwrap();
+    match vm.resume() {
+        Err(LoxError::Runtime { message, .. }) => assert_eq!(message, "There's nothing to resume"),
+        other => panic!("Expected a runtime error, got {:?}", other),
+    }
+}
+
+#[test]
+fn the_register_backend_stops_and_resumes_at_the_same_limits() {
+    let (mut vm, output) = capturing_vm();
+    vm.options.backend = Backend::Register;
+    vm.options.set_optimization_level(0);
+    // a print of a constant is one register instruction
+    vm.limits.fuel = Some(2);
+    assert_eq!(limit_of(vm.interpret("print 1; print 2; print 3;")), Limit::Fuel);
+    assert_eq!(output.contents(), "1\n2\n");
+    vm.add_fuel(10);
+    vm.resume().unwrap();
+    assert_eq!(output.contents(), "1\n2\n3\n");
+    vm.limits.fuel = None;
+
+    vm.limits.max_stack = Some(2);
+    assert_eq!(limit_of(vm.interpret("print 4; print 1 + (2 + (3 + 4));")), Limit::StackDepth);
+    vm.limits.max_stack = None;
+    vm.resume().unwrap();
+    assert_eq!(output.contents(), "1\n2\n3\n4\n10\n");
+
+    vm.set_global("a", long_string(100));
+    vm.limits.max_heap_bytes = Some(1000);
+    assert_eq!(limit_of(vm.interpret("var b = a + a; print len(b);")), Limit::Heap);
+    vm.limits.max_heap_bytes = None;
+    vm.resume().unwrap();
+    assert_eq!(output.contents(), "1\n2\n3\n4\n10\n200\n");
+
+    vm.limits.time = Some(Duration::ZERO);
+    assert_eq!(limit_of(vm.interpret("print 5;")), Limit::Time);
+    vm.limits.time = None;
+    vm.resume().unwrap();
+    assert_eq!(output.contents(), "1\n2\n3\n4\n10\n200\n5\n");
+}
@@ -0,0 +1,114 @@
+use rlox::{Finding, LintConfig, Severity, lint};
+
+
+// (rule, line) of everything found
+fn found(source: &str, config: &LintConfig) -> Vec<(&'static str, i64)> {
+    return lint(source, config).iter().map(|finding: &Finding| (finding.rule, finding.line)).collect();
+}
+
+
+#[test]
+fn every_rule_has_something_to_find() {
+    let source = "\
+var a = 1;
+var unused = 2;
+print a == a;
+print 1 == \"1\";
+if (true) print missing;
+a = a + 1;
+if (false) {
+    print a;
+}
+print len(\"ok\") < 3;
+{
+    return;
+    print a;
+}
+";
+    assert_eq!(found(source, &LintConfig::default()), vec![
+        ("unused-variable", 2),
+        ("self-comparison", 3),
+        ("mixed-type-comparison", 4),
+        ("constant-condition", 5),
+        ("undefined-global", 5),
+        ("constant-condition", 7),
+        ("dead-branch", 7),
+        ("syntax", 12),
+        ("unreachable-code", 13),
+    ]);
+    let findings = lint(source, &LintConfig::default());
+    assert_eq!(findings[0].to_string(), "2:5: warning[unused-variable]: `unused` is assigned but never read");
+    assert_eq!(findings[1].message, "`a == a` compares `a` with itself");
+    assert_eq!(findings[4].severity, Severity::Error);
+}
+
+#[test]
+fn config_changes_severities_and_adds_globals() {
+    let config = LintConfig::parse("# for the game\nunused-variable = off\nself-comparison = error\nglobals = player, score\n").unwrap();
+    let findings = lint("var x = player == player;\nprint score;", &config);
+    assert_eq!(findings.len(), 1);
+    assert_eq!((findings[0].rule, findings[0].severity), ("self-comparison", Severity::Error));
+
+    assert!(LintConfig::parse("no-such-rule = off").unwrap_err().contains("no rule called no-such-rule"));
+    assert!(LintConfig::parse("unused-variable = loud").unwrap_err().contains("line 1"));
+    assert!(LintConfig::parse("unused-variable").is_err());
+}
+
+#[test]
+fn comments_turn_rules_on_and_off() {
+    let source = "\
+var a = 1;
+print a == a; // lint: allow self-comparison
+// lint: allow
+print nope;
+print nope;
+// lint: off mixed-type-comparison
+print null != false;
+// lint: error unused-variable
+var late = 1;
+// lint: shout
+";
+    let findings = lint(source, &LintConfig::default());
+    let summary: Vec<(&str, i64, Severity)> = findings.iter().map(|finding| (finding.rule, finding.line, finding.severity)).collect();
+    assert_eq!(summary, vec![
+        ("undefined-global", 5, Severity::Error),
+        ("unused-variable", 9, Severity::Error),
+        ("lint-comment", 10, Severity::Warning),
+    ]);
+}
+
+#[test]
+fn only_literals_of_different_types_are_mixed() {
+    let config = LintConfig::default();
+    assert_eq!(found("print 1 == -2;\nprint \"a\" != \"b\";\nvar n = 1;\nprint n == \"1\";", &config), vec![]);
+    assert_eq!(found("print true != 1;\nprint (null) == \"\";\nprint 1 < \"2\";", &config), vec![
+        ("mixed-type-comparison", 1),
+        ("mixed-type-comparison", 2),
+    ]);
+}
+
+#[test]
+fn scripts_that_dont_parse_get_one_finding() {
+    assert_eq!(found("print (1;\nprint nope;", &LintConfig::default()), vec![("syntax", 1)]);
+    assert_eq!(found("print 1 @ 2;", &LintConfig::default()), vec![("syntax", 1)]);
+    // and the benchmark script has nothing wrong with it
+    let workload = std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/benches/workload.lox")).unwrap();
+    assert_eq!(lint(&workload, &LintConfig::default()), vec![]);
+}
+
+#[test]
+fn code_that_never_runs() {
+    let config = LintConfig::default();
+    assert_eq!(found("if (1) print 1; else print 2;\nif (nil_or_not) print 3; else print 4;", &config), vec![
+        ("constant-condition", 1),
+        ("dead-branch", 1),
+        ("undefined-global", 2),
+    ]);
+    // rlox won't run a return yet, that's said alongside what it would leave unreachable
+    let findings = lint("{\n    return 1;\n    print 3;\n    print 4;\n}\nprint 5;\n", &config);
+    let summary: Vec<(&str, i64)> = findings.iter().map(|finding| (finding.rule, finding.line)).collect();
+    assert_eq!(summary, vec![("syntax", 2), ("unreachable-code", 3), ("unreachable-code", 6)]);
+    assert!(findings[0].message.starts_with("rlox won't compile this: "), "{}", findings[0].message);
+    // the rest of the script still gets looked at
+    assert_eq!(found("print !true;\nprint nope;", &config), vec![("syntax", 1), ("undefined-global", 2)]);
+}
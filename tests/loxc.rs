@@ -0,0 +1,107 @@
+use std::rc::Rc;
+
+use rlox::{Chunk, CompileOptions, LoxError, MemorySink, NativeFunction, ObjData, Value, Vm, chunk_from_bytes, chunk_to_bytes, compile_with_options, values_equal};
+
+
+const SCRIPTS: &[&str] = &[
+    "print 1 + 2 * 3; print -4.25 / 2;",
+    "var name = \"world\"; print \"hello \" + name; print len(name);",
+    "var a = true; var b; if (a) { print b == null; } else { print \"no\"; } print a == false;",
+    "var x = 10; x = x - 1; if (x > 5) print str(x) + \"!\"; print type(x) == \"number\";",
+    "print 1;\nprint 2;\n\nprint \"three\" + 3;",
+];
+
+fn options_at(level: u8) -> CompileOptions {
+    let mut options = CompileOptions::default();
+    options.set_optimization_level(level);
+    return options;
+}
+
+// what running the chunk printed, and how it ended
+fn run(chunk: &Chunk) -> (String, Result<(), LoxError>) {
+    let mut vm = Vm::new();
+    let output = MemorySink::new();
+    vm.set_output(output.clone());
+    vm.set_errors(MemorySink::new());
+    let result = vm.run_chunk(chunk).map(|_| ());
+    return (output.contents(), result);
+}
+
+fn assert_same_chunk(loaded: &Chunk, chunk: &Chunk) {
+    assert_eq!(loaded.code, chunk.code);
+    assert_eq!(loaded.lines, chunk.lines);
+    assert_eq!(loaded.constants.len(), chunk.constants.len());
+    for (loaded_constant, constant) in loaded.constants.iter().zip(&chunk.constants) {
+        assert!(values_equal(loaded_constant.clone(), constant.clone()), "{:?} came back as {:?}", constant, loaded_constant);
+    }
+}
+
+fn compiled_bytes() -> Vec<u8> {
+    return chunk_to_bytes(&compile_with_options(SCRIPTS[1], &CompileOptions::default()).unwrap()).unwrap();
+}
+
+
+#[test]
+fn chunks_come_back_the_same_at_every_optimization_level() {
+    for level in 0..=2 {
+        for script in SCRIPTS {
+            let chunk = compile_with_options(script, &options_at(level)).unwrap();
+            let loaded = chunk_from_bytes(&chunk_to_bytes(&chunk).unwrap()).unwrap();
+            assert_same_chunk(&loaded, &chunk);
+        }
+    }
+}
+
+#[test]
+fn loaded_chunks_run_the_same_as_the_source() {
+    for level in 0..=2 {
+        for script in SCRIPTS {
+            let chunk = compile_with_options(script, &options_at(level)).unwrap();
+            let loaded = chunk_from_bytes(&chunk_to_bytes(&chunk).unwrap()).unwrap();
+            assert_eq!(run(&loaded), run(&chunk), "{} at -O{}", script, level);
+        }
+    }
+}
+
+#[test]
+fn runtime_errors_keep_their_lines() {
+    let chunk = compile_with_options(SCRIPTS[4], &CompileOptions::default()).unwrap();
+    let (output, result) = run(&chunk_from_bytes(&chunk_to_bytes(&chunk).unwrap()).unwrap());
+    assert_eq!(output, "1\n2\n");
+    assert!(matches!(result, Err(LoxError::Runtime { line: 4, .. })), "{:?}", result);
+}
+
+#[test]
+fn writing_is_deterministic() {
+    assert_eq!(compiled_bytes(), compiled_bytes());
+    let loaded = chunk_from_bytes(&compiled_bytes()).unwrap();
+    assert_eq!(chunk_to_bytes(&loaded).unwrap(), compiled_bytes());
+}
+
+#[test]
+fn bad_files_are_refused() {
+    let bytes = compiled_bytes();
+
+    let mut wrong_magic = bytes.clone();
+    wrong_magic[0] = b'X';
+    assert_eq!(chunk_from_bytes(&wrong_magic).unwrap_err(), "Not a .loxc file, it doesn't start with LOXC");
+
+    let mut newer = bytes.clone();
+    newer[4] = 99;
+    assert_eq!(chunk_from_bytes(&newer).unwrap_err(), "This .loxc file is format version 99, but this rlox reads version 1");
+
+    for length in 0..bytes.len() {
+        assert!(chunk_from_bytes(&bytes[..length]).is_err(), "Loaded a file cut off after {} bytes", length);
+    }
+
+    let mut junk = bytes.clone();
+    junk.push(0);
+    assert_eq!(chunk_from_bytes(&junk).unwrap_err(), "There's junk after the end of the chunk");
+}
+
+#[test]
+fn natives_cant_be_written() {
+    let native = NativeFunction { name: "nope".to_string(), arity: 0, function: Rc::new(|_args: &[Value]| Ok(Value::null())), flush_output: false };
+    let chunk = Chunk { code: vec![], lines: vec![], constants: vec![Value::from_obj(ObjData::Native(Rc::new(native)))] };
+    assert_eq!(chunk_to_bytes(&chunk).unwrap_err(), "Can't write the native function nope to a .loxc file");
+}
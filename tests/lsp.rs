@@ -0,0 +1,199 @@
+use rlox::{CompileOptions, LoxError, compile, compile_tokens};
+use rlox::json::{Json, parse_json};
+use rlox::lsp::Document;
+use rlox::protocol::{read_message, write_message};
+use rlox::scanner::{TokenType, scan_with_comments};
+
+
+const SOURCE: &str = "var count = 1;\n// a comment\nvar name = \"two\nlines\";\nif (count < 2) {\n  print name + \"é\";\n}\ncount = count + 1;\nprint len(name);\n";
+
+// Sends the messages to a server and gives back everything that came out of it
+fn session(messages: &[Json]) -> Vec<Json> {
+    let mut input = vec![];
+    for message in messages {
+        write_message(&mut input, message).unwrap();
+    }
+    let mut output = vec![];
+    rlox::lsp::serve(&mut &input[..], &mut output, &CompileOptions::default()).unwrap();
+
+    let mut received = vec![];
+    let mut output = &output[..];
+    while let Some(message) = read_message(&mut output).unwrap() {
+        received.push(message);
+    }
+    return received;
+}
+
+fn message(text: &str) -> Json {
+    let mut message = parse_json(text).unwrap();
+    if let Json::Object(fields) = &mut message {
+        fields.insert(0, ("jsonrpc".to_string(), Json::from("2.0")));
+    }
+    return message;
+}
+
+fn open(text: &str) -> Json {
+    let document = Json::object(vec![("uri", Json::from("file:///a.lox")), ("languageId", Json::from("lox")), ("version", Json::from(1_i64)), ("text", Json::from(text))]);
+    return Json::object(vec![("jsonrpc", Json::from("2.0")), ("method", Json::from("textDocument/didOpen")), ("params", Json::object(vec![("textDocument", document)]))]);
+}
+
+fn at(id: i64, method: &str, line: i64, character: i64) -> Json {
+    return message(&format!(
+        "{{\"id\": {}, \"method\": \"{}\", \"params\": {{\"textDocument\": {{\"uri\": \"file:///a.lox\"}}, \"position\": {{\"line\": {}, \"character\": {}}}}}}}",
+        id, method, line, character
+    ));
+}
+
+fn result(messages: &[Json], id: i64) -> &Json {
+    return messages.iter().find(|message| message.get("id").as_i64() == Some(id)).unwrap().get("result");
+}
+
+fn diagnostics(messages: &[Json]) -> Vec<&Json> {
+    return messages.iter().filter(|message| message.get("method").as_str() == Some("textDocument/publishDiagnostics")).collect();
+}
+
+// (line, character) pairs of where the ranges start
+fn starts(locations: &Json) -> Vec<(i64, i64)> {
+    return locations.as_array().iter().map(|location| {
+        let start = location.get("range").get("start");
+        (start.get("line").as_i64().unwrap(), start.get("character").as_i64().unwrap())
+    }).collect();
+}
+
+
+#[test]
+fn tokens_know_their_columns() {
+    let tokens = scan_with_comments("var a = 1;\n  print \"x\ny\" + a; // done");
+    let columns: Vec<(i64, usize, TokenType)> = tokens.iter().map(|token| (token.line, token.column, token.token_type)).collect();
+    assert_eq!(columns, vec![
+        (1, 1, TokenType::Var), (1, 5, TokenType::Identifier), (1, 7, TokenType::Equal), (1, 9, TokenType::Number), (1, 10, TokenType::Semicolon),
+        (2, 3, TokenType::Print), (2, 9, TokenType::String), (3, 4, TokenType::Plus), (3, 6, TokenType::Identifier), (3, 7, TokenType::Semicolon),
+        (3, 9, TokenType::Comment),
+    ]);
+}
+
+#[test]
+fn incremental_rescans_match_scanning_everything() {
+    let snippets = ["", "\"", "\n", "//", "var x", " = 12.5;", "}", "é", "print \"a\nb\";", "@"];
+    let mut document = Document::new(SOURCE);
+    let mut seed: u64 = 12345;
+    let mut random = |below: usize| {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        return (seed >> 33) as usize % below.max(1);
+    };
+    for _ in 0..500 {
+        let length = document.text().chars().count();
+        let start = random(length + 1);
+        let end = (start + random(6)).min(length);
+        let snippet = snippets[random(snippets.len())];
+        document.edit(start, end, snippet);
+        assert_eq!(document.tokens(), &scan_with_comments(&document.text())[..], "after putting {:?} at {}..{} of {:?}", snippet, start, end, document.text());
+        let line_starts: Vec<usize> = std::iter::once(0).chain(document.text().chars().enumerate().filter(|(_, c)| *c == '\n').map(|(index, _)| index + 1)).collect();
+        assert_eq!(document.line_starts(), &line_starts[..], "after putting {:?} at {}..{} of {:?}", snippet, start, end, document.text());
+    }
+}
+
+#[test]
+fn diagnostics_follow_edits() {
+    let change = message("{\"method\": \"textDocument/didChange\", \"params\": {\"textDocument\": {\"uri\": \"file:///a.lox\", \"version\": 2}, \"contentChanges\": [{\"range\": {\"start\": {\"line\": 1, \"character\": 0}, \"end\": {\"line\": 1, \"character\": 1}}, \"text\": \"\"}]}}");
+    let messages = session(&[open("print 1\n@ print 2;"), change, message("{\"method\": \"exit\"}")]);
+    let published = diagnostics(&messages);
+
+    // the scanner's errors come first, with exact ranges
+    let first = published[0].get("params").get("diagnostics").as_array();
+    assert_eq!(first.len(), 1);
+    assert_eq!(first[0].get("message").as_str(), Some("TOKEN ERROR: Unexpected character"));
+    assert_eq!(first[0].get("range").get("start").get("line").as_i64(), Some(1));
+    assert_eq!(first[0].get("range").get("start").get("character").as_i64(), Some(0));
+    assert_eq!(first[0].get("range").get("end").get("character").as_i64(), Some(1));
+
+    // and the compiler's once there aren't any, the missing semicolon is found at the next print
+    let second = published[1].get("params").get("diagnostics").as_array();
+    assert_eq!(second[0].get("range").get("start").get("line").as_i64(), Some(1));
+    assert!(second[0].get("message").as_str().unwrap().contains("semicolon"), "{}", second[0]);
+}
+
+#[test]
+fn a_full_constant_pool_is_a_diagnostic() {
+    let distinct: String = (0..300).map(|i| format!("print {};\n", i)).collect();
+    let repeated: String = (0..300).map(|_| "print i;\n").collect();
+    let change = |version: i64, text: &str| Json::object(vec![
+        ("jsonrpc", Json::from("2.0")),
+        ("method", Json::from("textDocument/didChange")),
+        ("params", Json::object(vec![
+            ("textDocument", Json::object(vec![("uri", Json::from("file:///a.lox")), ("version", Json::from(version))])),
+            ("contentChanges", Json::from(vec![Json::object(vec![("text", Json::from(text))])])),
+        ])),
+    ]);
+    let messages = session(&[open(&distinct), change(2, &repeated), message("{\"method\": \"exit\"}")]);
+    let published = diagnostics(&messages);
+    assert_eq!(published.len(), 2);
+
+    let first = published[0].get("params").get("diagnostics").as_array();
+    assert_eq!(first.len(), 1);
+    assert!(first[0].get("message").as_str().unwrap().contains("Too many constants"), "{}", first[0]);
+    assert_eq!(first[0].get("range").get("start").get("line").as_i64(), Some(255));
+    // the same name over and over only needs the one constant
+    assert!(published[1].get("params").get("diagnostics").as_array().is_empty());
+}
+
+#[test]
+fn comments_are_skipped_by_the_compiler() {
+    let source = "// first\nvar a = 1; // after\n// between\nprint a\n// in the middle\n+ 1;\n// last";
+    let with_comments = compile_tokens(&scan_with_comments(source), &CompileOptions::default()).unwrap();
+    let without = compile(source).unwrap();
+    assert_eq!(with_comments.code, without.code);
+    assert_eq!(with_comments.lines, without.lines);
+    assert!(compile_tokens(&scan_with_comments("// nothing but this"), &CompileOptions::default()).unwrap().code.is_empty());
+    // running out of tokens is blamed on the last line with code on it
+    let error = compile_tokens(&scan_with_comments("print 1 // no semicolon\n// not this line"), &CompileOptions::default());
+    assert!(matches!(error, Err(LoxError::Compile { line: 1, .. })), "{:?}", error.map(|_| ()));
+}
+
+#[test]
+fn navigation_finds_var_declarations() {
+    let messages = session(&[
+        message("{\"id\": 1, \"method\": \"initialize\", \"params\": {}}"),
+        open(SOURCE),
+        at(2, "textDocument/hover", 8, 11),
+        at(3, "textDocument/definition", 7, 9),
+        at(4, "textDocument/references", 4, 5),
+        at(5, "textDocument/hover", 8, 7),
+        message("{\"id\": 6, \"method\": \"textDocument/documentSymbol\", \"params\": {\"textDocument\": {\"uri\": \"file:///a.lox\"}}}"),
+        message("{\"id\": 7, \"method\": \"shutdown\"}"),
+        message("{\"method\": \"exit\"}"),
+    ]);
+    assert_eq!(result(&messages, 1).get("capabilities").get("textDocumentSync").get("change").as_i64(), Some(2));
+    assert!(diagnostics(&messages)[0].get("params").get("diagnostics").as_array().is_empty());
+
+    let hover = result(&messages, 2).get("contents").get("value").as_str().unwrap();
+    assert_eq!(hover, "```lox\nvar name = \"two\n```\ndeclared on line 3");
+    assert_eq!(starts(result(&messages, 3)), vec![(0, 4)]);
+    assert_eq!(starts(result(&messages, 4)), vec![(0, 4), (4, 4), (7, 0), (7, 8)]);
+    assert!(result(&messages, 5).get("contents").get("value").as_str().unwrap().contains("built in"));
+
+    let symbols = result(&messages, 6).as_array();
+    let names: Vec<&str> = symbols.iter().map(|symbol| symbol.get("name").as_str().unwrap()).collect();
+    assert_eq!(names, vec!["count", "name"]);
+    assert!(result(&messages, 7).is_null());
+}
+
+#[test]
+fn semantic_tokens_are_relative() {
+    let messages = session(&[
+        open("var a = \"x\ny\"; // c\nprint a;"),
+        message("{\"id\": 1, \"method\": \"textDocument/semanticTokens/full\", \"params\": {\"textDocument\": {\"uri\": \"file:///a.lox\"}}}"),
+    ]);
+    let data: Vec<i64> = result(&messages, 1).get("data").as_array().iter().map(|number| number.as_i64().unwrap()).collect();
+    // keyword, declared variable, operator, the string in two pieces, comment, keyword, variable
+    assert_eq!(data, vec![
+        0, 0, 3, 0, 0,
+        0, 4, 1, 1, 1,
+        0, 2, 1, 4, 0,
+        0, 2, 2, 2, 0,
+        1, 0, 2, 2, 0,
+        0, 4, 4, 5, 0,
+        1, 0, 5, 0, 0,
+        0, 6, 1, 1, 0,
+    ]);
+}
@@ -0,0 +1,233 @@
+use rlox::chunk::jump_target;
+use rlox::disassembler::size_at;
+use rlox::optimizer::{fold_constants, fuse_superinstructions};
+use rlox::{Chunk, CompileOptions, LoxError, MemorySink, OpCode, Value, Vm, assemble, compile_with_options, disassemble};
+
+
+// Scripts that give every pass something to do
+const SCRIPTS: &[&str] = &[
+    "print 1 + 2 * 3; print -4.25 / 2; print (1 + 2) * (3 - 4);",
+    "print \"a\" + \"b\" + \"c\"; print 1 < 2; print 2 <= 1; print 3 > 3; print 3 >= 3; print 1 != 2; print \"a\" == \"a\";",
+    "var a = 1; var b = 2;\nif (a < b) {\n  if (b > a) { print \"inner\"; } else { print \"inner else\"; }\n} else {\n  print \"outer else\";\n}\na = 5;\nprint a;",
+    "var name = \"world\"; print \"hello \" + name; print len(name) == 5; print str(len(name) + 1);",
+    "if (1 < 2) print \"yes\"; else print \"no\"; if (false) print 1; if (null) print 2; else print 3;",
+    "var x = 1; x = x + 1; x = x + 1; print x; print x + 10; 1 + 2; x;",
+];
+
+fn options(fold: bool, peephole: bool, superinstructions: bool) -> CompileOptions {
+    return CompileOptions { fold: fold, peephole: peephole, superinstructions: superinstructions, ..Default::default() };
+}
+
+fn output_of(chunk: &Chunk) -> String {
+    let mut vm = Vm::new();
+    let output = MemorySink::new();
+    vm.set_output(output.clone());
+    vm.set_errors(MemorySink::new());
+    if let Err(error) = vm.run_chunk(chunk) {
+        return format!("{}{}\n", output.contents(), error);
+    }
+    return output.contents();
+}
+
+// The instructions without offsets and lines, like "Constant 0 ; 7"
+fn instructions(chunk: &Chunk) -> Vec<String> {
+    let listing = disassemble(chunk, "test");
+    let code = listing.split("code:\n").nth(1).unwrap();
+    return code.lines().map(|line| line.split_whitespace().skip(2).collect::<Vec<&str>>().join(" ")).collect();
+}
+
+// (offset, opcode) of every instruction
+fn opcodes(chunk: &Chunk) -> Vec<(usize, u8)> {
+    let mut opcodes = vec![];
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        opcodes.push((offset, chunk.code[offset]));
+        offset += size_at(chunk, offset);
+    }
+    return opcodes;
+}
+
+fn is_jump(opcode: u8) -> bool {
+    return opcode == OpCode::Jump as u8 || opcode == OpCode::JumpIfFalse as u8;
+}
+
+
+#[test]
+fn folding_replaces_literal_arithmetic_with_its_result() {
+    let chunk = compile_with_options("print 1 + 2 * 3; print \"a\" + \"b\"; print -(2 - 4) == 2;", &options(true, false, false)).unwrap();
+    assert_eq!(instructions(&chunk), vec!["Constant 0 ; 7", "Print", "Constant 1 ; \"ab\"", "Print", "True", "Print"]);
+}
+
+#[test]
+fn folding_leaves_what_would_fail_at_runtime() {
+    // these have to go on failing when they run, not when they compile
+    for source in ["print -\"a\";", "print \"a\" - \"b\";", "print \"a\" < \"b\";", "print 1 + \"a\";"] {
+        let unfolded = compile_with_options(source, &options(false, false, false)).unwrap();
+        let folded = compile_with_options(source, &options(true, false, false)).unwrap();
+        assert_eq!(instructions(&folded), instructions(&unfolded), "{}", source);
+        assert!(output_of(&folded).contains("Runtime error"), "{}", source);
+    }
+}
+
+#[test]
+fn folded_scripts_print_the_same_thing() {
+    for source in SCRIPTS {
+        let unfolded = compile_with_options(source, &options(false, false, false)).unwrap();
+        let folded = compile_with_options(source, &options(true, false, false)).unwrap();
+        assert!(folded.code.len() <= unfolded.code.len(), "{}", source);
+        assert_eq!(output_of(&folded), output_of(&unfolded), "{}", source);
+    }
+}
+
+#[test]
+fn folding_that_would_overflow_the_pool_is_skipped() {
+    // a full pool of powers of two, each one printed and then added to the next, the sums would
+    // need another 255 slots
+    let mut chunk = Chunk { code: vec![], lines: vec![], constants: vec![] };
+    for i in 0..255u8 {
+        chunk.constants.push(Value::from_number(2f64.powi(i as i32)));
+        chunk.code.extend([OpCode::Constant as u8, i, OpCode::Print as u8]);
+        chunk.code.extend([OpCode::Constant as u8, i, OpCode::Constant as u8, (i + 1) % 255, OpCode::Add as u8, OpCode::Pop as u8]);
+        chunk.lines.extend([1; 9]);
+    }
+    let before = chunk.code.clone();
+    fold_constants(&mut chunk);
+    assert_eq!(chunk.code, before);
+    assert_eq!(chunk.constants.len(), 255);
+}
+
+#[test]
+fn constants_share_slots() {
+    let chunk = compile_with_options("var a = 1; print a; a = a + 1; print \"s\" + \"s\"; print 1;", &options(false, false, false)).unwrap();
+    assert_eq!(chunk.constants.len(), 3);
+    // but 0 and -0 aren't the same constant
+    let chunk = compile_with_options("print 0; print -0;", &options(true, false, false)).unwrap();
+    assert_eq!(output_of(&chunk), "0\n-0\n");
+}
+
+#[test]
+fn too_many_constants_is_a_compile_error() {
+    // 300 different numbers don't fit
+    let source: String = (0..300).map(|i| format!("print {};\n", i)).collect();
+    match compile_with_options(&source, &options(false, false, false)) {
+        Err(LoxError::Compile { line, message }) => {
+            assert_eq!(line, 256);
+            assert!(message.contains("Too many constants"), "{}", message);
+        },
+        other => panic!("Expected a compile error, got {:?}", other.map(|_| ())),
+    }
+    // but the same name 300 times is one constant
+    let source: String = (0..300).map(|_| "x = x + 1;\n").collect();
+    let chunk = compile_with_options(&format!("var x = 0;\n{}print x;", source), &options(true, true, true)).unwrap();
+    assert_eq!(output_of(&chunk), "300\n");
+}
+
+#[test]
+fn peephole_fuses_negated_comparisons() {
+    let source = "var a = 1; print a <= 2; print a >= 2; print a != 2;";
+    let plain = compile_with_options(source, &options(false, false, false)).unwrap();
+    let optimized = compile_with_options(source, &options(false, true, false)).unwrap();
+    assert_eq!(instructions(&plain).iter().filter(|instruction| *instruction == "Not").count(), 3);
+    let fused: Vec<String> = instructions(&optimized).into_iter().filter(|instruction| !instruction.contains(' ') && instruction != "Print").collect();
+    assert_eq!(fused, vec!["LessEqual", "GreaterEqual", "NotEqual"]);
+    assert_eq!(output_of(&optimized), "true\nfalse\ntrue\n");
+}
+
+#[test]
+fn peephole_drops_pops_of_literals_but_not_of_globals() {
+    // reading a global can fail, so that has to stay
+    let optimized = compile_with_options("var a = 1; 1; \"s\"; true; a;", &options(false, true, false)).unwrap();
+    assert_eq!(instructions(&optimized), vec!["Constant 0 ; 1", "DefineGlobal 1 ; a", "GetGlobal 1 ; a", "Pop"]);
+}
+
+#[test]
+fn peephole_threads_jumps_to_jumps() {
+    let source = "var a = 1;\nif (a < 2) { if (a > 0) print \"x\"; else print \"y\"; } else print \"z\";";
+    let plain = compile_with_options(source, &options(false, false, false)).unwrap();
+    let optimized = compile_with_options(source, &options(false, true, false)).unwrap();
+    let lands_on_jump = |chunk: &Chunk| opcodes(chunk).iter().any(|(offset, opcode)| {
+        is_jump(*opcode) && jump_target(chunk, *offset) < chunk.code.len() && is_jump(chunk.code[jump_target(chunk, *offset)])
+    });
+    assert!(lands_on_jump(&plain));
+    assert!(!lands_on_jump(&optimized));
+    assert_eq!(output_of(&optimized), output_of(&plain));
+}
+
+#[test]
+fn peephole_scripts_print_the_same_thing() {
+    for source in SCRIPTS {
+        let plain = compile_with_options(source, &options(true, false, false)).unwrap();
+        let optimized = compile_with_options(source, &options(true, true, false)).unwrap();
+        assert_eq!(output_of(&optimized), output_of(&plain), "{}", source);
+    }
+}
+
+#[test]
+fn no_peephole_flag_turns_it_off() {
+    let path = std::env::temp_dir().join(format!("rlox-optimizer-{}.lox", std::process::id()));
+    std::fs::write(&path, "var a = 1; print a != 2;").unwrap();
+    let listing = |flags: &[&str]| {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_rlox")).args(flags).arg("disasm").arg(&path).output().unwrap();
+        return String::from_utf8(output.stdout).unwrap();
+    };
+    assert!(listing(&[]).contains("NotEqual"));
+    let unfused = listing(&["--no-peephole"]);
+    assert!(!unfused.contains("NotEqual") && unfused.contains("Not"), "{}", unfused);
+}
+
+#[test]
+fn superinstructions_replace_the_pairs_they_stand_for() {
+    let source = "var a = 1;\nprint a;\nprint 2;\na = 3;\nprint a + 1;";
+    let fused = compile_with_options(source, &options(true, true, true)).unwrap();
+    assert_eq!(instructions(&fused), vec![
+        "Constant 0 ; 1", "DefineGlobal 1 ; a", "GetGlobalPrint 1 ; a", "ConstantPrint 2 ; 2", "Constant 3 ; 3", "SetGlobalPop 1 ; a",
+        "GetGlobal 1 ; a", "ConstantAdd 0 ; 1", "Print",
+    ]);
+    assert_eq!(output_of(&fused), "1\n2\n4\n");
+}
+
+#[test]
+fn fused_scripts_print_the_same_thing() {
+    for source in SCRIPTS {
+        let unfused = compile_with_options(source, &options(true, true, false)).unwrap();
+        let fused = compile_with_options(source, &options(true, true, true)).unwrap();
+        assert!(fused.code.len() < unfused.code.len(), "nothing fused in {}", source);
+        assert_eq!(output_of(&fused), output_of(&unfused), "{}", source);
+    }
+}
+
+#[test]
+fn fusing_keeps_jump_targets() {
+    // the jump lands on the Print, so Constant "b" and Print can't become one ConstantPrint
+    let mut chunk = assemble("\
+constants:
+    0  \"a\"
+    1  \"b\"
+code:
+0000     1  Constant       0
+0002     |  False
+0003     |  JumpIfFalse    L0
+0006     |  Pop
+0007     |  Constant       1
+L0:
+0009     |  Print
+").unwrap();
+    assert_eq!(output_of(&chunk), "a\n");
+    fuse_superinstructions(&mut chunk);
+    assert!(!instructions(&chunk).iter().any(|instruction| instruction.starts_with("ConstantPrint")));
+    assert_eq!(output_of(&chunk), "a\n");
+
+    // and jumps over fused code still land where they should
+    let source = "var a = 1;\nif (a < 2) { print a; print 2; a = 3; } else { print \"no\"; }\nprint a + 1;\nif (a > 5) print a; else print 9;";
+    let unfused = compile_with_options(source, &options(true, true, false)).unwrap();
+    let fused = compile_with_options(source, &options(true, true, true)).unwrap();
+    assert!(instructions(&fused).iter().any(|instruction| instruction.starts_with("GetGlobalPrint")));
+    for (offset, opcode) in opcodes(&fused) {
+        if is_jump(opcode) {
+            let target = jump_target(&fused, offset);
+            assert!(target == fused.code.len() || opcodes(&fused).iter().any(|(start, _)| *start == target), "jump at {} lands mid instruction", offset);
+        }
+    }
+    assert_eq!(output_of(&fused), output_of(&unfused));
+    assert_eq!(output_of(&fused), "1\n2\n4\n9\n");
+}
@@ -0,0 +1,100 @@
+use std::io::{Read, Write};
+use std::process::{ChildStdout, Command, Stdio};
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
+
+
+// somewhere no test would have made on purpose
+fn scratch_path(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("rlox-output-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_file(&path);
+    return path.to_string_lossy().to_string();
+}
+
+// Everything the child prints, as it comes
+fn reader(mut stdout: ChildStdout) -> Receiver<String> {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let mut buffer = [0; 256];
+        while let Ok(count @ 1..) = stdout.read(&mut buffer) {
+            let _ = sender.send(String::from_utf8_lossy(&buffer[..count]).to_string());
+        }
+    });
+    return receiver;
+}
+
+// What came through once `expected` is in it, or once 10 seconds have gone by without it
+fn read_until(receiver: &Receiver<String>, expected: &str) -> String {
+    let mut seen = String::new();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !seen.contains(expected) && Instant::now() < deadline {
+        if let Ok(text) = receiver.recv_timeout(Duration::from_millis(50)) {
+            seen.push_str(&text);
+        }
+    }
+    return seen;
+}
+
+
+#[test]
+fn output_comes_out_before_the_error_that_stopped_it() {
+    // both into the one pipe, stdout's buffered there so it's on the vm to flush it first
+    let path = scratch_path("order.lox");
+    std::fs::write(&path, "print 1;\nprint 2;\nprint -\"a\";\nprint 3;\n").unwrap();
+    let output = Command::new("sh").arg("-c").arg("\"$0\" \"$1\" 2>&1").arg(env!("CARGO_BIN_EXE_rlox")).arg(&path).output().unwrap();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\n2\n[line 3] Runtime error: You can't negate an object!\n");
+}
+
+#[test]
+fn prints_show_up_straight_away_on_a_terminal() {
+    // `script` gives the vm a terminal, the script then waits on a fifo nobody writes to until
+    // the test has seen the first print
+    let fifo = scratch_path("fifo");
+    let path = scratch_path("tty.lox");
+    assert!(Command::new("mkfifo").arg(&fifo).status().unwrap().success());
+    std::fs::write(&path, format!("print \"before\";\nprint read_file(\"{}\");\n", fifo)).unwrap();
+    let command = format!("{} {}", env!("CARGO_BIN_EXE_rlox"), path);
+    let mut child = Command::new("script").args(["-qfec", &command, "/dev/null"])
+        .stdin(Stdio::null()).stdout(Stdio::piped()).spawn().unwrap();
+
+    let seen = read_until(&reader(child.stdout.take().unwrap()), "before");
+
+    // the terminal going away takes the vm with it
+    let _ = child.kill();
+    let _ = child.wait();
+    let _ = std::fs::remove_file(&fifo);
+    let _ = std::fs::remove_file(&path);
+    assert!(seen.contains("before"), "nothing was printed while the script waited, got {:?}", seen);
+}
+
+#[test]
+fn exit_lets_out_what_was_printed_first() {
+    let path = scratch_path("exit.lox");
+    std::fs::write(&path, "print \"before\";\nexit(3);\nprint \"after\";\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_rlox")).arg(&path).output().unwrap();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "before\n");
+}
+
+#[test]
+fn input_shows_the_prompt_before_it_waits() {
+    let path = scratch_path("input.lox");
+    std::fs::write(&path, "print \"name?\";\nvar name = input();\nprint \"hi \" + name;\n").unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rlox")).arg(&path)
+        .stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().unwrap();
+
+    // nothing's been typed yet, so the prompt can only have come out before input() started waiting
+    let output = reader(child.stdout.take().unwrap());
+    let prompt = read_until(&output, "name?");
+    let mut stdin = child.stdin.take().unwrap();
+    let _ = stdin.write_all(b"lox\n");
+    drop(stdin);
+    let rest = read_until(&output, "hi lox");
+    let _ = child.wait();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(prompt, "name?\n");
+    assert_eq!(rest, "hi lox\n");
+}
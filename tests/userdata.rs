@@ -0,0 +1,142 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use rlox::{FromLox, LoxError, MemorySink, UserData, Value, Vm};
+
+
+struct Counter {
+    count: Cell<f64>,
+}
+
+impl UserData for Counter {
+    fn type_name(&self) -> &str {
+        return "Counter";
+    }
+
+    fn call_method(&self, name: &str, args: &[Value]) -> Result<Value, String> {
+        match name {
+            "add" => {
+                self.count.set(self.count.get() + f64::from_lox(&args[0])?);
+                return Ok(Value::null());
+            },
+            "get" => return Ok(Value::from_number(self.count.get())),
+            _ => return Err(format!("Counter has no method {}", name)),
+        }
+    }
+}
+
+// Two points are equal when they're at the same place, not only when they're the same object
+struct Point {
+    x: f64,
+    y: f64,
+}
+
+impl UserData for Point {
+    fn type_name(&self) -> &str {
+        return "Point";
+    }
+
+    fn display(&self) -> String {
+        return format!("<point {} {}>", self.x, self.y);
+    }
+
+    fn equals(&self, other: &dyn UserData) -> bool {
+        let other = other as &dyn std::any::Any;
+        match other.downcast_ref::<Point>() {
+            Some(other) => return self.x == other.x && self.y == other.y,
+            None => return false,
+        }
+    }
+}
+
+
+fn vm() -> (Vm, MemorySink) {
+    let mut vm = Vm::new();
+    let output = MemorySink::new();
+    vm.set_output(output.clone());
+    vm.set_errors(MemorySink::new());
+    return (vm, output);
+}
+
+fn runtime_error(vm: &mut Vm, source: &str) -> String {
+    match vm.interpret(source) {
+        Err(LoxError::Runtime { message, .. }) => return message,
+        other => panic!("Expected a runtime error from {:?}, got {:?}", source, other),
+    }
+}
+
+
+#[test]
+fn userdata_is_the_same_object_on_both_sides() {
+    let (mut vm, output) = vm();
+    let counter = Rc::new(Counter { count: Cell::new(0.0) });
+    vm.set_global("a", counter.clone());
+    vm.set_global("b", Rc::new(Counter { count: Cell::new(0.0) }));
+    vm.interpret("var c = a; print a == c; print a == b; print a != b; print a; print type(a);").unwrap();
+    assert_eq!(output.contents(), "true\nfalse\ntrue\n<Counter>\nCounter\n");
+
+    let back = Rc::<Counter>::from_lox(&vm.get_global("c").unwrap()).unwrap();
+    assert!(Rc::ptr_eq(&back, &counter));
+}
+
+#[test]
+fn userdata_can_decide_what_equal_means() {
+    let (mut vm, output) = vm();
+    vm.set_global("p", Rc::new(Point { x: 1.0, y: 2.0 }));
+    vm.set_global("q", Rc::new(Point { x: 1.0, y: 2.0 }));
+    vm.set_global("r", Rc::new(Point { x: 3.0, y: 2.0 }));
+    vm.set_global("c", Rc::new(Counter { count: Cell::new(0.0) }));
+    vm.interpret("print p == q; print p == r; print p == c; print c == p; print p == 1; print p;").unwrap();
+    assert_eq!(output.contents(), "true\nfalse\nfalse\nfalse\nfalse\n<point 1 2>\n");
+}
+
+#[test]
+fn methods_go_to_the_host_object() {
+    let (mut vm, output) = vm();
+    let counter = Rc::new(Counter { count: Cell::new(0.0) });
+    vm.set_global("counter", counter.clone());
+    vm.interpret("counter.add(2); counter.add(3); print counter.get();").unwrap();
+    assert_eq!(output.contents(), "5\n");
+    assert_eq!(counter.count.get(), 5.0);
+
+    assert_eq!(runtime_error(&mut vm, "counter.reset();"), "Counter has no method reset");
+    assert_eq!(runtime_error(&mut vm, "counter.add(\"x\");"), "expected a number but got \"x\"");
+    assert_eq!(runtime_error(&mut vm, "print \"s\".get();"), "Only userdata has methods, \"s\" doesn't");
+    // Point leaves call_method to the default, which says it doesn't have any
+    vm.set_global("p", Rc::new(Point { x: 0.0, y: 0.0 }));
+    assert_eq!(runtime_error(&mut vm, "p.get();"), "Point has no method get");
+}
+
+#[test]
+fn natives_check_the_type_of_each_argument() {
+    let (mut vm, output) = vm();
+    vm.register_native("bump", |counter: Rc<Counter>, by: f64| counter.count.set(counter.count.get() + by));
+    vm.register_native("x_of", |point: Rc<Point>| point.x);
+    vm.set_global("c", Rc::new(Counter { count: Cell::new(1.0) }));
+    vm.set_global("p", Rc::new(Point { x: 4.0, y: 0.0 }));
+    vm.interpret("bump(c, 2); print c.get(); print x_of(p);").unwrap();
+    assert_eq!(output.contents(), "3\n4\n");
+
+    let message = runtime_error(&mut vm, "x_of(c);");
+    assert!(message.starts_with("x_of() argument 1: expected a ") && message.ends_with("Point but got <Counter>"), "{}", message);
+    let message = runtime_error(&mut vm, "bump(c, true);");
+    assert_eq!(message, "bump() argument 2: expected a number but got true");
+    let message = runtime_error(&mut vm, "bump(1, 1);");
+    assert!(message.starts_with("bump() argument 1: expected a ") && message.ends_with("Counter but got 1"), "{}", message);
+}
+
+#[test]
+fn natives_check_how_many_arguments_they_got() {
+    let (mut vm, output) = vm();
+    vm.register_native("add", |a: f64, b: f64| a + b);
+    vm.register_native("nothing", || ());
+    vm.register_native("fails", |message: String| -> Result<f64, String> { Err(message) });
+    vm.interpret("print add(1, 2); print nothing();").unwrap();
+    assert_eq!(output.contents(), "3\nnull\n");
+
+    assert_eq!(runtime_error(&mut vm, "add(1);"), "add() expected 2 arguments but got 1");
+    assert_eq!(runtime_error(&mut vm, "add(1, 2, 3);"), "add() expected 2 arguments but got 3");
+    assert_eq!(runtime_error(&mut vm, "nothing(1);"), "nothing() expected 0 arguments but got 1");
+    assert_eq!(runtime_error(&mut vm, "fails(\"no luck\");"), "no luck");
+    assert_eq!(runtime_error(&mut vm, "1(2);"), "Can only call functions, 1 isn't one");
+}
@@ -0,0 +1,69 @@
+use std::rc::Rc;
+
+use rlox::{NativeFunction, ObjData, Value, ValueKind, get_value_str, values_equal};
+
+
+// Runs against whichever layout the tests were built with, `cargo test --features nan-boxing`
+// for the packed one
+
+fn number(value: &Value) -> f64 {
+    match value.kind() {
+        ValueKind::Number(num) => return num,
+        other => panic!("Expected a number, got {:?}", other),
+    }
+}
+
+
+#[test]
+fn numbers_come_back_bit_for_bit() {
+    for num in [0.0, -0.0, 1.5, -2.25, f64::MAX, f64::MIN_POSITIVE, f64::INFINITY, f64::NEG_INFINITY, 5e-324] {
+        let value = Value::Number(num);
+        assert_eq!(number(&value).to_bits(), num.to_bits(), "{}", num);
+        assert_eq!(number(&value.clone()).to_bits(), num.to_bits(), "{}", num);
+    }
+    // -0.0 keeps its sign but is still equal to 0
+    assert!(number(&Value::from_number(-0.0)).is_sign_negative());
+    assert!(values_equal(Value::Number(-0.0), Value::Number(0.0)));
+}
+
+#[test]
+fn nans_stay_numbers() {
+    // whatever bits a NaN has, it mustn't come back as null, a bool or an object
+    let quiet_with_payload = f64::from_bits(0x7ffc000000000003);
+    let negative = f64::from_bits(0xfffc000000000001);
+    for nan in [f64::NAN, -f64::NAN, quiet_with_payload, negative, f64::INFINITY - f64::INFINITY] {
+        let value = Value::Number(nan);
+        assert!(number(&value).is_nan());
+        assert!(!values_equal(value.clone(), value.clone()));
+        assert_eq!(get_value_str(&value), "NaN");
+    }
+}
+
+#[test]
+fn bools_and_null_round_trip() {
+    assert_eq!(Value::Bool(true).kind(), ValueKind::Bool(true));
+    assert_eq!(Value::from_bool(false).kind(), ValueKind::Bool(false));
+    assert_eq!(Value::Null.kind(), ValueKind::Null);
+    assert_eq!(Value::null().kind(), ValueKind::Null);
+    assert!(!values_equal(Value::Null, Value::Bool(false)));
+    assert!(!values_equal(Value::Number(0.0), Value::Bool(false)));
+}
+
+#[test]
+fn objects_round_trip_and_are_freed() {
+    let value = Value::Obj(ObjData::String("héllo".chars().collect()));
+    let copy = value.clone();
+    drop(value);
+    assert_eq!(copy.kind(), ValueKind::Obj(&ObjData::String("héllo".chars().collect())));
+    assert_eq!(get_value_str(&copy), "héllo");
+
+    // anything the object holds on to is let go once the last value pointing at it is dropped
+    let native = Rc::new(NativeFunction { name: "f".to_string(), arity: 0, function: Rc::new(|_: &[Value]| Ok(Value::Null)), flush_output: false });
+    let values: Vec<Value> = (0..10).map(|_| Value::from_obj(ObjData::Native(native.clone()))).collect();
+    let copies = values.clone();
+    assert!(Rc::strong_count(&native) > 1);
+    drop(values);
+    assert!(matches!(copies[3].kind(), ValueKind::Obj(ObjData::Native(held)) if Rc::ptr_eq(held, &native)));
+    drop(copies);
+    assert_eq!(Rc::strong_count(&native), 1);
+}
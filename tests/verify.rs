@@ -0,0 +1,92 @@
+use rlox::{Chunk, CompileOptions, LoxError, MemorySink, ObjData, OpCode, Value, Vm, compile_with_options, verify};
+
+
+fn string(text: &str) -> Value {
+    return Value::from_obj(ObjData::String(text.chars().collect()));
+}
+
+fn chunk(code: Vec<u8>, constants: Vec<Value>) -> Chunk {
+    let lines = vec![1; code.len()];
+    return Chunk { code: code, lines: lines, constants: constants };
+}
+
+fn rejection(chunk: &Chunk) -> (usize, String) {
+    match verify(chunk) {
+        Err(LoxError::Invalid { offset, message }) => return (offset, message),
+        other => panic!("Expected {:?} to be rejected, got {:?}", chunk.code, other),
+    }
+}
+
+
+#[test]
+fn compiled_chunks_pass() {
+    let script = "var a = 1; if (a > 0) { print a; } else { print -a; } a = str(a) + \"!\"; if (a == \"1!\") print len(a);";
+    for level in 0..=2 {
+        let mut options = CompileOptions::default();
+        options.set_optimization_level(level);
+        verify(&compile_with_options(script, &options).unwrap()).unwrap();
+        options.repl = true;
+        verify(&compile_with_options("1 + 2", &options).unwrap()).unwrap();
+    }
+}
+
+#[test]
+fn bad_opcodes_and_cut_off_operands() {
+    assert_eq!(rejection(&chunk(vec![OpCode::Null as u8, 200], vec![])), (1, "200 isn't an opcode".to_string()));
+    assert_eq!(rejection(&chunk(vec![OpCode::Null as u8, OpCode::Jump as u8, 0], vec![])), (1, "Jump is cut off by the end of the code".to_string()));
+}
+
+#[test]
+fn constants_have_to_exist_and_names_have_to_be_strings() {
+    let (offset, message) = rejection(&chunk(vec![OpCode::Constant as u8, 1, OpCode::Print as u8], vec![Value::from_number(1.0)]));
+    assert_eq!((offset, message.as_str()), (0, "Constant uses constant 1 but there are only 1"));
+
+    let (offset, message) = rejection(&chunk(vec![OpCode::Null as u8, OpCode::DefineGlobal as u8, 0], vec![Value::from_number(1.0)]));
+    assert_eq!((offset, message.as_str()), (1, "DefineGlobal needs a string constant for the name, constant 0 isn't one"));
+
+    verify(&chunk(vec![OpCode::Null as u8, OpCode::DefineGlobal as u8, 0], vec![string("a")])).unwrap();
+}
+
+#[test]
+fn jumps_have_to_land_on_an_instruction() {
+    // past the end
+    let (offset, message) = rejection(&chunk(vec![OpCode::Jump as u8, 0, 5, OpCode::Null as u8, OpCode::Pop as u8], vec![]));
+    assert_eq!((offset, message.as_str()), (0, "Jump goes to 8, which isn't the start of an instruction"));
+    // into the middle of the Constant
+    let (offset, _) = rejection(&chunk(vec![OpCode::Jump as u8, 0, 1, OpCode::Constant as u8, 0, OpCode::Pop as u8], vec![Value::null()]));
+    assert_eq!(offset, 0);
+    // the very end is fine
+    verify(&chunk(vec![OpCode::Jump as u8, 0, 2, OpCode::Null as u8, OpCode::Pop as u8], vec![])).unwrap();
+}
+
+#[test]
+fn the_stack_cant_underflow() {
+    let (offset, message) = rejection(&chunk(vec![OpCode::Null as u8, OpCode::Add as u8], vec![]));
+    assert_eq!((offset, message.as_str()), (1, "Add needs 2 values on the stack but there are only 1"));
+    let (offset, _) = rejection(&chunk(vec![OpCode::Null as u8, OpCode::Call as u8, 1], vec![]));
+    assert_eq!(offset, 1);
+}
+
+#[test]
+fn merge_points_have_to_agree() {
+    // the true branch pushes an extra value before falling into the end
+    let code = vec![
+        OpCode::True as u8,
+        OpCode::JumpIfFalse as u8, 0, 1,
+        OpCode::Null as u8,
+        OpCode::Null as u8,
+        OpCode::Pop as u8,
+    ];
+    let (offset, message) = rejection(&chunk(code, vec![]));
+    assert_eq!((offset, message.as_str()), (5, "the stack is 1 deep one way in here but 0 deep another"));
+}
+
+#[test]
+fn run_chunk_refuses_bad_chunks() {
+    let mut vm = Vm::new();
+    let errors = MemorySink::new();
+    vm.set_errors(errors.clone());
+    let result = vm.run_chunk(&chunk(vec![OpCode::GetGlobal as u8, 7, OpCode::Print as u8], vec![]));
+    assert!(matches!(result, Err(LoxError::Invalid { offset: 0, .. })), "{:?}", result);
+    assert_eq!(errors.contents(), "[offset 0] Invalid bytecode: GetGlobal uses constant 7 but there are only 0\n");
+}